@@ -0,0 +1,269 @@
+/*
+ * Copyright 2020, Ulf Lilleengen
+ * License: Apache License 2.0 (see the file LICENSE or http://apache.org/licenses/LICENSE-2.0.html).
+ */
+
+//! Derive macros for Dove. Use them through the `derive` feature of the `dove` crate.
+
+use proc_macro::TokenStream;
+use proc_macro2::{Literal, TokenStream as TokenStream2};
+use quote::{format_ident, quote};
+use syn::{parse_macro_input, Data, DeriveInput, Error, Expr, Fields, LitInt, LitStr, Type};
+
+/// Derive `Encoder`, `TryFromValue` and an inherent `decode(FrameDecoder)` function for a
+/// struct that maps to an AMQP described list.
+///
+/// The descriptor is given with `#[amqp(descriptor = 0x73, name = "amqp:properties:list")]`.
+/// At least one of the numeric code or the symbolic name must be present. Encoding uses the
+/// numeric code when available, while decoding accepts either.
+///
+/// Fields are encoded in declaration order. `Option` fields are optional and trailing `None`
+/// fields are trimmed from the encoded list. Other fields are required unless annotated with
+/// `#[amqp(default)]` or `#[amqp(default = "expr")]`, in which case a missing or null value
+/// decodes as the default.
+#[proc_macro_derive(AmqpComposite, attributes(amqp))]
+pub fn derive_amqp_composite(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+struct Descriptor {
+    code: Option<LitInt>,
+    name: Option<LitStr>,
+}
+
+enum FieldKind {
+    Optional,
+    Required,
+    Default(TokenStream2),
+}
+
+fn parse_descriptor(input: &DeriveInput) -> syn::Result<Descriptor> {
+    let mut descriptor = Descriptor {
+        code: None,
+        name: None,
+    };
+    for attr in input.attrs.iter().filter(|a| a.path().is_ident("amqp")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("descriptor") {
+                descriptor.code = Some(meta.value()?.parse()?);
+                Ok(())
+            } else if meta.path.is_ident("name") {
+                descriptor.name = Some(meta.value()?.parse()?);
+                Ok(())
+            } else {
+                Err(meta.error("expected `descriptor` or `name`"))
+            }
+        })?;
+    }
+    if descriptor.code.is_none() && descriptor.name.is_none() {
+        return Err(Error::new_spanned(
+            &input.ident,
+            "missing #[amqp(descriptor = ..)] or #[amqp(name = \"..\")] attribute",
+        ));
+    }
+    Ok(descriptor)
+}
+
+fn is_option(ty: &Type) -> bool {
+    match ty {
+        Type::Path(path) if path.qself.is_none() => path
+            .path
+            .segments
+            .last()
+            .map(|s| s.ident == "Option")
+            .unwrap_or(false),
+        _ => false,
+    }
+}
+
+fn parse_field_kind(field: &syn::Field) -> syn::Result<FieldKind> {
+    let mut kind = if is_option(&field.ty) {
+        FieldKind::Optional
+    } else {
+        FieldKind::Required
+    };
+    for attr in field.attrs.iter().filter(|a| a.path().is_ident("amqp")) {
+        attr.parse_nested_meta(|meta| {
+            if !meta.path.is_ident("default") {
+                return Err(meta.error("expected `default`"));
+            }
+            if let FieldKind::Optional = kind {
+                return Err(meta.error("`default` cannot be used on Option fields"));
+            }
+            let default = if meta.input.peek(syn::Token![=]) {
+                let expr: LitStr = meta.value()?.parse()?;
+                let expr: Expr = expr.parse()?;
+                quote!(#expr)
+            } else {
+                quote!(::core::default::Default::default())
+            };
+            kind = FieldKind::Default(default);
+            Ok(())
+        })?;
+    }
+    Ok(kind)
+}
+
+fn expand(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let descriptor = parse_descriptor(&input)?;
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => fields.named.iter().collect::<Vec<_>>(),
+            Fields::Unit => Vec::new(),
+            Fields::Unnamed(_) => {
+                return Err(Error::new_spanned(
+                    &input.ident,
+                    "AmqpComposite requires named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(Error::new_spanned(
+                &input.ident,
+                "AmqpComposite can only be derived for structs",
+            ))
+        }
+    };
+    let kinds = fields
+        .iter()
+        .map(|f| parse_field_kind(f))
+        .collect::<syn::Result<Vec<_>>>()?;
+
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let encode_descriptor = match (&descriptor.code, &descriptor.name) {
+        (Some(code), _) => quote!(::dove::types::Value::Ulong(#code)),
+        (None, Some(name)) => quote!(::dove::types::Value::Symbol(#name.as_bytes().to_vec())),
+        (None, None) => unreachable!(),
+    };
+
+    // Fields up to and including the last non-optional field are always encoded, the
+    // remaining ones only up to the last field that is set.
+    let always = kinds
+        .iter()
+        .rposition(|k| !matches!(k, FieldKind::Optional))
+        .map(|i| i + 1)
+        .unwrap_or(0);
+    let mut encode_args = Vec::new();
+    let mut trailing = Vec::new();
+    for (i, field) in fields.iter().enumerate() {
+        let name = field.ident.as_ref().unwrap();
+        if i < always {
+            encode_args.push(quote!(encoder.encode_arg(&self.#name)?;));
+        } else {
+            let index = Literal::usize_unsuffixed(i);
+            let count = Literal::usize_unsuffixed(i + 1);
+            trailing.push(quote! {
+                if self.#name.is_some() {
+                    nfields = #count;
+                }
+            });
+            encode_args.push(quote! {
+                if nfields > #index {
+                    encoder.encode_arg(&self.#name)?;
+                }
+            });
+        }
+    }
+    let always = Literal::usize_unsuffixed(always);
+    let nfields = if trailing.is_empty() {
+        quote!()
+    } else {
+        quote! {
+            let mut nfields: usize = #always;
+            #(#trailing)*
+        }
+    };
+
+    let mut decode_fields = Vec::new();
+    let mut init_fields = Vec::new();
+    for (field, kind) in fields.iter().zip(kinds.iter()) {
+        let name = field.ident.as_ref().unwrap();
+        let ty = &field.ty;
+        let tmp = format_ident!("__{}", name);
+        match kind {
+            FieldKind::Optional => {
+                decode_fields.push(quote! {
+                    let mut #tmp: #ty = None;
+                    decoder.decode_optional(&mut #tmp)?;
+                });
+                init_fields.push(quote!(#name: #tmp));
+            }
+            FieldKind::Required => {
+                let missing = format!("Missing required field {}::{}", ident, name);
+                decode_fields.push(quote! {
+                    let mut #tmp: Option<#ty> = None;
+                    decoder.decode_optional(&mut #tmp)?;
+                });
+                init_fields.push(quote! {
+                    #name: #tmp.ok_or_else(|| ::dove::error::AmqpError::decode_error(Some(#missing)))?
+                });
+            }
+            FieldKind::Default(default) => {
+                decode_fields.push(quote! {
+                    let mut #tmp: Option<#ty> = None;
+                    decoder.decode_optional(&mut #tmp)?;
+                });
+                init_fields.push(quote!(#name: #tmp.unwrap_or_else(|| #default)));
+            }
+        }
+    }
+
+    let mut descriptor_arms = Vec::new();
+    if let Some(code) = &descriptor.code {
+        descriptor_arms.push(quote!(::dove::types::Value::Ulong(code) => *code == #code,));
+    }
+    if let Some(name) = &descriptor.name {
+        descriptor_arms.push(quote! {
+            ::dove::types::Value::Symbol(symbol) => symbol[..] == *#name.as_bytes(),
+            ::dove::types::Value::SymbolSlice(symbol) => *symbol == #name.as_bytes(),
+        });
+    }
+    let type_name = ident.to_string();
+
+    Ok(quote! {
+        impl #impl_generics ::dove::types::Encoder for #ident #ty_generics #where_clause {
+            fn encode(&self, writer: &mut dyn ::std::io::Write) -> ::dove::error::Result<::dove::types::TypeCode> {
+                let mut encoder = ::dove::frame_codec::FrameEncoder::new(#encode_descriptor);
+                #nfields
+                #(#encode_args)*
+                encoder.encode(writer)
+            }
+        }
+
+        impl #impl_generics #ident #ty_generics #where_clause {
+            pub fn decode(mut decoder: ::dove::frame_codec::FrameDecoder) -> ::dove::error::Result<Self> {
+                #(#decode_fields)*
+                Ok(Self {
+                    #(#init_fields,)*
+                })
+            }
+        }
+
+        impl #impl_generics ::dove::convert::TryFromValue for #ident #ty_generics #where_clause {
+            fn try_from(value: ::dove::types::Value) -> ::dove::error::Result<Self> {
+                if let ::dove::types::Value::Described(descriptor, mut list) = value {
+                    let matches = match &*descriptor {
+                        #(#descriptor_arms)*
+                        _ => false,
+                    };
+                    if !matches {
+                        return Err(::dove::error::AmqpError::decode_error(Some(
+                            format!("Expected {} descriptor but found {:?}", #type_name, descriptor).as_str(),
+                        )));
+                    }
+                    let decoder = ::dove::frame_codec::FrameDecoder::new(&descriptor, &mut list)?;
+                    Self::decode(decoder)
+                } else {
+                    Err(::dove::error::AmqpError::decode_error(Some(
+                        format!("Missing expected {} descriptor", #type_name).as_str(),
+                    )))
+                }
+            }
+        }
+    })
+}
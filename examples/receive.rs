@@ -14,7 +14,7 @@ use tokio::time::Duration;
  */
 fn main() {
     let args: Vec<String> = env::args().collect();
-    if args.len() < 1 {
+    if args.is_empty() {
         println!("Usage: receive amqp://localhost:5672/myqueue");
         std::process::exit(1);
     }
@@ -32,6 +32,7 @@ fn main() {
         idle_timeout: Some(Duration::from_secs(5)),
         buffer_size: Some(1024 * 512),
         tcp_nodelay: None,
+        ..ConnectionOptions::new()
     };
 
     let container = Container::new()
@@ -42,7 +43,7 @@ fn main() {
     block_on(async {
         println!("Going to connect");
         let connection = container
-            .connect(format!("{}:{}", url.hostname, url.port), opts)
+            .connect_url(&url, opts)
             .await
             .expect("connection not created");
 
@@ -34,6 +34,7 @@ fn main() {
         idle_timeout: Some(Duration::from_secs(5)),
         buffer_size: Some(1024 * 512),
         tcp_nodelay: None,
+        ..ConnectionOptions::new()
     };
 
     let container = Container::new()
@@ -43,7 +44,7 @@ fn main() {
     // connect creates the TCP connection and sends OPEN frame.
     block_on(async {
         let connection = container
-            .connect(format!("{}:{}", url.hostname, url.port), opts)
+            .connect_url(&url, opts)
             .await
             .expect("connection not created");
 
@@ -55,7 +56,7 @@ fn main() {
 
         // new_sender creates the AMQP sender link.
         let sender = session
-            .new_sender(&url.address)
+            .new_sender(url.address)
             .await
             .expect("sender not created");
 
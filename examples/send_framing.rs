@@ -47,9 +47,7 @@ fn main() {
     let handle = connection.handle(waker);
 
     handle.open(Open::new("example-send-minimal")).unwrap();
-    handle
-        .begin(0, Begin::new(0, std::u32::MAX, std::u32::MAX))
-        .unwrap();
+    handle.begin(0, Begin::new(0, u32::MAX, u32::MAX)).unwrap();
     handle
         .attach(
             0,
@@ -66,39 +64,35 @@ fn main() {
     while !done {
         let mut received = Vec::new();
         let result = connection.process(&mut received);
-        match result {
-            Ok(_) => {
-                for frame in received.drain(..) {
-                    match frame {
-                        Frame::AMQP(AmqpFrame {
-                            channel: _,
-                            performative,
-                            payload: _,
-                        }) => match performative {
-                            Some(Performative::Flow(_)) => {
-                                handle
-                                    .transfer(
-                                        0,
-                                        Transfer::new(0)
-                                            .delivery_id(0)
-                                            .delivery_tag(&[1])
-                                            .settled(true),
-                                        Some(buffer.clone()),
-                                    )
-                                    .unwrap();
-                                handle.close(Close { error: None }).unwrap();
-                            }
-                            Some(Performative::Close(_)) => {
-                                done = true;
-                            }
-                            _ => {}
-                        },
-
+        if result.is_ok() {
+            for frame in received.drain(..) {
+                if let Frame::AMQP(AmqpFrame {
+                    channel: _,
+                    performative,
+                    payload: _,
+                }) = frame
+                {
+                    match performative {
+                        Some(Performative::Flow(_)) => {
+                            handle
+                                .transfer(
+                                    0,
+                                    Transfer::new(0)
+                                        .delivery_id(0)
+                                        .delivery_tag(&[1])
+                                        .settled(true),
+                                    Some(buffer.clone()),
+                                )
+                                .unwrap();
+                            handle.close(Close { error: None }).unwrap();
+                        }
+                        Some(Performative::Close(_)) => {
+                            done = true;
+                        }
                         _ => {}
                     }
                 }
             }
-            _ => {}
         }
     }
 }
@@ -6,11 +6,16 @@
 //! The conn module contains basic primitives for establishing and accepting AMQP connections and performing the initial handshake. Once handshake is complete, the connection can be used to send and receive frames.
 
 use crate::connection::ConnectionHandle;
+use crate::decoding::DecodeLimits;
 use crate::driver::Channel;
 use crate::error::*;
 use crate::framing::*;
 use crate::sasl::*;
+use crate::streaming::MIN_MAX_FRAME_SIZE;
+#[cfg(feature = "tls")]
+use crate::tls::TlsConfig;
 use crate::transport::*;
+use crate::url::Url;
 use async_channel::Sender;
 use std::sync::Arc;
 use std::time::Duration;
@@ -24,6 +29,14 @@ pub struct ConnectionOptions {
     pub idle_timeout: Option<Duration>,
     pub buffer_size: Option<usize>,
     pub tcp_nodelay: Option<bool>,
+    pub decode_limits: Option<DecodeLimits>,
+    pub max_frame_size: Option<u32>,
+    #[cfg(feature = "tls")]
+    pub tls: Option<TlsConfig>,
+    #[cfg(feature = "websocket")]
+    pub websocket: Option<String>,
+    #[cfg(feature = "websocket")]
+    pub websocket_path: Option<String>,
 }
 
 impl ConnectionOptions {
@@ -35,6 +48,14 @@ impl ConnectionOptions {
             idle_timeout: None,
             buffer_size: None,
             tcp_nodelay: None,
+            decode_limits: None,
+            max_frame_size: None,
+            #[cfg(feature = "tls")]
+            tls: None,
+            #[cfg(feature = "websocket")]
+            websocket: None,
+            #[cfg(feature = "websocket")]
+            websocket_path: None,
         }
     }
 
@@ -46,6 +67,14 @@ impl ConnectionOptions {
             idle_timeout: None,
             buffer_size: None,
             tcp_nodelay: None,
+            decode_limits: None,
+            max_frame_size: None,
+            #[cfg(feature = "tls")]
+            tls: None,
+            #[cfg(feature = "websocket")]
+            websocket: None,
+            #[cfg(feature = "websocket")]
+            websocket_path: None,
         }
     }
 
@@ -57,6 +86,14 @@ impl ConnectionOptions {
             idle_timeout: None,
             buffer_size: None,
             tcp_nodelay: None,
+            decode_limits: None,
+            max_frame_size: None,
+            #[cfg(feature = "tls")]
+            tls: None,
+            #[cfg(feature = "websocket")]
+            websocket: None,
+            #[cfg(feature = "websocket")]
+            websocket_path: None,
         }
     }
 
@@ -80,8 +117,8 @@ impl ConnectionOptions {
         self
     }
 
-    /// The size of the transport buffer. Messages (including the header) larger
-    /// than the given value cannot be received.
+    /// The size of the transport read and write buffers. Frames larger than the
+    /// buffer are read and written in multiple steps.
     pub fn buffer_size(mut self, buffer_size: usize) -> Self {
         self.buffer_size = Some(buffer_size);
         self
@@ -92,6 +129,88 @@ impl ConnectionOptions {
         self.tcp_nodelay = Some(nodelay);
         self
     }
+
+    /// The limits applied when decoding frames and messages received from the peer.
+    pub fn decode_limits(mut self, limits: DecodeLimits) -> Self {
+        self.decode_limits = Some(limits);
+        self
+    }
+
+    /// The largest frame accepted from the peer, announced in the open performative. The
+    /// connection is closed with a framing error if the peer sends larger frames. AMQP
+    /// requires a value of at least 512, connecting fails with a smaller value.
+    pub fn max_frame_size(mut self, max_frame_size: u32) -> Self {
+        self.max_frame_size = Some(max_frame_size);
+        self
+    }
+
+    /// Connect using TLS with the given configuration.
+    #[cfg(feature = "tls")]
+    pub fn tls(mut self, config: TlsConfig) -> Self {
+        self.tls = Some(config);
+        self
+    }
+
+    /// Connect using AMQP over WebSocket, upgrading the connection at the given `ws://` or
+    /// `wss://` URL. A `wss://` URL also requires TLS to be configured.
+    #[cfg(feature = "websocket")]
+    pub fn websocket(mut self, url: impl Into<String>) -> Self {
+        self.websocket = Some(url.into());
+        self
+    }
+
+    /// The path of the WebSocket endpoint when connecting to a `ws://` or `wss://` URL,
+    /// e.g. `/$servicebus/websocket`. Defaults to the root resource, as the path of the URL
+    /// is the address.
+    #[cfg(feature = "websocket")]
+    pub fn websocket_path(mut self, path: impl Into<String>) -> Self {
+        self.websocket_path = Some(path.into());
+        self
+    }
+
+    /// Configure the network for the scheme of the given URL: TLS for `amqps://` and
+    /// `wss://`, with the URL hostname as server name unless configured otherwise, and
+    /// WebSocket for `ws://` and `wss://` at the configured path. Fails if the scheme
+    /// requires a disabled feature.
+    pub(crate) fn for_url(self, url: &Url) -> Result<Self> {
+        let opts = self;
+        #[cfg(feature = "tls")]
+        let opts = if url.scheme.tls() {
+            ConnectionOptions {
+                tls: Some(
+                    opts.tls
+                        .unwrap_or_default()
+                        .default_server_name(url.hostname),
+                ),
+                ..opts
+            }
+        } else {
+            opts
+        };
+        #[cfg(not(feature = "tls"))]
+        if url.scheme.tls() {
+            return Err(AmqpError::Generic(format!(
+                "{:?} URLs require the tls feature",
+                url.scheme
+            )));
+        }
+        #[cfg(feature = "websocket")]
+        let websocket_url =
+            url.websocket_url_with_path(opts.websocket_path.as_deref().unwrap_or("/"));
+        #[cfg(feature = "websocket")]
+        let opts = match websocket_url {
+            Some(websocket_url) if opts.websocket.is_none() => opts.websocket(websocket_url),
+            _ => opts,
+        };
+        #[cfg(not(feature = "websocket"))]
+        if url.websocket_url().is_some() {
+            return Err(AmqpError::Generic(format!(
+                "{:?} URLs require the websocket feature",
+                url.scheme
+            )));
+        }
+        Ok(opts)
+    }
 }
 
 /*
@@ -127,9 +246,21 @@ const AMQP_10_HEADER: ProtocolHeader = ProtocolHeader::AMQP(Version(1, 0, 0));
 const SASL_10_HEADER: ProtocolHeader = ProtocolHeader::SASL(Version(1, 0, 0));
 
 pub fn connect<N: Network>(
-    transport: Transport<N>,
+    mut transport: Transport<N>,
     opts: ConnectionOptions,
 ) -> Result<Connection<N>> {
+    if let Some(limits) = opts.decode_limits {
+        transport.set_decode_limits(limits);
+    }
+    if let Some(max_frame_size) = opts.max_frame_size {
+        if (max_frame_size as usize) < MIN_MAX_FRAME_SIZE {
+            return Err(AmqpError::Generic(format!(
+                "Maximum frame size {} is below the minimum of {}",
+                max_frame_size, MIN_MAX_FRAME_SIZE
+            )));
+        }
+        transport.set_max_frame_size(max_frame_size as usize);
+    }
     let mut connection = Connection::new(transport);
     if opts.username.is_some() || opts.password.is_some() || opts.sasl_mechanism.is_some() {
         connection.sasl = Some(Sasl {
@@ -194,13 +325,19 @@ impl<N: Network> Connection<N> {
         self.sasl.as_ref().map(Sasl::is_done).unwrap_or(true)
     }
 
-    // Write outgoing frames
+    // Write outgoing frames. Frames stay queued while the transport write buffer is full.
     pub fn flush(&mut self) -> Result<()> {
+        self.transport.flush()?;
         match self.state {
             ConnectionState::Opened | ConnectionState::Closed => {
-                while let Ok(frame) = self.tx_frames.try_recv() {
-                    debug!("TX {:?}", frame);
-                    self.transport.write_frame(&frame)?;
+                while self.transport.is_writable() {
+                    match self.tx_frames.try_recv() {
+                        Ok(frame) => {
+                            debug!("TX {:?}", frame);
+                            self.transport.write_frame(&frame)?;
+                        }
+                        Err(_) => break,
+                    }
                 }
             }
             _ => {}
@@ -299,6 +436,25 @@ impl<N: Network> Connection<N> {
             }
             ConnectionState::Opened => {
                 let frame = self.transport.read_frame()?;
+                if let Frame::AMQP(AmqpFrame {
+                    performative: Some(Performative::Open(open)),
+                    ..
+                }) = &frame
+                {
+                    if let Some(max_frame_size) = open.max_frame_size {
+                        if (max_frame_size as usize) < MIN_MAX_FRAME_SIZE {
+                            return Err(AmqpError::framing_error(Some(
+                                format!(
+                                    "Remote maximum frame size {} is below the minimum of {}",
+                                    max_frame_size, MIN_MAX_FRAME_SIZE
+                                )
+                                .as_str(),
+                            )));
+                        }
+                        self.transport
+                            .set_remote_max_frame_size(max_frame_size as usize);
+                    }
+                }
                 frames.push(frame);
             }
             ConnectionState::Closed => {
@@ -1,4 +1,5 @@
 use crate::conn::ChannelId;
+use crate::decoding::DecodeLimits;
 use crate::error::Result;
 use crate::framing::{
     AmqpFrame, Attach, Begin, Close, Detach, Disposition, End, Flow, Frame, Open, Performative,
@@ -6,18 +7,40 @@ use crate::framing::{
 };
 use crate::transport::TransportInfo;
 use async_channel::Sender;
-use mio::Waker;
+use std::fmt::Debug;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 
+/// Wakes whatever drives the I/O of a connection, e.g. the container poll loop, when frames
+/// are queued for sending.
+pub trait ConnectionWaker: Send + Sync + Debug {
+    fn wake(&self) -> Result<()>;
+}
+
+impl ConnectionWaker for mio::Waker {
+    fn wake(&self) -> Result<()> {
+        Ok(mio::Waker::wake(self)?)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct ConnectionHandle {
     transport: Arc<TransportInfo>,
-    waker: Arc<Waker>,
+    waker: Arc<dyn ConnectionWaker>,
     sender: Sender<Frame>,
 }
 
 impl ConnectionHandle {
+    /// The limits applied when decoding frames and messages received on this connection.
+    pub fn decode_limits(&self) -> DecodeLimits {
+        self.transport.decode_limits()
+    }
+
+    /// The largest frame accepted by the remote peer.
+    pub fn max_frame_size(&self) -> usize {
+        self.transport.remote_max_frame_size()
+    }
+
     pub fn open(&self, open: Open) -> Result<()> {
         self.send_amqp_frame(AmqpFrame {
             channel: 0,
@@ -124,8 +147,10 @@ impl ConnectionHandle {
     }
 }
 
-impl From<(Sender<Frame>, (Arc<TransportInfo>, Arc<Waker>))> for ConnectionHandle {
-    fn from((sender, (info, waker)): (Sender<Frame>, (Arc<TransportInfo>, Arc<Waker>))) -> Self {
+impl<W: ConnectionWaker + 'static> From<(Sender<Frame>, (Arc<TransportInfo>, Arc<W>))>
+    for ConnectionHandle
+{
+    fn from((sender, (info, waker)): (Sender<Frame>, (Arc<TransportInfo>, Arc<W>))) -> Self {
         Self {
             sender,
             transport: info,
@@ -6,13 +6,19 @@
 //! The container module contains a simple API for creating client connections and sending and receiving messages
 
 use crate::conn;
+use crate::connection::ConnectionWaker;
 use crate::driver::{
     Channel, ConnectionDriver, DeliveryDriver, LinkDriver, SessionDriver, SessionOpts,
 };
 use crate::error::*;
-use crate::framing::{LinkRole, Open, Performative};
+use crate::framing::{
+    Declare, Discharge, LinkRole, Open, Performative, Rejected, TransactionalState, Transfer,
+};
+use crate::message::MessageBody;
+use crate::streaming::DEFAULT_MAX_FRAME_SIZE;
 use crate::transport;
-use mio::{Events, Poll, Token, Waker};
+use mio::event::Source;
+use mio::{Events, Interest, Poll, Token, Waker};
 use std::cell::RefCell;
 use std::collections::HashMap;
 use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
@@ -23,13 +29,24 @@ use uuid::Uuid;
 
 // Re-exports
 pub use crate::conn::ConnectionOptions;
-pub use crate::framing::DeliveryState;
-pub use crate::message::{Message, MessageProperties};
+pub use crate::framing::{DeliveryState, ReceiverSettleMode, SenderSettleMode};
+pub use crate::message::{LazyMessage, Message, MessageProperties};
 use crate::options::{LinkOptions, ReceiverOptions, SenderOptions};
 pub use crate::sasl::SaslMechanism;
-use crate::transport::mio::MioNetwork;
-pub use crate::types::{Value, ValueRef};
-use std::net::{SocketAddr, ToSocketAddrs};
+#[cfg(feature = "tls")]
+use crate::tls::TlsNetwork;
+#[cfg(unix)]
+use crate::transport::mio::UnixNetwork;
+use crate::transport::mio::{MioNetwork, MioSource};
+use crate::transport::Network;
+pub use crate::transport::PeerAddress;
+pub use crate::types::{BorrowedValue, Value, ValueRef};
+use crate::url::{Url, UrlScheme};
+#[cfg(feature = "websocket")]
+use crate::websocket::WsNetwork;
+use std::net::ToSocketAddrs;
+#[cfg(unix)]
+use std::path::{Path, PathBuf};
 
 /// Represents an AMQP 1.0 container that can manage multiple connections.
 pub struct Container {
@@ -38,12 +55,13 @@ pub struct Container {
     thread: Option<thread::JoinHandle<()>>,
 }
 
+type ContainerConnection = conn::Connection<Box<dyn MioSource>>;
+
 struct ContainerInner {
     container_id: String,
     poll: RefCell<Poll>,
-    incoming: Channel<(Token, Arc<ConnectionDriver>, conn::Connection<MioNetwork>)>,
-    #[allow(clippy::type_complexity)] // subjective judgement: complexity is reasonable
-    connections: Mutex<HashMap<Token, (Arc<ConnectionDriver>, conn::Connection<MioNetwork>)>>,
+    incoming: Channel<(Token, Arc<ConnectionDriver>, ContainerConnection)>,
+    connections: Mutex<HashMap<Token, (Arc<ConnectionDriver>, ContainerConnection)>>,
     token_generator: AtomicU32,
     waker: Arc<Waker>,
     closed: AtomicBool,
@@ -52,16 +70,18 @@ struct ContainerInner {
 /// Represents a single AMQP connection to a remote endpoint.
 pub struct Connection {
     connection: Arc<ConnectionDriver>,
-    waker: Arc<Waker>,
+    waker: Arc<dyn ConnectionWaker>,
 
     pub container_id: String,
-    pub host: SocketAddr,
+    pub host: PeerAddress,
     pub channel_max: u16,
     pub idle_timeout: Duration,
+    pub max_frame_size: u32,
 
     pub remote_idle_timeout: Duration,
     pub remote_container_id: String,
     pub remote_channel_max: u16,
+    pub remote_max_frame_size: u32,
 }
 
 /// Represents an AMQP session.
@@ -82,6 +102,15 @@ impl Sender {
     }
 }
 
+/// Represents a delivery sent in chunks, see [`Sender::send_streaming`].
+pub struct StreamingDelivery {
+    link: Arc<LinkDriver>,
+    delivery: Arc<DeliveryDriver>,
+    // Transfer of the first frame, until the first chunk is sent
+    transfer: Option<Transfer>,
+    done: bool,
+}
+
 /// Represents a receiver link.
 pub struct Receiver {
     address: String,
@@ -98,6 +127,14 @@ impl Receiver {
 #[allow(dead_code)] // TODO this type does not seem to be useful (yet)?
 pub struct Disposition {
     delivery: Arc<DeliveryDriver>,
+    state: Option<DeliveryState>,
+}
+
+/// Represents a transaction declared with [`Session::begin_transaction`]. Messages sent and
+/// deliveries settled through the transaction take effect when it is committed.
+pub struct Transaction {
+    controller: Sender,
+    id: Vec<u8>,
 }
 
 /// Represent a delivery
@@ -108,6 +145,12 @@ pub struct Delivery {
     delivery: Arc<DeliveryDriver>,
 }
 
+/// Represent a delivery whose message is kept in its encoded form
+pub struct LazyDelivery {
+    message: LazyMessage,
+    delivery: Delivery,
+}
+
 unsafe impl std::marker::Sync for ContainerInner {}
 
 impl Container {
@@ -187,6 +230,63 @@ impl Container {
         self.container.connect(host, opts).await
     }
 
+    /// Connect to the AMQP endpoint of the given URL and send the initial open performative.
+    /// The network follows the URL scheme, see [`ConnectionOptions`] for TLS and WebSocket
+    /// options. TLS connections use the URL hostname as server name unless configured
+    /// otherwise. URLs with a scheme of a disabled feature fail to connect.
+    pub async fn connect_url(&self, url: &Url<'_>, opts: ConnectionOptions) -> Result<Connection> {
+        if url.scheme == UrlScheme::UNIX {
+            #[cfg(unix)]
+            {
+                let socket_path = url
+                    .socket_path
+                    .filter(|path| !path.is_empty())
+                    .ok_or_else(|| AmqpError::generic("URL is missing a socket path"))?;
+                return self.connect_unix(socket_path, opts).await;
+            }
+            #[cfg(not(unix))]
+            return Err(AmqpError::generic(
+                "amqp+unix:// URLs require a Unix platform",
+            ));
+        }
+        let opts = opts.for_url(url)?;
+        self.connect(format!("{}:{}", url.hostname, url.port), opts)
+            .await
+    }
+
+    /// Connect to an AMQP endpoint listening on a Unix domain socket, e.g. for
+    /// `amqp+unix://` URLs, and send the initial open performative.
+    #[cfg(unix)]
+    pub async fn connect_unix<P: AsRef<Path>>(
+        &self,
+        path: P,
+        opts: ConnectionOptions,
+    ) -> Result<Connection> {
+        self.container
+            .connect_unix(path.as_ref().to_path_buf(), opts)
+            .await
+    }
+
+    /// Connect to an AMQP endpoint over a custom network and send the initial open
+    /// performative. The network must be connected, and is registered with the container
+    /// poll loop like the built-in networks.
+    pub async fn connect_with_transport<N>(
+        &self,
+        network: N,
+        peer: PeerAddress,
+        opts: ConnectionOptions,
+    ) -> Result<Connection>
+    where
+        N: Network + Source + Send + 'static,
+    {
+        self.container
+            .connect_network(opts, move |_| {
+                let network: Box<dyn MioSource> = Box::new(network);
+                Ok((peer, network))
+            })
+            .await
+    }
+
     /// Close the connection. Flushes outgoing buffer before sending the final close performative,
     /// and closing the connection.
     pub fn close(&mut self) -> Result<()> {
@@ -210,6 +310,40 @@ impl Drop for Container {
     }
 }
 
+/// Wrap a TCP network in TLS if configured in the options.
+#[cfg(feature = "tls")]
+fn tls_network(network: MioNetwork, opts: &ConnectionOptions) -> Result<Box<dyn MioSource>> {
+    Ok(match &opts.tls {
+        Some(config) => Box::new(TlsNetwork::new(network, config)?),
+        None => Box::new(network),
+    })
+}
+
+#[cfg(not(feature = "tls"))]
+fn tls_network(network: MioNetwork, _opts: &ConnectionOptions) -> Result<Box<dyn MioSource>> {
+    Ok(Box::new(network))
+}
+
+/// Wrap a network in WebSocket if configured in the options.
+#[cfg(feature = "websocket")]
+fn websocket_network(
+    network: Box<dyn MioSource>,
+    opts: &ConnectionOptions,
+) -> Result<Box<dyn MioSource>> {
+    Ok(match &opts.websocket {
+        Some(url) => Box::new(WsNetwork::new(network, url)?),
+        None => network,
+    })
+}
+
+#[cfg(not(feature = "websocket"))]
+fn websocket_network(
+    network: Box<dyn MioSource>,
+    _opts: &ConnectionOptions,
+) -> Result<Box<dyn MioSource>> {
+    Ok(network)
+}
+
 impl ContainerInner {
     fn close(&self) -> Result<()> {
         if self.closed.fetch_or(true, Ordering::SeqCst) {
@@ -233,6 +367,32 @@ impl ContainerInner {
         host: S,
         opts: ConnectionOptions,
     ) -> Result<Connection> {
+        self.connect_network(opts, move |opts| {
+            let network = MioNetwork::connect(&host)?;
+            let host = PeerAddress::Tcp(network.peer_addr());
+            let network = websocket_network(tls_network(network, opts)?, opts)?;
+            Ok((host, network))
+        })
+        .await
+    }
+
+    #[cfg(unix)]
+    async fn connect_unix(&self, path: PathBuf, opts: ConnectionOptions) -> Result<Connection> {
+        self.connect_network(opts, move |_| {
+            let network = UnixNetwork::connect(&path)?;
+            let host = PeerAddress::Unix(network.path().to_path_buf());
+            let network: Box<dyn MioSource> = Box::new(network);
+            Ok((host, network))
+        })
+        .await
+    }
+
+    /// Connect using the network opened by the given function and send the initial open
+    /// performative.
+    async fn connect_network<F>(&self, opts: ConnectionOptions, open: F) -> Result<Connection>
+    where
+        F: FnOnce(&ConnectionOptions) -> Result<(PeerAddress, Box<dyn MioSource>)> + Send + 'static,
+    {
         let options = opts.clone();
         let (tx, rx) = async_channel::bounded(1);
 
@@ -240,18 +400,17 @@ impl ContainerInner {
         thread::spawn({
             move || {
                 let result: Result<_> = (|| {
-                    let network = transport::mio::MioNetwork::connect(&host)?;
+                    let (host, network) = open(&opts)?;
                     let transport =
                         transport::Transport::new(network, opts.buffer_size.unwrap_or(1024 * 1024));
                     let connection = conn::connect(transport, opts)?;
-                    Ok(connection)
+                    Ok((host, connection))
                 })();
                 let _ = tx.try_send(result);
             }
         });
 
-        let connection = rx.recv().await??;
-        let host = connection.transport().network().peer_addr();
+        let (host, connection) = rx.recv().await??;
         trace!("{}: connected to {}", self.container_id, host);
 
         let id = Token(self.token_generator.fetch_add(1, Ordering::SeqCst) as usize);
@@ -259,74 +418,33 @@ impl ContainerInner {
             "{}: created connection to {} with local id {:?}",
             self.container_id, host, id,
         );
-        let driver = {
-            let handle = connection.handle(self.waker.clone());
-            let driver = Arc::new(ConnectionDriver::new(
-                handle,
-                options.idle_timeout.unwrap_or_default(),
-            ));
-
-            driver.open({
-                let mut open = Open::new(&self.container_id);
-                // open.hostname = Some(host.to_string());
-                open.channel_max = Some(u16::MAX);
-                open.idle_timeout = options.idle_timeout.map(|d| d.as_millis() as _);
-                open
-            })?;
-
-            driver
-        };
+        let driver = Arc::new(ConnectionDriver::new(
+            connection.handle(self.waker.clone()),
+            options.idle_timeout.unwrap_or_default(),
+        ));
 
         self.incoming.send((id, driver.clone(), connection))?;
         self.waker.wake()?;
 
-        loop {
-            let frame = driver.recv().await?;
-            match frame.performative {
-                Some(Performative::Open(o)) => {
-                    trace!("{}: received OPEN frame from {}", self.container_id, host);
-                    // Populate remote properties
-                    return Ok(Connection {
-                        waker: self.waker.clone(),
-                        connection: driver,
-                        container_id: self.container_id.clone(),
-                        host,
-                        channel_max: u16::MAX,
-                        idle_timeout: options.idle_timeout.unwrap_or_default(),
-
-                        remote_container_id: o.container_id.clone(),
-                        remote_channel_max: o.channel_max.unwrap_or(u16::MAX),
-                        remote_idle_timeout: Duration::from_millis(
-                            o.idle_timeout.unwrap_or(0) as u64
-                        ),
-                    });
-                }
-                Some(Performative::Close(c)) => {
-                    trace!("{}: received CLOSE frame from {}", self.container_id, host);
-                    return if let Some(e) = c.error {
-                        Err(AmqpError::Amqp(e))
-                    } else {
-                        Err(AmqpError::Generic("connection closed".to_string()))
-                    };
-                }
-                _ => {
-                    // Push it back into the queue
-                    // TODO: Prevent reordering
-                    driver.unrecv(frame)?;
-                }
-            }
-        }
+        Connection::open(
+            &self.container_id,
+            host,
+            &options,
+            driver,
+            self.waker.clone(),
+        )
+        .await
     }
 
     fn process(&self) -> Result<()> {
         let mut poll = self.poll.borrow_mut();
         // Register new connections
         while let Ok((id, driver, mut connection)) = self.incoming.try_recv() {
-            if let Err(e) = connection
-                .transport_mut()
-                .network_mut()
-                .register(id, &mut poll)
-            {
+            if let Err(e) = poll.registry().register(
+                connection.transport_mut().network_mut(),
+                id,
+                Interest::READABLE | Interest::WRITABLE,
+            ) {
                 let _ = driver.close(None);
                 let _ = connection.shutdown();
                 error!("Failed to register connection {:?}: {}", id, e);
@@ -393,8 +511,11 @@ impl ContainerInner {
     fn process_connection_by_id(&self, id: Token) -> Result<()> {
         let mut m = self.connections.lock().unwrap();
         if let Some((driver, connection)) = m.get_mut(&id) {
-            let close = match self.process_connection(driver, connection) {
+            let close = match process_connection(driver, connection) {
                 Err(AmqpError::Amqp(condition)) => Err(Some(condition)),
+                Err(AmqpError::AmqpConnectionFramingError(description)) => {
+                    Err(Some(ErrorCondition::framing_error(description)))
+                }
                 Err(e) => {
                     error!("Error while processing a connection: {:?}", e);
                     Err(None)
@@ -413,6 +534,7 @@ impl ContainerInner {
                 }
 
                 if let Some((_, mut connection)) = m.remove(&id) {
+                    let _ = connection.flush();
                     let _ = connection.shutdown();
                 }
 
@@ -423,43 +545,43 @@ impl ContainerInner {
         }
         Ok(())
     }
+}
+
+/// Read and dispatch frames of a connection until its network would block.
+pub(crate) fn process_connection<N: Network>(
+    driver: &ConnectionDriver,
+    connection: &mut conn::Connection<N>,
+) -> Result<()> {
+    if driver.closed() {
+        return Ok(());
+    }
 
-    fn process_connection(
-        &self,
-        driver: &ConnectionDriver,
-        connection: &mut conn::Connection<MioNetwork>,
-    ) -> Result<()> {
+    // Read frames until we're blocked
+    let mut rx_frames = Vec::new();
+    let result = loop {
         if driver.closed() {
             return Ok(());
         }
-
-        // Read frames until we're blocked
-        let mut rx_frames = Vec::new();
-        let result = loop {
-            if driver.closed() {
-                return Ok(());
+        let result = connection.process(&mut rx_frames);
+        match result {
+            Ok(_) => {}
+            // This means that we should poll again to await further I/O action for this driver.
+            Err(AmqpError::IoError(ref e)) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                break Ok(());
             }
-            let result = connection.process(&mut rx_frames);
-            match result {
-                Ok(_) => {}
-                // This means that we should poll again to await further I/O action for this driver.
-                Err(AmqpError::IoError(ref e)) if e.kind() == std::io::ErrorKind::WouldBlock => {
-                    break Ok(());
-                }
-                Err(ref e) => {
-                    error!("Processing connection frames failed: {:?}", e);
-                    break result;
-                }
+            Err(ref e) => {
+                error!("Processing connection frames failed: {:?}", e);
+                break result;
             }
-        };
-
-        if !rx_frames.is_empty() {
-            trace!("Dispatching {:?} frames", rx_frames.len());
         }
+    };
 
-        let dispatch_result = driver.dispatch(rx_frames);
-        result.and(dispatch_result)
+    if !rx_frames.is_empty() {
+        trace!("Dispatching {:?} frames", rx_frames.len());
     }
+
+    let dispatch_result = driver.dispatch(rx_frames);
+    result.and(dispatch_result)
 }
 
 impl Drop for ContainerInner {
@@ -469,6 +591,71 @@ impl Drop for ContainerInner {
 }
 
 impl Connection {
+    /// Send the initial open performative on a new connection and wait for the remote
+    /// open performative.
+    pub(crate) async fn open(
+        container_id: &str,
+        host: PeerAddress,
+        options: &ConnectionOptions,
+        driver: Arc<ConnectionDriver>,
+        waker: Arc<dyn ConnectionWaker>,
+    ) -> Result<Connection> {
+        driver.open({
+            let mut open = Open::new(container_id);
+            // open.hostname = Some(host.to_string());
+            open.channel_max = Some(u16::MAX);
+            open.idle_timeout = options.idle_timeout.map(|d| d.as_millis() as _);
+            // Announce the limit enforced when decoding received frames
+            open.max_frame_size = Some(
+                options
+                    .max_frame_size
+                    .unwrap_or(DEFAULT_MAX_FRAME_SIZE as u32),
+            );
+            open
+        })?;
+
+        loop {
+            let frame = driver.recv().await?;
+            match frame.performative {
+                Some(Performative::Open(o)) => {
+                    trace!("{}: received OPEN frame from {}", container_id, host);
+                    // Populate remote properties
+                    return Ok(Connection {
+                        waker,
+                        connection: driver,
+                        container_id: container_id.to_string(),
+                        host,
+                        channel_max: u16::MAX,
+                        idle_timeout: options.idle_timeout.unwrap_or_default(),
+                        max_frame_size: options
+                            .max_frame_size
+                            .unwrap_or(DEFAULT_MAX_FRAME_SIZE as u32),
+
+                        remote_container_id: o.container_id.clone(),
+                        remote_channel_max: o.channel_max.unwrap_or(u16::MAX),
+                        remote_max_frame_size: o.max_frame_size.unwrap_or(u32::MAX),
+                        remote_idle_timeout: Duration::from_millis(
+                            o.idle_timeout.unwrap_or(0) as u64
+                        ),
+                    });
+                }
+                Some(Performative::Close(c)) => {
+                    trace!("{}: received CLOSE frame from {}", container_id, host);
+                    return if let Some(e) = c.error {
+                        Err(AmqpError::Amqp(e))
+                    } else {
+                        Err(AmqpError::Generic("connection closed".to_string()))
+                    };
+                }
+                _ => {
+                    // Push it back into the queue
+                    // TODO: Prevent reordering
+                    driver.unrecv(frame)?;
+                }
+            }
+        }
+    }
+
     /// Create a new session over this connection. Returns a session once the other
     /// endpoint have confirmed the creation.
     pub async fn new_session(&self, opts: Option<SessionOpts>) -> Result<Session> {
@@ -530,6 +717,27 @@ impl Session {
             .await
     }
 
+    /// Declare a new transaction with the transaction coordinator of the remote peer. The
+    /// transaction is declared and discharged over a dedicated controller link.
+    pub async fn begin_transaction(&self) -> Result<Transaction> {
+        let (address, link) = self.session.new_link("", LinkOptions::Coordinator).await?;
+        let controller = Sender {
+            address,
+            link,
+            next_message_id: AtomicU64::new(0),
+        };
+        match Transaction::control(&controller, Declare::default().into())
+            .await?
+            .state
+        {
+            Some(DeliveryState::Declared(declared)) => Ok(Transaction {
+                controller,
+                id: declared.txn_id,
+            }),
+            state => Err(Transaction::error(state)),
+        }
+    }
+
     /// Create a new sender link for a given address cross this session. The sender
     /// is returned when the other side have confirmed its existence.
     async fn new_sender_with_link_options(
@@ -594,10 +802,15 @@ impl Sender {
         self.send_with_mode(message, SendMode::AtLeastOnce).await
     }
 
-    pub async fn send_with_mode(
+    pub async fn send_with_mode(&self, message: Message, mode: SendMode) -> Result<Disposition> {
+        self.send_with_state(message, mode, None).await
+    }
+
+    async fn send_with_state(
         &self,
         mut message: Message,
         mode: SendMode,
+        state: Option<DeliveryState>,
     ) -> Result<Disposition> {
         let message_id = Some(Value::Ulong(
             self.next_message_id.fetch_add(1, Ordering::SeqCst),
@@ -625,42 +838,44 @@ impl Sender {
                 Some(p)
             },
         );
-        let settled = SendMode::AtMostOnce == mode;
-        let delivery = self.link.send_message(message, settled).await?;
+        let settled = self.settled(mode);
+        let delivery = self.link.send_message(message, settled, state).await?;
         debug!(
             "Message sent (handle {}), awaiting disposition",
             self.link.handle
         );
 
         if !settled {
-            loop {
-                let frame = self.link.recv().await?;
-                match frame.performative {
-                    Some(Performative::Disposition(ref disposition)) => {
-                        let first = disposition.first;
-                        let last = disposition.last.unwrap_or(first);
-                        if first <= delivery.id && last >= delivery.id {
-                            // TODO: Better error checking
-                            return Ok(Disposition { delivery });
-                        } else {
-                            self.link.unrecv(frame)?;
-                        }
-                    }
-                    Some(Performative::Detach(detach)) => {
-                        debug!("Link got detached: {:?}", detach);
-                        let error_condition =
-                            detach.error.unwrap_or_else(ErrorCondition::detach_received);
-                        return Err(AmqpError::Amqp(error_condition));
-                    }
-                    _ => {
-                        // TODO: Prevent reordering
-                        warn!("({}) unreceiving: {:?}", self.link.handle, frame);
-                        self.link.unrecv(frame)?;
-                    }
-                }
-            }
+            await_disposition(&self.link, delivery).await
         } else {
-            Ok(Disposition { delivery })
+            Ok(Disposition {
+                delivery,
+                state: None,
+            })
+        }
+    }
+
+    /// Start sending a message with a body sent in chunks, each chunk as a data section.
+    /// The message is sent across multiple transfer frames, allowing large payloads to be
+    /// sent without buffering them in full.
+    pub async fn send_streaming(&self) -> Result<StreamingDelivery> {
+        let settled = self.settled(SendMode::AtLeastOnce);
+        let (delivery, transfer) = self.link.start_delivery(settled).await?;
+        Ok(StreamingDelivery {
+            link: self.link.clone(),
+            delivery,
+            transfer: Some(transfer),
+            done: false,
+        })
+    }
+
+    /// Whether a delivery sent with the given mode is pre-settled. The sender settle mode of
+    /// the link takes precedence over the send mode, unless it is mixed.
+    fn settled(&self, mode: SendMode) -> bool {
+        match self.link.snd_settle_mode() {
+            SenderSettleMode::Settled => true,
+            SenderSettleMode::Unsettled => false,
+            SenderSettleMode::Mixed => SendMode::AtMostOnce == mode,
         }
     }
 
@@ -681,6 +896,177 @@ impl Drop for Sender {
     }
 }
 
+/// Wait for the disposition of an unsettled delivery. A delivery left unsettled by the
+/// receiver, as in the second receiver settle mode, is settled with the received outcome.
+async fn await_disposition(
+    link: &LinkDriver,
+    delivery: Arc<DeliveryDriver>,
+) -> Result<Disposition> {
+    loop {
+        let frame = link.recv().await?;
+        match frame.performative {
+            Some(Performative::Disposition(ref disposition)) => {
+                let first = disposition.first;
+                let last = disposition.last.unwrap_or(first);
+                if first <= delivery.id && last >= delivery.id {
+                    // TODO: Better error checking
+                    match (disposition.settled, &disposition.state) {
+                        (Some(true), _) | (_, None) | (_, Some(DeliveryState::Received(_))) => {}
+                        (_, Some(outcome)) => link.disposition(&delivery, true, outcome.clone())?,
+                    }
+                    return Ok(Disposition {
+                        delivery,
+                        state: disposition.state.clone(),
+                    });
+                } else {
+                    link.unrecv(frame)?;
+                }
+            }
+            Some(Performative::Detach(detach)) => {
+                debug!("Link got detached: {:?}", detach);
+                let error_condition = detach.error.unwrap_or_else(ErrorCondition::detach_received);
+                return Err(AmqpError::Amqp(error_condition));
+            }
+            _ => {
+                // TODO: Prevent reordering
+                warn!("({}) unreceiving: {:?}", link.handle, frame);
+                link.unrecv(frame)?;
+            }
+        }
+    }
+}
+
+impl Disposition {
+    /// The delivery state sent by the receiver.
+    pub fn state(&self) -> Option<&DeliveryState> {
+        self.state.as_ref()
+    }
+}
+
+impl Transaction {
+    /// The transaction identifier assigned by the coordinator.
+    pub fn id(&self) -> &[u8] {
+        &self.id
+    }
+
+    /// Send a message as part of the transaction.
+    pub async fn send(&self, sender: &Sender, message: Message) -> Result<Disposition> {
+        sender
+            .send_with_state(message, SendMode::AtLeastOnce, Some(self.state(None)))
+            .await
+    }
+
+    /// Settle a delivery as part of the transaction. The given outcome takes effect when the
+    /// transaction is committed.
+    pub async fn disposition(&self, delivery: &mut Delivery, outcome: DeliveryState) -> Result<()> {
+        delivery.disposition(true, self.state(Some(outcome))).await
+    }
+
+    /// Commit the transaction.
+    pub async fn commit(self) -> Result<()> {
+        self.discharge(false).await
+    }
+
+    /// Roll back the transaction.
+    pub async fn rollback(self) -> Result<()> {
+        self.discharge(true).await
+    }
+
+    async fn discharge(self, fail: bool) -> Result<()> {
+        let discharge = Discharge {
+            txn_id: self.id.clone(),
+            fail: Some(fail),
+        };
+        match Transaction::control(&self.controller, discharge.into())
+            .await?
+            .state
+        {
+            Some(DeliveryState::Accepted) => Ok(()),
+            state => Err(Transaction::error(state)),
+        }
+    }
+
+    fn state(&self, outcome: Option<DeliveryState>) -> DeliveryState {
+        DeliveryState::TransactionalState(TransactionalState {
+            txn_id: self.id.clone(),
+            outcome: outcome.map(Box::new),
+        })
+    }
+
+    /// Send a control message to the coordinator.
+    async fn control(controller: &Sender, body: Value) -> Result<Disposition> {
+        controller.link.wait_for_credit().await?;
+        controller
+            .send_with_state(Message::amqp_value(body), SendMode::AtLeastOnce, None)
+            .await
+    }
+
+    fn error(state: Option<DeliveryState>) -> AmqpError {
+        match state {
+            Some(DeliveryState::Rejected(Rejected { error: Some(error) })) => {
+                AmqpError::Amqp(error)
+            }
+            state => AmqpError::Generic(format!("Unexpected transaction outcome {:?}", state)),
+        }
+    }
+}
+
+impl StreamingDelivery {
+    /// Send a chunk of the message body as a data section.
+    pub async fn send(&mut self, chunk: &[u8]) -> Result<()> {
+        let mut payload = Vec::new();
+        MessageBody::Data(chunk.to_vec()).encode(&mut payload)?;
+        self.link
+            .send_chunk(self.transfer.take(), payload, true)
+            .await
+    }
+
+    /// Complete the message and wait for its disposition. A message without chunks is sent
+    /// with an empty body.
+    pub async fn finish(mut self) -> Result<Disposition> {
+        let mut payload = Vec::new();
+        if self.transfer.is_some() {
+            MessageBody::Data(Vec::new()).encode(&mut payload)?;
+        }
+        self.done = true;
+        self.link
+            .send_chunk(self.transfer.take(), payload, false)
+            .await?;
+        if self.delivery.settled {
+            return Ok(Disposition {
+                delivery: self.delivery.clone(),
+                state: None,
+            });
+        }
+        debug!(
+            "Message sent (handle {}), awaiting disposition",
+            self.link.handle
+        );
+        await_disposition(&self.link, self.delivery.clone()).await
+    }
+
+    /// Abort the message. The receiver discards the chunks sent so far.
+    pub async fn abort(mut self) -> Result<()> {
+        self.done = true;
+        self.link.abort(&self.delivery, self.transfer.take()).await
+    }
+}
+
+impl Drop for StreamingDelivery {
+    /// Abort an unfinished delivery. Dropping does not wait for the session window, so if it
+    /// is closed the link is detached instead, which also ends the delivery.
+    fn drop(&mut self) {
+        if !self.done {
+            match self.link.try_abort(&self.delivery, self.transfer.take()) {
+                Ok(true) => {}
+                _ => {
+                    let _ = self.link.close(None);
+                }
+            }
+        }
+    }
+}
+
 impl Receiver {
     /// Issue credits to the remote sender link, signalling that the receiver canaldigital
     /// accept more messages.
@@ -689,14 +1075,43 @@ impl Receiver {
     }
 
     /// Receive a single message across the link. The delivery is returned
-    /// when a message is received.
+    /// when a message is received. Messages transferred in multiple frames are
+    /// returned once the last frame is received.
     pub async fn receive(&self) -> Result<Delivery> {
+        let (delivery, mut input) = self.receive_transfer().await?;
+        let limits = self.link.connection().decode_limits();
+        let message = Message::decode_with_limits(&mut input, &limits)?;
+        Ok(Delivery {
+            settled: false,
+            link: self.link.clone(),
+            message: Some(message),
+            delivery,
+        })
+    }
+
+    /// Receive a single message across the link without decoding it. The message sections
+    /// can be accessed on demand using [`LazyDelivery::message`].
+    pub async fn receive_lazy(&self) -> Result<LazyDelivery> {
+        let (delivery, input) = self.receive_transfer().await?;
+        let limits = self.link.connection().decode_limits();
+        let message = LazyMessage::with_limits(input, limits)?;
+        Ok(LazyDelivery {
+            message,
+            delivery: Delivery {
+                settled: false,
+                link: self.link.clone(),
+                message: None,
+                delivery,
+            },
+        })
+    }
+
+    async fn receive_transfer(&self) -> Result<(Arc<DeliveryDriver>, Vec<u8>)> {
         loop {
             let frame = self.link.recv().await?;
             match frame.performative {
                 Some(Performative::Transfer(transfer)) => {
-                    if let Some(mut input) = frame.payload {
-                        let message = Message::decode(&mut input)?;
+                    if let Some(input) = frame.payload {
                         let delivery = Arc::new(DeliveryDriver {
                             state: transfer.state,
                             tag: transfer
@@ -709,16 +1124,17 @@ impl Receiver {
                             message: None,
                             settled: false,
                         });
-                        return Ok(Delivery {
-                            settled: false,
-                            link: self.link.clone(),
-                            message: Some(message),
-                            delivery,
-                        });
+                        return Ok((delivery, input));
                     } else {
                         return Err(AmqpError::TransferFrameIsMissingPayload);
                     }
                 }
+                Some(Performative::Detach(detach)) => {
+                    debug!("Link got detached: {:?}", detach);
+                    let error_condition =
+                        detach.error.unwrap_or_else(ErrorCondition::detach_received);
+                    return Err(AmqpError::Amqp(error_condition));
+                }
                 _ => {
                     // TODO: Prevent reordering
                     self.link.unrecv(frame)?;
@@ -751,23 +1167,60 @@ impl Delivery {
     }
 
     /// Send a disposition for this delivery, indicating message settlement and delivery state.
+    /// In the second receiver settle mode, settling the delivery sends the outcome and waits
+    /// for the sender to settle it first.
     pub async fn disposition(&mut self, settled: bool, state: DeliveryState) -> Result<()> {
         if !self.settled {
-            self.link.disposition(&self.delivery, settled, state)?;
+            if settled && self.settles_second() {
+                self.link
+                    .send_outcome(&self.delivery, state)?
+                    .recv()
+                    .await?;
+            } else {
+                self.link.disposition(&self.delivery, settled, state)?;
+            }
             self.settled = settled;
         }
         Ok(())
     }
+
+    /// Whether the delivery is settled by the sender before the receiver.
+    fn settles_second(&self) -> bool {
+        self.link.rcv_settle_mode() == ReceiverSettleMode::Second && !self.delivery.remotely_settled
+    }
+}
+
+impl LazyDelivery {
+    /// Retrieve reference to the undecoded message associated with this delivery.
+    pub fn message(&self) -> &LazyMessage {
+        &self.message
+    }
+
+    /// Take the undecoded message, settling the delivery as accepted.
+    pub fn into_message(self) -> LazyMessage {
+        self.message
+    }
+
+    /// Send a disposition for this delivery, indicating message settlement and delivery state.
+    pub async fn disposition(&mut self, settled: bool, state: DeliveryState) -> Result<()> {
+        self.delivery.disposition(settled, state).await
+    }
 }
 
 impl Drop for Delivery {
     fn drop(&mut self) {
         if !self.settled {
             self.settled = true;
-            if let Err(e) = self
-                .link
-                .disposition(&self.delivery, true, DeliveryState::Accepted)
-            {
+            let result = if self.settles_second() {
+                // The delivery is settled once the sender settles it
+                self.link
+                    .send_outcome(&self.delivery, DeliveryState::Accepted)
+                    .map(|_| ())
+            } else {
+                self.link
+                    .disposition(&self.delivery, true, DeliveryState::Accepted)
+            };
+            if let Err(e) = result {
                 error!(
                     "Disposition failed for delivery with id {}: {:?}",
                     self.delivery.id, e
@@ -776,3 +1229,351 @@ impl Drop for Delivery {
         }
     }
 }
+
+#[cfg(all(test, unix))]
+mod tests {
+
+    use super::*;
+    use crate::driver::tests::{attach, dispatch, flow, new_session, set_remote_incoming_window};
+    use crate::driver::{Channel, SessionDriver};
+    use crate::framing::{AmqpFrame, Attach, Frame, Transfer};
+    use crate::testing::{accept_connection, read_performative};
+    use futures::executor::block_on;
+    use mio::{Registry, Token};
+    use std::io::{Read, Write};
+    use std::os::unix::net::UnixStream;
+
+    // A network on one end of a socket pair, standing in for a custom transport
+    #[derive(Debug)]
+    struct PipeNetwork(mio::net::UnixStream);
+
+    impl Network for PipeNetwork {
+        fn set_nodelay(&self, _: bool) -> Result<()> {
+            Ok(())
+        }
+
+        fn close(&mut self) -> Result<()> {
+            Ok(self.0.shutdown(std::net::Shutdown::Both)?)
+        }
+    }
+
+    impl Read for PipeNetwork {
+        fn read(&mut self, b: &mut [u8]) -> std::io::Result<usize> {
+            self.0.read(b)
+        }
+    }
+
+    impl Write for PipeNetwork {
+        fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+            self.0.write(data)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            self.0.flush()
+        }
+    }
+
+    impl Source for PipeNetwork {
+        fn register(
+            &mut self,
+            registry: &Registry,
+            token: Token,
+            interests: Interest,
+        ) -> std::io::Result<()> {
+            self.0.register(registry, token, interests)
+        }
+
+        fn reregister(
+            &mut self,
+            registry: &Registry,
+            token: Token,
+            interests: Interest,
+        ) -> std::io::Result<()> {
+            self.0.reregister(registry, token, interests)
+        }
+
+        fn deregister(&mut self, registry: &Registry) -> std::io::Result<()> {
+            self.0.deregister(registry)
+        }
+    }
+
+    fn pipe() -> (PipeNetwork, UnixStream) {
+        let (local, remote) = UnixStream::pair().unwrap();
+        local.set_nonblocking(true).unwrap();
+        (PipeNetwork(mio::net::UnixStream::from_std(local)), remote)
+    }
+
+    #[test]
+    fn connect_with_transport() {
+        let (network, remote) = pipe();
+        let peer = thread::spawn(move || accept_connection(remote, Open::new("peer")));
+
+        let container = Container::new().unwrap().start();
+        let connection = block_on(container.connect_with_transport(
+            network,
+            PeerAddress::Other("pipe".to_string()),
+            ConnectionOptions::new(),
+        ))
+        .unwrap();
+        peer.join().unwrap();
+
+        assert_eq!("peer", connection.remote_container_id);
+        assert_eq!(PeerAddress::Other("pipe".to_string()), connection.host);
+    }
+
+    /// A sender attached to a session whose outgoing frames are collected in the returned
+    /// channel, with the given credit granted.
+    fn new_sender(credit: u32) -> (Arc<SessionDriver>, Channel<Frame>, Sender) {
+        new_sender_with(credit, |a| a)
+    }
+
+    /// A sender like [`new_sender`], attached with the response returned by the given function.
+    fn new_sender_with(
+        credit: u32,
+        respond: impl FnOnce(Attach) -> Attach,
+    ) -> (Arc<SessionDriver>, Channel<Frame>, Sender) {
+        let (session, frames) = new_session();
+        let link = attach(&session, &frames, LinkRole::Sender, respond);
+        dispatch(&session, flow(link.handle, credit, 10), None);
+        let sender = Sender {
+            address: "queue1".to_string(),
+            link,
+            next_message_id: AtomicU64::new(0),
+        };
+        (session, frames, sender)
+    }
+
+    fn next_transfer(frames: &Channel<Frame>) -> (Transfer, Option<Vec<u8>>) {
+        match frames.try_recv().unwrap() {
+            Frame::AMQP(AmqpFrame {
+                performative: Some(Performative::Transfer(transfer)),
+                payload,
+                ..
+            }) => (transfer, payload),
+            frame => panic!("unexpected frame {:?}", frame),
+        }
+    }
+
+    /// Dispatch the disposition of the remote receiver accepting a delivery.
+    fn accept(session: &SessionDriver, delivery_id: u32) {
+        // The decoded role of a disposition from the receiver is the sender role
+        let disposition = crate::framing::Disposition {
+            role: LinkRole::Sender,
+            first: delivery_id,
+            last: None,
+            settled: Some(true),
+            state: Some(DeliveryState::Accepted),
+            batchable: None,
+        };
+        dispatch(session, Performative::Disposition(disposition), None);
+    }
+
+    fn data(chunk: &[u8]) -> Vec<u8> {
+        let mut payload = Vec::new();
+        MessageBody::Data(chunk.to_vec())
+            .encode(&mut payload)
+            .unwrap();
+        payload
+    }
+
+    #[test]
+    fn send_streaming() {
+        let (session, frames, sender) = new_sender(2);
+        let mut delivery = block_on(sender.send_streaming()).unwrap();
+        assert_eq!(1, sender.credits());
+        assert!(frames.try_recv().is_err());
+
+        block_on(delivery.send(b"ab")).unwrap();
+        block_on(delivery.send(b"cd")).unwrap();
+        let (disposition, _) =
+            block_on(async { futures::join!(delivery.finish(), async { accept(&session, 0) }) });
+        assert_eq!(Some(&DeliveryState::Accepted), disposition.unwrap().state());
+
+        let (first, payload) = next_transfer(&frames);
+        assert_eq!(Some(0), first.delivery_id);
+        assert!(first.delivery_tag.is_some());
+        assert_eq!(Some(false), first.settled);
+        assert_eq!(Some(true), first.more);
+        assert_eq!(Some(data(b"ab")), payload);
+
+        let (second, payload) = next_transfer(&frames);
+        assert_eq!(None, second.delivery_id);
+        assert_eq!(Some(true), second.more);
+        assert_eq!(Some(data(b"cd")), payload);
+
+        let (last, payload) = next_transfer(&frames);
+        assert_eq!(None, last.delivery_id);
+        assert_eq!(Some(false), last.more);
+        assert_ne!(Some(true), last.aborted);
+        assert_eq!(Some(Vec::new()), payload);
+        assert!(frames.try_recv().is_err());
+    }
+
+    #[test]
+    fn send_streaming_without_chunks() {
+        let (session, frames, sender) = new_sender(1);
+        let delivery = block_on(sender.send_streaming()).unwrap();
+        let (disposition, _) =
+            block_on(async { futures::join!(delivery.finish(), async { accept(&session, 0) }) });
+        disposition.unwrap();
+
+        let (transfer, payload) = next_transfer(&frames);
+        assert_eq!(Some(0), transfer.delivery_id);
+        assert_eq!(Some(false), transfer.more);
+        assert_eq!(Some(data(b"")), payload);
+        assert!(matches!(
+            block_on(sender.send_streaming()),
+            Err(AmqpError::NotEnoughCredits)
+        ));
+    }
+
+    #[test]
+    fn abort_streaming() {
+        let (_session, frames, sender) = new_sender(2);
+        let mut delivery = block_on(sender.send_streaming()).unwrap();
+        block_on(delivery.send(b"ab")).unwrap();
+        block_on(delivery.abort()).unwrap();
+
+        let (first, _) = next_transfer(&frames);
+        assert_eq!(Some(0), first.delivery_id);
+        assert_eq!(Some(true), first.more);
+        let (aborted, payload) = next_transfer(&frames);
+        assert_eq!(None, aborted.delivery_id);
+        assert_eq!(Some(false), aborted.more);
+        assert_eq!(Some(true), aborted.aborted);
+        assert_eq!(None, payload);
+
+        // The aborted delivery consumed its credit and delivery id
+        assert_eq!(1, sender.credits());
+        block_on(block_on(sender.send_streaming()).unwrap().abort()).unwrap();
+        let (next, _) = next_transfer(&frames);
+        assert_eq!(Some(1), next.delivery_id);
+        assert_eq!(Some(true), next.aborted);
+        assert_eq!(0, sender.credits());
+    }
+
+    #[test]
+    fn drop_streaming() {
+        let (session, frames, sender) = new_sender(2);
+        drop(block_on(sender.send_streaming()).unwrap());
+        let (aborted, payload) = next_transfer(&frames);
+        assert_eq!(Some(0), aborted.delivery_id);
+        assert_eq!(Some(true), aborted.aborted);
+        assert_eq!(None, payload);
+
+        // Without a session window, dropping detaches the link instead of waiting
+        let mut delivery = block_on(sender.send_streaming()).unwrap();
+        block_on(delivery.send(b"ab")).unwrap();
+        next_transfer(&frames);
+        set_remote_incoming_window(&session, 0);
+        drop(delivery);
+        assert!(matches!(
+            frames.try_recv().unwrap(),
+            Frame::AMQP(AmqpFrame {
+                performative: Some(Performative::Detach(_)),
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn sender_settle_modes() {
+        let (_session, _frames, sender) = new_sender(1);
+        assert!(sender.settled(SendMode::AtMostOnce));
+        assert!(!sender.settled(SendMode::AtLeastOnce));
+
+        // The settle mode of the response takes precedence over the requested one
+        let (_session, frames, sender) = new_sender_with(1, |mut a| {
+            a.snd_settle_mode = Some(SenderSettleMode::Settled);
+            a
+        });
+        assert!(sender.settled(SendMode::AtLeastOnce));
+        block_on(sender.send(Message::amqp_value(Value::Int(1)))).unwrap();
+        let (transfer, _) = next_transfer(&frames);
+        assert_eq!(Some(true), transfer.settled);
+
+        // Unsettled deliveries wait for the disposition of the receiver
+        let (session, frames, sender) = new_sender_with(1, |mut a| {
+            a.snd_settle_mode = Some(SenderSettleMode::Unsettled);
+            a
+        });
+        assert!(!sender.settled(SendMode::AtMostOnce));
+        let (disposition, _) = block_on(async {
+            futures::join!(
+                sender.send_with_mode(Message::amqp_value(Value::Int(1)), SendMode::AtMostOnce),
+                async { accept(&session, 0) }
+            )
+        });
+        assert_eq!(Some(&DeliveryState::Accepted), disposition.unwrap().state());
+        let (transfer, _) = next_transfer(&frames);
+        assert_eq!(Some(false), transfer.settled);
+    }
+
+    #[test]
+    fn connect_url_options() {
+        let url = Url::parse("amqps://localhost/queue1").unwrap();
+        let opts = ConnectionOptions::new().for_url(&url);
+        #[cfg(feature = "tls")]
+        assert_eq!(Some("localhost"), opts.unwrap().tls.unwrap().server_name());
+        #[cfg(not(feature = "tls"))]
+        assert!(matches!(opts, Err(AmqpError::Generic(_))));
+
+        let url = Url::parse("ws://localhost/queue1").unwrap();
+        let opts = ConnectionOptions::new().for_url(&url);
+        #[cfg(feature = "websocket")]
+        assert_eq!(
+            Some("ws://localhost:80/".to_string()),
+            opts.unwrap().websocket
+        );
+        #[cfg(feature = "websocket")]
+        assert_eq!(
+            Some("ws://localhost:80/$servicebus/websocket".to_string()),
+            ConnectionOptions::new()
+                .websocket_path("/$servicebus/websocket")
+                .for_url(&url)
+                .unwrap()
+                .websocket
+        );
+        #[cfg(not(feature = "websocket"))]
+        assert!(matches!(opts, Err(AmqpError::Generic(_))));
+    }
+
+    #[test]
+    fn reject_small_max_frame_size() {
+        let (network, _remote) = pipe();
+
+        let container = Container::new().unwrap().start();
+        let result = block_on(container.connect_with_transport(
+            network,
+            PeerAddress::Other("pipe".to_string()),
+            ConnectionOptions::new().max_frame_size(511),
+        ));
+        assert!(matches!(result, Err(AmqpError::Generic(_))));
+    }
+
+    #[test]
+    fn reject_small_remote_max_frame_size() {
+        let (network, remote) = pipe();
+        let peer = thread::spawn(move || {
+            let mut open = Open::new("peer");
+            open.max_frame_size = Some(511);
+            let mut transport = accept_connection(remote, open);
+            read_performative(&mut transport)
+        });
+
+        let container = Container::new().unwrap().start();
+        let result = block_on(container.connect_with_transport(
+            network,
+            PeerAddress::Other("pipe".to_string()),
+            ConnectionOptions::new(),
+        ));
+        assert!(result.is_err());
+        match peer.join().unwrap() {
+            Performative::Close(close) => assert_eq!(
+                "amqp:connection:framing-error",
+                close.error.unwrap().condition
+            ),
+            performative => panic!("unexpected performative {:?}", performative),
+        }
+    }
+}
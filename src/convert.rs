@@ -7,11 +7,15 @@
 //! when decoding frames. At present there is a lot of duplication, and this part     *
 //! could use some refactoring to simplify.                                           *
 
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
 use std::vec::Vec;
+use uuid::Uuid;
 
+use crate::decimal::*;
 use crate::error::*;
 use crate::frame_codec::*;
+use crate::registry::{convert, Described, DescriptorRegistry};
 use crate::symbol::*;
 use crate::types::*;
 
@@ -36,141 +40,297 @@ impl TryFromValue for Vec<u8> {
     fn try_from(value: Value) -> Result<Self> {
         match value {
             Value::Binary(v) => Ok(v),
-            v => Err(AmqpError::decode_error(Some(
-                format!("Error converting value to u8: {:?}", v).as_str(),
-            ))),
+            v => Err(AmqpError::unexpected_type("binary", &v)),
         }
     }
 }
 
+/// Lists and arrays convert element-wise, and any other value except a map converts into a
+/// single element vector, which is how AMQP encodes fields declared as `multiple`. Maps are
+/// rejected, use `BTreeMap` or `HashMap` to convert them.
 impl<T: TryFromValueVec> TryFromValue for Vec<T> {
     fn try_from(value: Value) -> Result<Self> {
         match value {
             Value::List(v) | Value::Array(v) => {
                 Ok(v.into_iter().map(T::try_from).collect::<Result<Vec<T>>>()?)
             }
+            Value::Map(_) => Err(AmqpError::unexpected_type("list", &value)),
             _ => Ok(vec![T::try_from(value)?]),
         }
     }
 }
 
-impl TryFromValue for u8 {
-    fn try_from(value: Value) -> Result<Self> {
-        match value {
-            Value::Ubyte(v) => Ok(v),
-            v => Err(AmqpError::decode_error(Some(
-                format!("Error converting value to u8: {:?}", v).as_str(),
-            ))),
+macro_rules! try_from_value {
+    ($t:ty, $expected:literal, $($variant:ident)|+) => {
+        impl TryFromValue for $t {
+            fn try_from(value: Value) -> Result<Self> {
+                match value {
+                    $(Value::$variant(v) => Ok(v.into()),)+
+                    v => Err(AmqpError::unexpected_type($expected, &v)),
+                }
+            }
         }
-    }
+    };
 }
 
-impl TryFromValue for u64 {
+try_from_value!(u8, "ubyte", Ubyte);
+try_from_value!(u16, "ushort", Ushort);
+try_from_value!(u32, "uint", Uint);
+try_from_value!(u64, "ulong", Ulong);
+try_from_value!(i8, "byte", Byte);
+try_from_value!(i16, "short", Short);
+try_from_value!(i32, "int", Int);
+try_from_value!(i64, "long", Long);
+try_from_value!(Float, "float", Float);
+try_from_value!(Double, "double", Double);
+try_from_value!(bool, "boolean", Bool);
+try_from_value!(char, "char", Char);
+try_from_value!(Decimal32, "decimal32", Decimal32);
+try_from_value!(Decimal64, "decimal64", Decimal64);
+try_from_value!(Decimal128, "decimal128", Decimal128);
+try_from_value!(Uuid, "uuid", Uuid);
+
+impl TryFromValue for f32 {
     fn try_from(value: Value) -> Result<Self> {
-        match value {
-            Value::Ulong(v) => Ok(v),
-            _ => Err(AmqpError::decode_error(Some(
-                "Error converting value to u64",
-            ))),
-        }
+        <Float as TryFromValue>::try_from(value).map(|v| v.0)
     }
 }
 
-impl TryFromValue for u32 {
+impl TryFromValue for f64 {
     fn try_from(value: Value) -> Result<Self> {
-        match value {
-            Value::Uint(v) => Ok(v),
-            _ => Err(AmqpError::decode_error(Some(
-                "Error converting value to u32",
-            ))),
-        }
+        <Double as TryFromValue>::try_from(value).map(|v| v.0)
     }
 }
 
-impl TryFromValue for u16 {
+impl TryFromValue for String {
     fn try_from(value: Value) -> Result<Self> {
         match value {
-            Value::Ushort(v) => Ok(v),
-            _ => Err(AmqpError::decode_error(Some(
-                "Error converting value to u32",
-            ))),
+            Value::Symbol(v) => Ok(String::from_utf8_lossy(&v[..]).to_string()),
+            Value::SymbolSlice(v) => Ok(String::from_utf8_lossy(v).to_string()),
+            Value::String(v) => Ok(v),
+            Value::Str(v) => Ok(v.to_string()),
+            v => Err(AmqpError::unexpected_type("string", &v)),
         }
     }
 }
 
-impl TryFromValue for bool {
+impl<K, V> TryFromValue for BTreeMap<K, V>
+where
+    K: TryFromValue + Ord,
+    V: TryFromValue,
+{
     fn try_from(value: Value) -> Result<Self> {
         match value {
-            Value::Bool(v) => Ok(v),
-            _ => Err(AmqpError::decode_error(Some(
-                "Error converting value to bool",
-            ))),
+            Value::Map(v) => v
+                .into_iter()
+                .map(|(key, value)| Ok((K::try_from(key)?, V::try_from(value)?)))
+                .collect(),
+            v => Err(AmqpError::unexpected_type("map", &v)),
         }
     }
 }
 
-impl TryFromValueVec for String {}
-impl TryFromValue for String {
+impl<K, V> TryFromValue for HashMap<K, V>
+where
+    K: TryFromValue + Eq + Hash,
+    V: TryFromValue,
+{
     fn try_from(value: Value) -> Result<Self> {
         match value {
-            Value::Symbol(v) => Ok(String::from_utf8_lossy(&v[..]).to_string()),
-            Value::String(v) => Ok(v),
-            _ => Err(AmqpError::decode_error(Some(
-                "Error converting value to String",
-            ))),
+            Value::Map(v) => v
+                .into_iter()
+                .map(|(key, value)| Ok((K::try_from(key)?, V::try_from(value)?)))
+                .collect(),
+            v => Err(AmqpError::unexpected_type("map", &v)),
         }
     }
 }
 
-impl<T: TryFromValue + std::cmp::Ord> TryFromValue for BTreeMap<T, Value> {
-    fn try_from(value: Value) -> Result<Self> {
-        match value {
-            Value::Map(v) => {
-                let mut m = BTreeMap::new();
-                for (key, value) in v.into_iter() {
-                    m.insert(T::try_from(key)?, value);
+/// Tuples convert from lists with the same number of elements.
+macro_rules! try_from_value_tuple {
+    ($len:literal, $($t:ident),+) => {
+        impl<$($t: TryFromValue),+> TryFromValue for ($($t,)+) {
+            fn try_from(value: Value) -> Result<Self> {
+                match value {
+                    Value::List(v) if v.len() == $len => {
+                        let mut v = v.into_iter();
+                        Ok(($($t::try_from(v.next().unwrap())?,)+))
+                    }
+                    Value::List(v) => Err(AmqpError::decode_error(Some(
+                        format!("Expected list of {} elements, found {}", $len, v.len()).as_str(),
+                    ))),
+                    v => Err(AmqpError::unexpected_type("list", &v)),
                 }
-                Ok(m)
             }
-            _ => Err(AmqpError::decode_error(Some(
-                "Error converting value to Map<T, Value>",
-            ))),
         }
-    }
+
+        impl<$($t: TryFromValue),+> TryFromValueVec for ($($t,)+) {}
+    };
 }
 
+try_from_value_tuple!(1, A);
+try_from_value_tuple!(2, A, B);
+try_from_value_tuple!(3, A, B, C);
+try_from_value_tuple!(4, A, B, C, D);
+try_from_value_tuple!(5, A, B, C, D, E);
+try_from_value_tuple!(6, A, B, C, D, E, F);
+
 impl TryFromValue for Timestamp {
-    fn try_from(value: Value) -> Result<Self>
-    where
-        Self: Sized,
-    {
+    fn try_from(value: Value) -> Result<Self> {
         match value {
             Value::Timestamp(v) => Ok(Timestamp(v)),
-            _ => Err(AmqpError::decode_error(Some(
-                "Error converting value to Timestamp",
-            ))),
+            v => Err(AmqpError::unexpected_type("timestamp", &v)),
         }
     }
 }
 
+/// Described values are converted using the process wide registry.
+impl TryFromValue for Described {
+    fn try_from(value: Value) -> Result<Self> {
+        convert(value)
+    }
+}
+
+/// Convert a described value into a type registered in the given registry.
+pub fn try_from_registered<T: 'static>(value: Value, registry: &DescriptorRegistry) -> Result<T> {
+    match registry.convert(value)?.downcast::<T>() {
+        Ok(value) => Ok(value),
+        Err(Described::Registered(value)) => Err(AmqpError::decode_error(Some(
+            format!("Unexpected registered type {:?}", value).as_str(),
+        ))),
+        Err(Described::Unknown(value)) => Err(AmqpError::decode_error(Some(
+            format!("No registered type for {:?}", value).as_str(),
+        ))),
+    }
+}
+
 impl TryFromValue for Value {
     fn try_from(value: Value) -> Result<Self> {
         Ok(value)
     }
 }
 
-impl TryFromValueVec for Symbol {}
 impl TryFromValue for Symbol {
     fn try_from(value: Value) -> Result<Self> {
         match value {
             Value::Symbol(v) => Ok(Symbol::from(v)),
-            _ => Err(AmqpError::decode_error(Some(
-                "Error converting value to Symbol",
-            ))),
+            Value::SymbolSlice(v) => Ok(Symbol::from(v)),
+            v => Err(AmqpError::unexpected_type("symbol", &v)),
         }
     }
 }
 
+/// Conversion through the standard `TryFrom` trait for the types that convert into a value,
+/// and for optional values of these types.
+macro_rules! try_from {
+    ($($t:ty),+) => {
+        $(
+            impl TryFrom<Value> for $t {
+                type Error = AmqpError;
+
+                fn try_from(value: Value) -> Result<Self> {
+                    <$t as TryFromValue>::try_from(value)
+                }
+            }
+
+            impl TryFrom<Value> for Option<$t> {
+                type Error = AmqpError;
+
+                fn try_from(value: Value) -> Result<Self> {
+                    <Option<$t> as TryFromValue>::try_from(value)
+                }
+            }
+        )+
+    };
+}
+
+impl<T: TryFromValueVec> TryFrom<Value> for Vec<T> {
+    type Error = AmqpError;
+
+    fn try_from(value: Value) -> Result<Self> {
+        <Vec<T> as TryFromValue>::try_from(value)
+    }
+}
+
+impl<K, V> TryFrom<Value> for BTreeMap<K, V>
+where
+    K: TryFromValue + Ord,
+    V: TryFromValue,
+{
+    type Error = AmqpError;
+
+    fn try_from(value: Value) -> Result<Self> {
+        <BTreeMap<K, V> as TryFromValue>::try_from(value)
+    }
+}
+
+impl<K, V> TryFrom<Value> for HashMap<K, V>
+where
+    K: TryFromValue + Eq + Hash,
+    V: TryFromValue,
+{
+    type Error = AmqpError;
+
+    fn try_from(value: Value) -> Result<Self> {
+        <HashMap<K, V> as TryFromValue>::try_from(value)
+    }
+}
+
+try_from!(
+    u8,
+    u16,
+    u32,
+    u64,
+    i8,
+    i16,
+    i32,
+    i64,
+    f32,
+    f64,
+    Float,
+    Double,
+    bool,
+    char,
+    Decimal32,
+    Decimal64,
+    Decimal128,
+    Uuid,
+    String,
+    Vec<u8>,
+    Timestamp,
+    Symbol,
+    ErrorCondition
+);
+
+impl TryFromValueVec for u16 {}
+impl TryFromValueVec for u32 {}
+impl TryFromValueVec for u64 {}
+impl TryFromValueVec for i8 {}
+impl TryFromValueVec for i16 {}
+impl TryFromValueVec for i32 {}
+impl TryFromValueVec for i64 {}
+impl TryFromValueVec for f32 {}
+impl TryFromValueVec for f64 {}
+impl TryFromValueVec for Float {}
+impl TryFromValueVec for Double {}
+impl TryFromValueVec for bool {}
+impl TryFromValueVec for char {}
+impl TryFromValueVec for Decimal32 {}
+impl TryFromValueVec for Decimal64 {}
+impl TryFromValueVec for Decimal128 {}
+impl TryFromValueVec for Uuid {}
+impl TryFromValueVec for String {}
+impl TryFromValueVec for Timestamp {}
+impl TryFromValueVec for Value {}
+impl TryFromValueVec for Symbol {}
+impl TryFromValueVec for Described {}
+impl TryFromValueVec for ErrorCondition {}
+impl TryFromValueVec for Vec<u8> {}
+impl<T: TryFromValueVec> TryFromValueVec for Vec<T> {}
+impl<T: TryFromValue> TryFromValueVec for Option<T> {}
+impl<K: TryFromValue + Ord, V: TryFromValue> TryFromValueVec for BTreeMap<K, V> {}
+impl<K: TryFromValue + Eq + Hash, V: TryFromValue> TryFromValueVec for HashMap<K, V> {}
+
 impl TryFromValue for ErrorCondition {
     fn try_from(value: Value) -> Result<Self> {
         if let Value::Described(descriptor, mut list) = value {
@@ -188,3 +348,65 @@ impl TryFromValue for ErrorCondition {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn roundtrip<T: TryFromValue + Into<Value> + Clone + PartialEq>(value: T) {
+        assert_eq!(value, T::try_from(value.clone().into()).unwrap());
+    }
+
+    #[test]
+    fn check_conversions() {
+        roundtrip(-1i8);
+        roundtrip(-2i16);
+        roundtrip(-3i32);
+        roundtrip(i64::MIN);
+        roundtrip('x');
+        roundtrip(Some(Some(1u32)));
+        roundtrip(None::<u32>);
+        roundtrip((1u8, String::from("two"), (3i64, true)));
+        roundtrip(HashMap::from([
+            (String::from("a"), 1i32),
+            (String::from("b"), 2i32),
+        ]));
+        roundtrip(BTreeMap::from([(1u64, vec![1u8, 2u8])]));
+        roundtrip(ErrorCondition {
+            condition: String::from("amqp:not-found"),
+            description: String::from("missing"),
+        });
+
+        let list: Vec<i32> =
+            TryFromValue::try_from(Value::from(vec![Value::Int(1), Value::Int(2)])).unwrap();
+        assert_eq!(vec![1, 2], list);
+        let map = Value::Map(vec![(Value::from("a"), Value::Int(1))]);
+        assert!(<Vec<(String, i32)> as TryFromValue>::try_from(map.clone()).is_err());
+        let map: HashMap<String, i32> = map.try_into().unwrap();
+        assert_eq!(Some(&1), map.get("a"));
+        let nested: Vec<Vec<Symbol>> = TryFromValue::try_from(Value::List(vec![Value::Array(
+            vec![Value::Symbol(b"a".to_vec())],
+        )]))
+        .unwrap();
+        assert_eq!(vec![vec![Symbol::from_static_str("a")]], nested);
+        let long: std::result::Result<i64, AmqpError> = Value::Long(7).try_into();
+        assert_eq!(7, long.unwrap());
+        let list: Vec<u32> = Value::List(vec![Value::Uint(1)]).try_into().unwrap();
+        assert_eq!(vec![1], list);
+        let none: Option<String> = Value::Null.try_into().unwrap();
+        assert_eq!(None, none);
+        let map: BTreeMap<u64, Vec<u8>> = Value::from(BTreeMap::from([(1u64, vec![1u8])]))
+            .try_into()
+            .unwrap();
+        assert_eq!(Some(&vec![1]), map.get(&1));
+
+        match <i32 as TryFromValue>::try_from(Value::Uint(1)) {
+            Err(AmqpError::AmqpUnexpectedType { expected, actual }) => {
+                assert_eq!(("int", "uint"), (expected, actual));
+            }
+            r => panic!("unexpected result {:?}", r),
+        }
+        assert!(<(u8, u8) as TryFromValue>::try_from(Value::List(vec![Value::Ubyte(1)])).is_err());
+    }
+}
@@ -0,0 +1,206 @@
+/*
+ * Copyright 2020, Ulf Lilleengen
+ * License: Apache License 2.0 (see the file LICENSE or http://apache.org/licenses/LICENSE-2.0.html).
+ */
+
+//! The decimal module contains the AMQP decimal types. The values are kept as the raw IEEE 754-2008
+//! bits in Binary Integer Decimal (BID) encoding, so they can be received and forwarded without loss.
+//! Conversion into [`rust_decimal::Decimal`] is available with the `decimal` feature.
+
+/// IEEE 754-2008 decimal32 using the Binary Integer Decimal encoding.
+#[derive(Copy, Clone, Debug, Ord, PartialOrd, Eq, PartialEq, Hash)]
+pub struct Decimal32(pub u32);
+
+/// IEEE 754-2008 decimal64 using the Binary Integer Decimal encoding.
+#[derive(Copy, Clone, Debug, Ord, PartialOrd, Eq, PartialEq, Hash)]
+pub struct Decimal64(pub u64);
+
+/// IEEE 754-2008 decimal128 using the Binary Integer Decimal encoding.
+#[derive(Copy, Clone, Debug, Ord, PartialOrd, Eq, PartialEq, Hash)]
+pub struct Decimal128(pub u128);
+
+#[cfg(feature = "decimal")]
+mod rust_decimal_support {
+    use super::*;
+    use crate::convert::TryFromValue;
+    use crate::error::*;
+    use crate::types::Value;
+    use rust_decimal::Decimal;
+
+    /// Bit layout of one of the BID interchange formats.
+    struct Format {
+        width: u32,
+        exponent_bits: u32,
+        bias: i32,
+        max_coefficient: u128,
+    }
+
+    const DECIMAL32: Format = Format {
+        width: 32,
+        exponent_bits: 8,
+        bias: 101,
+        max_coefficient: 9_999_999,
+    };
+
+    const DECIMAL64: Format = Format {
+        width: 64,
+        exponent_bits: 10,
+        bias: 398,
+        max_coefficient: 9_999_999_999_999_999,
+    };
+
+    const DECIMAL128: Format = Format {
+        width: 128,
+        exponent_bits: 14,
+        bias: 6176,
+        max_coefficient: 9_999_999_999_999_999_999_999_999_999_999,
+    };
+
+    const MAX_SCALE: u32 = 28;
+
+    fn mask(bits: u32) -> u128 {
+        (1u128 << bits) - 1
+    }
+
+    impl Format {
+        fn to_decimal(&self, bits: u128) -> Result<Decimal> {
+            let negative = (bits >> (self.width - 1)) & 1 == 1;
+            if (bits >> (self.width - 5)) & 0b1111 == 0b1111 {
+                return Err(AmqpError::decode_error(Some(
+                    "Decimal infinity or NaN cannot be converted to Decimal",
+                )));
+            }
+
+            let coefficient_bits = self.width - 1 - self.exponent_bits;
+            let (exponent, mut coefficient) = if (bits >> (self.width - 3)) & 0b11 == 0b11 {
+                let exponent = (bits >> (coefficient_bits - 2)) & mask(self.exponent_bits);
+                let coefficient =
+                    (0b100 << (coefficient_bits - 2)) | (bits & mask(coefficient_bits - 2));
+                (exponent, coefficient)
+            } else {
+                let exponent = (bits >> coefficient_bits) & mask(self.exponent_bits);
+                (exponent, bits & mask(coefficient_bits))
+            };
+
+            // Non-canonical coefficients are interpreted as zero
+            if coefficient > self.max_coefficient {
+                coefficient = 0;
+            }
+            if coefficient == 0 {
+                return Ok(Decimal::ZERO);
+            }
+
+            let exponent = exponent as i32 - self.bias;
+            let (mantissa, scale) = if exponent >= 0 {
+                let mantissa = 10u128
+                    .checked_pow(exponent as u32)
+                    .and_then(|factor| coefficient.checked_mul(factor));
+                (mantissa, 0)
+            } else {
+                let mut scale = exponent.unsigned_abs();
+                while scale > MAX_SCALE && coefficient % 10 == 0 {
+                    coefficient /= 10;
+                    scale -= 1;
+                }
+                (Some(coefficient), scale)
+            };
+
+            let mantissa = mantissa
+                .filter(|_| scale <= MAX_SCALE)
+                .and_then(|m| i128::try_from(m).ok())
+                .ok_or_else(|| {
+                    AmqpError::decode_error(Some("Decimal value is out of range for Decimal"))
+                })?;
+            let mantissa = if negative { -mantissa } else { mantissa };
+            Decimal::try_from_i128_with_scale(mantissa, scale).map_err(|e| {
+                AmqpError::decode_error(Some(format!("Error converting decimal: {}", e).as_str()))
+            })
+        }
+    }
+
+    impl TryFrom<Decimal32> for Decimal {
+        type Error = AmqpError;
+        fn try_from(value: Decimal32) -> Result<Self> {
+            DECIMAL32.to_decimal(value.0 as u128)
+        }
+    }
+
+    impl TryFrom<Decimal64> for Decimal {
+        type Error = AmqpError;
+        fn try_from(value: Decimal64) -> Result<Self> {
+            DECIMAL64.to_decimal(value.0 as u128)
+        }
+    }
+
+    impl TryFrom<Decimal128> for Decimal {
+        type Error = AmqpError;
+        fn try_from(value: Decimal128) -> Result<Self> {
+            DECIMAL128.to_decimal(value.0)
+        }
+    }
+
+    /// Every [`Decimal`] fits into a decimal128: the 96 bit mantissa is below the maximum
+    /// coefficient and the scale is within the exponent range.
+    impl From<Decimal> for Decimal128 {
+        fn from(value: Decimal) -> Self {
+            let sign = if value.is_sign_negative() { 1u128 } else { 0 };
+            let exponent = (DECIMAL128.bias - value.scale() as i32) as u128;
+            let coefficient = value.mantissa().unsigned_abs();
+            let coefficient_bits = DECIMAL128.width - 1 - DECIMAL128.exponent_bits;
+            Decimal128((sign << 127) | (exponent << coefficient_bits) | coefficient)
+        }
+    }
+
+    impl From<Decimal> for Value {
+        fn from(value: Decimal) -> Self {
+            Value::Decimal128(value.into())
+        }
+    }
+
+    impl TryFromValue for Decimal {
+        fn try_from(value: Value) -> Result<Self> {
+            match value {
+                Value::Decimal32(v) => v.try_into(),
+                Value::Decimal64(v) => v.try_into(),
+                Value::Decimal128(v) => v.try_into(),
+                _ => Err(AmqpError::decode_error(Some(
+                    "Error converting value to Decimal",
+                ))),
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "decimal"))]
+mod tests {
+
+    use super::*;
+    use rust_decimal::Decimal;
+
+    #[test]
+    fn check_decimal_conversion() {
+        // 1.23 = 123 * 10^-2, biased exponent 398 - 2
+        let d64 = Decimal64((396u64 << 53) | 123);
+        assert_eq!(Decimal::new(123, 2), Decimal::try_from(d64).unwrap());
+
+        // -7.5 = -75 * 10^-1, biased exponent 101 - 1
+        let d32 = Decimal32((1u32 << 31) | (100u32 << 23) | 75);
+        assert_eq!(Decimal::new(-75, 1), Decimal::try_from(d32).unwrap());
+
+        // Infinity
+        let inf = Decimal64(0x7800_0000_0000_0000);
+        assert!(Decimal::try_from(inf).is_err());
+
+        // 0E+50, biased exponent 398 + 50
+        let zero = Decimal64(448u64 << 53);
+        assert_eq!(Decimal::ZERO, Decimal::try_from(zero).unwrap());
+
+        // 0E-50, biased exponent 398 - 50
+        let zero = Decimal64(348u64 << 53);
+        assert_eq!(Decimal::ZERO, Decimal::try_from(zero).unwrap());
+
+        let value = Decimal::new(-1234567890123, 9);
+        let d128 = Decimal128::from(value);
+        assert_eq!(value, Decimal::try_from(d128).unwrap());
+    }
+}
@@ -9,29 +9,189 @@ use byteorder::NetworkEndian;
 use byteorder::ReadBytesExt;
 use std::io::Read;
 use std::vec::Vec;
+use uuid::Uuid;
 
+use crate::decimal::*;
 use crate::error::*;
 use crate::frame_codec::*;
+use crate::registry::{Described, DescriptorRegistry};
 use crate::types::*;
 
+/// Limits applied when decoding values, protecting against hostile or corrupt input that
+/// would otherwise cause huge allocations or unbounded recursion. Violations are reported
+/// as [`AmqpError::AmqpDecodeError`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecodeLimits {
+    /// Maximum nesting depth of described types, lists, maps and arrays.
+    pub max_depth: usize,
+    /// Maximum number of elements in a single list, map or array.
+    pub max_elements: usize,
+    /// Maximum size in bytes of a single string, symbol or binary value.
+    pub max_size: usize,
+    /// Maximum number of bytes allocated when decoding a single value.
+    pub max_allocation: usize,
+}
+
+impl DecodeLimits {
+    pub const fn new() -> DecodeLimits {
+        DecodeLimits {
+            max_depth: 64,
+            max_elements: 1024 * 1024,
+            max_size: 64 * 1024 * 1024,
+            max_allocation: 256 * 1024 * 1024,
+        }
+    }
+
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    pub fn max_elements(mut self, max_elements: usize) -> Self {
+        self.max_elements = max_elements;
+        self
+    }
+
+    pub fn max_size(mut self, max_size: usize) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    pub fn max_allocation(mut self, max_allocation: usize) -> Self {
+        self.max_allocation = max_allocation;
+        self
+    }
+}
+
+impl Default for DecodeLimits {
+    fn default() -> Self {
+        DecodeLimits::new()
+    }
+}
+
+/// Tracks the resources used while decoding a single value.
+struct DecodeState<'l> {
+    limits: &'l DecodeLimits,
+    depth: usize,
+    allocated: usize,
+}
+
+impl<'l> DecodeState<'l> {
+    fn new(limits: &'l DecodeLimits) -> Self {
+        DecodeState {
+            limits,
+            depth: 0,
+            allocated: 0,
+        }
+    }
+
+    fn enter(&mut self) -> Result<()> {
+        self.depth += 1;
+        if self.depth > self.limits.max_depth {
+            return Err(AmqpError::decode_error(Some(
+                format!("Nesting depth exceeds limit of {}", self.limits.max_depth).as_str(),
+            )));
+        }
+        Ok(())
+    }
+
+    fn leave(&mut self) {
+        self.depth -= 1;
+    }
+
+    /// Account for a compound value with the given number of elements.
+    fn elements(&mut self, count: usize) -> Result<()> {
+        if count > self.limits.max_elements {
+            return Err(AmqpError::decode_error(Some(
+                format!(
+                    "Element count {} exceeds limit of {}",
+                    count, self.limits.max_elements
+                )
+                .as_str(),
+            )));
+        }
+        self.allocate(count.saturating_mul(std::mem::size_of::<Value>()))
+    }
+
+    /// Account for a string, symbol or binary value of the given size.
+    fn sized(&mut self, len: usize) -> Result<()> {
+        if len > self.limits.max_size {
+            return Err(AmqpError::decode_error(Some(
+                format!("Size {} exceeds limit of {}", len, self.limits.max_size).as_str(),
+            )));
+        }
+        self.allocate(len)
+    }
+
+    fn allocate(&mut self, len: usize) -> Result<()> {
+        self.allocated = self.allocated.saturating_add(len);
+        if self.allocated > self.limits.max_allocation {
+            return Err(AmqpError::decode_error(Some(
+                format!(
+                    "Total allocation exceeds limit of {}",
+                    self.limits.max_allocation
+                )
+                .as_str(),
+            )));
+        }
+        Ok(())
+    }
+}
+
 /**
  * Decode an AMQP value from an byte reader. Reads the type constructor
  * first and passes this to the rest of the decoding function.
  */
 pub fn decode_value(reader: &mut dyn Read) -> Result<Value> {
+    decode_value_with_limits(reader, &DecodeLimits::default())
+}
+
+/**
+ * Decode an AMQP value from an byte reader, enforcing the given limits.
+ */
+pub fn decode_value_with_limits(reader: &mut dyn Read, limits: &DecodeLimits) -> Result<Value> {
+    decode_value_inner(reader, &mut DecodeState::new(limits))
+}
+
+/**
+ * Decode an AMQP value from an byte reader and convert described values using the decoders
+ * registered in the given registry.
+ */
+pub fn decode_described(reader: &mut dyn Read, registry: &DescriptorRegistry) -> Result<Described> {
+    decode_described_with_limits(reader, registry, &DecodeLimits::default())
+}
+
+/**
+ * Decode an AMQP value from an byte reader using the given registry, enforcing the given limits.
+ */
+pub fn decode_described_with_limits(
+    reader: &mut dyn Read,
+    registry: &DescriptorRegistry,
+    limits: &DecodeLimits,
+) -> Result<Described> {
+    registry.convert(decode_value_with_limits(reader, limits)?)
+}
+
+fn decode_value_inner(reader: &mut dyn Read, state: &mut DecodeState) -> Result<Value> {
     let raw_code: u8 = reader.read_u8()?;
-    decode_value_with_ctor(raw_code, reader)
+    decode_value_with_ctor(raw_code, reader, state)
 }
 
 /**
  * Decode an AMQP value from a byte reader based on the type constructor passed
  */
-fn decode_value_with_ctor(raw_code: u8, reader: &mut dyn Read) -> Result<Value> {
+fn decode_value_with_ctor(
+    raw_code: u8,
+    reader: &mut dyn Read,
+    state: &mut DecodeState,
+) -> Result<Value> {
     let code = decode_type(raw_code)?;
     match code {
         TypeCode::Described => {
-            let descriptor = decode_value(reader)?;
-            let value = decode_value(reader)?;
+            state.enter()?;
+            let descriptor = decode_value_inner(reader, state)?;
+            let value = decode_value_inner(reader, state)?;
+            state.leave();
             Ok(Value::Described(Box::new(descriptor), Box::new(value)))
         }
         TypeCode::Null => Ok(Value::Null),
@@ -91,8 +251,29 @@ fn decode_value_with_ctor(raw_code: u8, reader: &mut dyn Read) -> Result<Value>
             let val = reader.read_i8()? as i64;
             Ok(Value::Long(val))
         }
+        TypeCode::Float => {
+            let val = reader.read_f32::<NetworkEndian>()?;
+            Ok(Value::Float(Float(val)))
+        }
+        TypeCode::Double => {
+            let val = reader.read_f64::<NetworkEndian>()?;
+            Ok(Value::Double(Double(val)))
+        }
+        TypeCode::Decimal32 => {
+            let val = reader.read_u32::<NetworkEndian>()?;
+            Ok(Value::Decimal32(Decimal32(val)))
+        }
+        TypeCode::Decimal64 => {
+            let val = reader.read_u64::<NetworkEndian>()?;
+            Ok(Value::Decimal64(Decimal64(val)))
+        }
+        TypeCode::Decimal128 => {
+            let val = reader.read_u128::<NetworkEndian>()?;
+            Ok(Value::Decimal128(Decimal128(val)))
+        }
         TypeCode::Str8 => {
             let len = reader.read_u8()? as usize;
+            state.sized(len)?;
             let mut buffer = vec![0u8; len];
             reader.read_exact(&mut buffer)?;
             let s = String::from_utf8(buffer)?;
@@ -100,6 +281,7 @@ fn decode_value_with_ctor(raw_code: u8, reader: &mut dyn Read) -> Result<Value>
         }
         TypeCode::Str32 => {
             let len = reader.read_u32::<NetworkEndian>()? as usize;
+            state.sized(len)?;
             let mut buffer = vec![0u8; len];
             reader.read_exact(&mut buffer)?;
             let s = String::from_utf8(buffer)?;
@@ -107,24 +289,28 @@ fn decode_value_with_ctor(raw_code: u8, reader: &mut dyn Read) -> Result<Value>
         }
         TypeCode::Sym8 => {
             let len = reader.read_u8()? as usize;
+            state.sized(len)?;
             let mut buffer = vec![0u8; len];
             reader.read_exact(&mut buffer)?;
             Ok(Value::Symbol(buffer))
         }
         TypeCode::Sym32 => {
             let len = reader.read_u32::<NetworkEndian>()? as usize;
+            state.sized(len)?;
             let mut buffer = vec![0u8; len];
             reader.read_exact(&mut buffer)?;
             Ok(Value::Symbol(buffer))
         }
         TypeCode::Bin8 => {
             let len = reader.read_u8()? as usize;
+            state.sized(len)?;
             let mut buffer = vec![0u8; len];
             reader.read_exact(&mut buffer)?;
             Ok(Value::Binary(buffer))
         }
         TypeCode::Bin32 => {
             let len = reader.read_u32::<NetworkEndian>()? as usize;
+            state.sized(len)?;
             let mut buffer = vec![0u8; len];
             reader.read_exact(&mut buffer)?;
             Ok(Value::Binary(buffer))
@@ -133,65 +319,75 @@ fn decode_value_with_ctor(raw_code: u8, reader: &mut dyn Read) -> Result<Value>
         TypeCode::List8 => {
             let _sz = reader.read_u8()? as usize;
             let count = reader.read_u8()? as usize;
+            state.enter()?;
+            state.elements(count)?;
             let mut data: Vec<Value> = Vec::new();
             for _num in 0..count {
-                let result = decode_value(reader)?;
+                let result = decode_value_inner(reader, state)?;
                 data.push(result);
             }
+            state.leave();
             Ok(Value::List(data))
         }
         TypeCode::List32 => {
             let _sz = reader.read_u32::<NetworkEndian>()? as usize;
             let count = reader.read_u32::<NetworkEndian>()? as usize;
+            state.enter()?;
+            state.elements(count)?;
             let mut data: Vec<Value> = Vec::new();
             for _num in 0..count {
-                let result = decode_value(reader)?;
+                let result = decode_value_inner(reader, state)?;
                 data.push(result);
             }
+            state.leave();
             Ok(Value::List(data))
         }
         TypeCode::Array8 => {
             let _sz = reader.read_u8()? as usize;
             let count = reader.read_u8()? as usize;
             let ctype = reader.read_u8()?;
-            let mut data: Vec<Value> = Vec::new();
-            for _num in 0..count {
-                let result = decode_value_with_ctor(ctype, reader)?;
-                data.push(result);
-            }
+            state.enter()?;
+            state.elements(count)?;
+            let data = decode_array_elements(ctype, count, reader, state)?;
+            state.leave();
             Ok(Value::Array(data))
         }
         TypeCode::Array32 => {
             let _sz = reader.read_u32::<NetworkEndian>()? as usize;
             let count = reader.read_u32::<NetworkEndian>()? as usize;
             let ctype = reader.read_u8()?;
-            let mut data: Vec<Value> = Vec::new();
-            for _num in 0..count {
-                let result = decode_value_with_ctor(ctype, reader)?;
-                data.push(result);
-            }
+            state.enter()?;
+            state.elements(count)?;
+            let data = decode_array_elements(ctype, count, reader, state)?;
+            state.leave();
             Ok(Value::Array(data))
         }
         TypeCode::Map8 => {
             let _sz = reader.read_u8()? as usize;
             let count = reader.read_u8()? as usize / 2;
+            state.enter()?;
+            state.elements(count * 2)?;
             let mut data: Vec<(Value, Value)> = Vec::new();
             for _num in 0..count {
-                let key = decode_value(reader)?;
-                let value = decode_value(reader)?;
+                let key = decode_value_inner(reader, state)?;
+                let value = decode_value_inner(reader, state)?;
                 data.push((key, value));
             }
+            state.leave();
             Ok(Value::Map(data))
         }
         TypeCode::Map32 => {
             let _sz = reader.read_u32::<NetworkEndian>()? as usize;
             let count = reader.read_u32::<NetworkEndian>()? as usize / 2;
+            state.enter()?;
+            state.elements(count * 2)?;
             let mut data: Vec<(Value, Value)> = Vec::new();
             for _num in 0..count {
-                let key = decode_value(reader)?;
-                let value = decode_value(reader)?;
+                let key = decode_value_inner(reader, state)?;
+                let value = decode_value_inner(reader, state)?;
                 data.push((key, value));
             }
+            state.leave();
             Ok(Value::Map(data))
         }
         TypeCode::Char => {
@@ -204,6 +400,312 @@ fn decode_value_with_ctor(raw_code: u8, reader: &mut dyn Read) -> Result<Value>
             let val = reader.read_u64::<NetworkEndian>()?;
             Ok(Value::Timestamp(val))
         }
+        TypeCode::Uuid => {
+            let mut buffer = [0u8; 16];
+            reader.read_exact(&mut buffer)?;
+            Ok(Value::Uuid(Uuid::from_bytes(buffer)))
+        }
+    }
+}
+
+/**
+ * Decode the elements of an array sharing the element constructor. For arrays of described
+ * types, the descriptor is part of the constructor and applied to every element.
+ */
+fn decode_array_elements(
+    ctype: u8,
+    count: usize,
+    reader: &mut dyn Read,
+    state: &mut DecodeState,
+) -> Result<Vec<Value>> {
+    let mut data: Vec<Value> = Vec::new();
+    if ctype == TypeCode::Described as u8 {
+        state.enter()?;
+        let descriptor = decode_value_inner(reader, state)?;
+        let ctype = reader.read_u8()?;
+        let values = decode_array_elements(ctype, count, reader, state)?;
+        state.leave();
+        state.elements(count)?;
+        for value in values {
+            data.push(Value::Described(
+                Box::new(descriptor.clone()),
+                Box::new(value),
+            ));
+        }
+    } else {
+        for _num in 0..count {
+            data.push(decode_value_with_ctor(ctype, reader, state)?);
+        }
+    }
+    Ok(data)
+}
+
+/**
+ * Decode an AMQP value borrowed from a byte slice. Strings, symbols and binary values reference
+ * the input directly instead of being copied. The slice is advanced past the decoded value.
+ */
+pub fn decode_borrowed_value<'a>(input: &mut &'a [u8]) -> Result<BorrowedValue<'a>> {
+    decode_borrowed_value_with_limits(input, &DecodeLimits::default())
+}
+
+/**
+ * Decode an AMQP value borrowed from a byte slice, enforcing the given limits.
+ */
+pub fn decode_borrowed_value_with_limits<'a>(
+    input: &mut &'a [u8],
+    limits: &DecodeLimits,
+) -> Result<BorrowedValue<'a>> {
+    decode_borrowed_value_inner(input, &mut DecodeState::new(limits))
+}
+
+fn decode_borrowed_value_inner<'a>(
+    input: &mut &'a [u8],
+    state: &mut DecodeState,
+) -> Result<BorrowedValue<'a>> {
+    let raw_code: u8 = input.read_u8()?;
+    decode_borrowed_value_with_ctor(raw_code, input, state)
+}
+
+fn decode_borrowed_value_with_ctor<'a>(
+    raw_code: u8,
+    input: &mut &'a [u8],
+    state: &mut DecodeState,
+) -> Result<BorrowedValue<'a>> {
+    let code = decode_type(raw_code)?;
+    match code {
+        TypeCode::Described => {
+            state.enter()?;
+            let descriptor = decode_borrowed_value_inner(input, state)?;
+            let value = decode_borrowed_value_inner(input, state)?;
+            state.leave();
+            Ok(BorrowedValue::Described(
+                Box::new(descriptor),
+                Box::new(value),
+            ))
+        }
+        TypeCode::Str8 | TypeCode::Str32 => {
+            let data = take_sized(code == TypeCode::Str32, input)?;
+            Ok(BorrowedValue::String(std::str::from_utf8(data)?))
+        }
+        TypeCode::Sym8 | TypeCode::Sym32 => Ok(BorrowedValue::Symbol(take_sized(
+            code == TypeCode::Sym32,
+            input,
+        )?)),
+        TypeCode::Bin8 | TypeCode::Bin32 => Ok(BorrowedValue::Binary(take_sized(
+            code == TypeCode::Bin32,
+            input,
+        )?)),
+        TypeCode::List0 => Ok(BorrowedValue::List(Vec::new())),
+        TypeCode::List8 | TypeCode::List32 => {
+            let wide = code == TypeCode::List32;
+            let mut body = take_sized(wide, input)?;
+            let count = read_count(wide, &mut body)?;
+            state.enter()?;
+            state.elements(count)?;
+            let mut data = Vec::new();
+            for _num in 0..count {
+                data.push(decode_borrowed_value_inner(&mut body, state)?);
+            }
+            state.leave();
+            Ok(BorrowedValue::List(data))
+        }
+        TypeCode::Array8 | TypeCode::Array32 => {
+            let wide = code == TypeCode::Array32;
+            let mut body = take_sized(wide, input)?;
+            let count = read_count(wide, &mut body)?;
+            let ctype = body.read_u8()?;
+            state.enter()?;
+            state.elements(count)?;
+            let data = decode_array_elements_borrowed(ctype, count, &mut body, state)?;
+            state.leave();
+            Ok(BorrowedValue::Array(data))
+        }
+        TypeCode::Map8 | TypeCode::Map32 => {
+            let wide = code == TypeCode::Map32;
+            let mut body = take_sized(wide, input)?;
+            let count = read_count(wide, &mut body)? / 2;
+            state.enter()?;
+            state.elements(count * 2)?;
+            let mut data = Vec::new();
+            for _num in 0..count {
+                let key = decode_borrowed_value_inner(&mut body, state)?;
+                let value = decode_borrowed_value_inner(&mut body, state)?;
+                data.push((key, value));
+            }
+            state.leave();
+            Ok(BorrowedValue::Map(data))
+        }
+        // The remaining types are fixed width and do not allocate
+        _ => match decode_value_with_ctor(raw_code, input, state)? {
+            Value::Null => Ok(BorrowedValue::Null),
+            Value::Bool(v) => Ok(BorrowedValue::Bool(v)),
+            Value::Ubyte(v) => Ok(BorrowedValue::Ubyte(v)),
+            Value::Ushort(v) => Ok(BorrowedValue::Ushort(v)),
+            Value::Uint(v) => Ok(BorrowedValue::Uint(v)),
+            Value::Ulong(v) => Ok(BorrowedValue::Ulong(v)),
+            Value::Byte(v) => Ok(BorrowedValue::Byte(v)),
+            Value::Short(v) => Ok(BorrowedValue::Short(v)),
+            Value::Int(v) => Ok(BorrowedValue::Int(v)),
+            Value::Long(v) => Ok(BorrowedValue::Long(v)),
+            Value::Float(v) => Ok(BorrowedValue::Float(v)),
+            Value::Double(v) => Ok(BorrowedValue::Double(v)),
+            Value::Decimal32(v) => Ok(BorrowedValue::Decimal32(v)),
+            Value::Decimal64(v) => Ok(BorrowedValue::Decimal64(v)),
+            Value::Decimal128(v) => Ok(BorrowedValue::Decimal128(v)),
+            Value::Char(v) => Ok(BorrowedValue::Char(v)),
+            Value::Timestamp(v) => Ok(BorrowedValue::Timestamp(v)),
+            Value::Uuid(v) => Ok(BorrowedValue::Uuid(v)),
+            v => Err(AmqpError::decode_error(Some(
+                format!("Unexpected value {:?} for type code {:?}", v, code).as_str(),
+            ))),
+        },
+    }
+}
+
+fn decode_array_elements_borrowed<'a>(
+    ctype: u8,
+    count: usize,
+    input: &mut &'a [u8],
+    state: &mut DecodeState,
+) -> Result<Vec<BorrowedValue<'a>>> {
+    let mut data = Vec::new();
+    if ctype == TypeCode::Described as u8 {
+        state.enter()?;
+        let descriptor = decode_borrowed_value_inner(input, state)?;
+        let ctype = input.read_u8()?;
+        let values = decode_array_elements_borrowed(ctype, count, input, state)?;
+        state.leave();
+        state.elements(count)?;
+        for value in values {
+            data.push(BorrowedValue::Described(
+                Box::new(descriptor.clone()),
+                Box::new(value),
+            ));
+        }
+    } else {
+        for _num in 0..count {
+            data.push(decode_borrowed_value_with_ctor(ctype, input, state)?);
+        }
+    }
+    Ok(data)
+}
+
+/**
+ * Split the encoded AMQP value at the start of the input from the rest of the input without
+ * decoding it. The slice is advanced past the value and the raw bytes of the value are returned.
+ */
+pub fn split_value<'a>(input: &mut &'a [u8]) -> Result<&'a [u8]> {
+    split_value_with_limits(input, &DecodeLimits::default())
+}
+
+/**
+ * Split the encoded AMQP value at the start of the input, enforcing the given limits.
+ */
+pub fn split_value_with_limits<'a>(
+    input: &mut &'a [u8],
+    limits: &DecodeLimits,
+) -> Result<&'a [u8]> {
+    let start: &'a [u8] = input;
+    skip_value(input, &mut DecodeState::new(limits))?;
+    Ok(&start[..start.len() - input.len()])
+}
+
+/**
+ * Determine the size of the encoded AMQP value at the start of the input without decoding it.
+ * Returns `None` if the input ends before the value is complete.
+ */
+pub fn value_size(input: &[u8], limits: &DecodeLimits) -> Result<Option<usize>> {
+    let mut remaining = input;
+    match skip_value(&mut remaining, &mut DecodeState::new(limits)) {
+        Ok(()) => Ok(Some(input.len() - remaining.len())),
+        Err(AmqpError::IoError(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+fn skip_value(input: &mut &[u8], state: &mut DecodeState) -> Result<()> {
+    let code = decode_type(input.read_u8()?)?;
+    let width = match code {
+        TypeCode::Described => {
+            state.enter()?;
+            skip_value(input, state)?;
+            skip_value(input, state)?;
+            state.leave();
+            return Ok(());
+        }
+        TypeCode::Null
+        | TypeCode::BooleanTrue
+        | TypeCode::BooleanFalse
+        | TypeCode::Uint0
+        | TypeCode::Ulong0
+        | TypeCode::List0 => 0,
+        TypeCode::Boolean
+        | TypeCode::Ubyte
+        | TypeCode::Byte
+        | TypeCode::Uintsmall
+        | TypeCode::Ulongsmall
+        | TypeCode::Intsmall
+        | TypeCode::Longsmall => 1,
+        TypeCode::Ushort | TypeCode::Short => 2,
+        TypeCode::Uint | TypeCode::Int | TypeCode::Float | TypeCode::Decimal32 | TypeCode::Char => {
+            4
+        }
+        TypeCode::Ulong
+        | TypeCode::Long
+        | TypeCode::Double
+        | TypeCode::Decimal64
+        | TypeCode::Timestamp => 8,
+        TypeCode::Decimal128 | TypeCode::Uuid => 16,
+        TypeCode::Str8
+        | TypeCode::Sym8
+        | TypeCode::Bin8
+        | TypeCode::List8
+        | TypeCode::Map8
+        | TypeCode::Array8 => {
+            take_sized(false, input)?;
+            return Ok(());
+        }
+        TypeCode::Str32
+        | TypeCode::Sym32
+        | TypeCode::Bin32
+        | TypeCode::List32
+        | TypeCode::Map32
+        | TypeCode::Array32 => {
+            take_sized(true, input)?;
+            return Ok(());
+        }
+    };
+    take(input, width)?;
+    Ok(())
+}
+
+fn take<'a>(input: &mut &'a [u8], len: usize) -> Result<&'a [u8]> {
+    if input.len() < len {
+        return Err(AmqpError::IoError(std::io::Error::new(
+            std::io::ErrorKind::UnexpectedEof,
+            format!(
+                "Unexpected end of buffer: expected {} bytes, found {}",
+                len,
+                input.len()
+            ),
+        )));
+    }
+    let (head, tail) = input.split_at(len);
+    *input = tail;
+    Ok(head)
+}
+
+/// Reads an 8 or 32 bit size prefix and returns the slice of that size.
+fn take_sized<'a>(wide: bool, input: &mut &'a [u8]) -> Result<&'a [u8]> {
+    let len = read_count(wide, input)?;
+    take(input, len)
+}
+
+fn read_count(wide: bool, input: &mut &[u8]) -> Result<usize> {
+    if wide {
+        Ok(input.read_u32::<NetworkEndian>()? as usize)
+    } else {
+        Ok(input.read_u8()? as usize)
     }
 }
 
@@ -230,14 +732,14 @@ fn decode_type(code: u8) -> Result<TypeCode> {
         0x54 => Ok(TypeCode::Intsmall),
         0x81 => Ok(TypeCode::Long),
         0x55 => Ok(TypeCode::Longsmall),
-        // 0x72 => Ok(TypeCode::Float),
-        // 0x82 => Ok(TypeCode::Double),
-        // decimal32
-        // decimal64
-        // decimal128
+        0x72 => Ok(TypeCode::Float),
+        0x82 => Ok(TypeCode::Double),
+        0x74 => Ok(TypeCode::Decimal32),
+        0x84 => Ok(TypeCode::Decimal64),
+        0x94 => Ok(TypeCode::Decimal128),
         0x73 => Ok(TypeCode::Char),
         0x83 => Ok(TypeCode::Timestamp),
-        // uuid
+        0x98 => Ok(TypeCode::Uuid),
         0xA0 => Ok(TypeCode::Bin8),
         0xA1 => Ok(TypeCode::Str8),
         0xA3 => Ok(TypeCode::Sym8),
@@ -269,3 +771,49 @@ impl ErrorCondition {
         Ok(condition)
     }
 }
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn assert_limit_error(result: Result<Value>, expected: &str) {
+        match result {
+            Err(AmqpError::AmqpDecodeError(Some(description))) => {
+                assert!(description.contains(expected), "{}", description)
+            }
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+
+    #[test]
+    fn check_decode_limits() {
+        // Deeply nested described values
+        let nested = vec![TypeCode::Described as u8; 100_000];
+        assert_limit_error(decode_value(&mut &nested[..]), "Nesting depth");
+        assert!(split_value(&mut &nested[..]).is_err());
+        assert!(decode_borrowed_value(&mut &nested[..]).is_err());
+
+        // Array of u32::MAX nulls encoded in 10 bytes
+        let nulls = [0xF0, 0x00, 0x00, 0x00, 0x05, 0xFF, 0xFF, 0xFF, 0xFF, 0x40];
+        assert_limit_error(decode_value(&mut &nulls[..]), "Element count");
+        assert!(decode_borrowed_value(&mut &nulls[..]).is_err());
+
+        // Binary claiming a 4 GiB length
+        let binary = [0xB0, 0xFF, 0xFF, 0xFF, 0xFF];
+        assert_limit_error(decode_value(&mut &binary[..]), "Size");
+
+        let mut output = Vec::new();
+        Value::List(vec![Value::Binary(vec![0; 16]), Value::Binary(vec![0; 16])])
+            .encode(&mut output)
+            .unwrap();
+        let limits = DecodeLimits::new().max_allocation(2 * std::mem::size_of::<Value>() + 20);
+        assert_limit_error(
+            decode_value_with_limits(&mut &output[..], &limits),
+            "Total allocation",
+        );
+        let limits = DecodeLimits::new().max_size(15);
+        assert_limit_error(decode_value_with_limits(&mut &output[..], &limits), "Size");
+        assert!(decode_value(&mut &output[..]).is_ok());
+    }
+}
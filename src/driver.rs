@@ -11,10 +11,12 @@ use crate::error::*;
 use crate::framing;
 use crate::framing::{
     AmqpFrame, Attach, Begin, Close, DeliveryState, Detach, End, Flow, Frame, LinkRole, Open,
-    Performative, Source, Target, Transfer,
+    Performative, ReceiverSettleMode, SenderSettleMode, Source, Target, Transfer,
+    FRAME_HEADER_SIZE,
 };
 use crate::message::Message;
 use crate::options::LinkOptions;
+use crate::types::Encoder;
 use rand::Rng;
 use std::collections::hash_map::Entry;
 use std::collections::HashMap;
@@ -26,6 +28,10 @@ use uuid::Uuid;
 pub type DeliveryTag = Vec<u8>;
 pub type HandleId = u32;
 
+/// Incoming deliveries awaiting settlement by the remote sender, by delivery id. The channel
+/// is signalled once the delivery is settled.
+type RemoteSettlements = Arc<Mutex<HashMap<u32, (HandleId, async_channel::Sender<()>)>>>;
+
 #[derive(Debug)]
 pub struct ConnectionDriver {
     channel_max: u16,
@@ -55,6 +61,7 @@ pub struct SessionDriver {
 
     #[allow(clippy::type_complexity)]
     did_to_delivery: Arc<Mutex<HashMap<u32, (HandleId, Arc<DeliveryDriver>)>>>,
+    remote_settlements: RemoteSettlements,
     initial_outgoing_id: u32,
 
     flow_control: Arc<Mutex<SessionFlowControl>>,
@@ -65,6 +72,7 @@ pub struct SessionDriver {
 struct SessionFlowControl {
     next_outgoing_id: u32,
     next_incoming_id: u32,
+    next_delivery_id: u32,
 
     incoming_window: u32,
     outgoing_window: u32,
@@ -78,9 +86,10 @@ impl SessionFlowControl {
         SessionFlowControl {
             next_outgoing_id: 0,
             next_incoming_id: 0,
+            next_delivery_id: 0,
 
-            incoming_window: std::i32::MAX as u32,
-            outgoing_window: std::i32::MAX as u32,
+            incoming_window: i32::MAX as u32,
+            outgoing_window: i32::MAX as u32,
 
             remote_incoming_window: 0,
             remote_outgoing_window: 0,
@@ -111,6 +120,12 @@ impl SessionFlowControl {
             None
         }
     }
+
+    fn next_delivery_id(&mut self) -> u32 {
+        let delivery_id = self.next_delivery_id;
+        self.next_delivery_id = delivery_id.wrapping_add(1);
+        delivery_id
+    }
 }
 
 #[derive(Debug)]
@@ -126,8 +141,85 @@ pub struct LinkDriver {
 
     #[allow(clippy::type_complexity)]
     did_to_delivery: Arc<Mutex<HashMap<u32, (HandleId, Arc<DeliveryDriver>)>>>,
+    remote_settlements: RemoteSettlements,
     credit: AtomicU32,
     delivery_count: AtomicU32,
+    // Signalled when the remote receiver grants credit
+    credit_granted: (async_channel::Sender<()>, async_channel::Receiver<()>),
+    // Signalled when the remote session opens its incoming window
+    window_opened: (async_channel::Sender<()>, async_channel::Receiver<()>),
+
+    // Settle modes agreed with the remote peer in its attach
+    settle_modes: Mutex<(SenderSettleMode, ReceiverSettleMode)>,
+    max_message_size: Option<u64>,
+    // Delivery being received across multiple transfer frames
+    incoming: Mutex<Option<IncomingDelivery>>,
+}
+
+/// A delivery received across multiple transfer frames.
+#[derive(Debug)]
+struct IncomingDelivery {
+    transfer: Transfer,
+    payload: Vec<u8>,
+    // Whether flow control admitted the delivery, otherwise its frames are discarded
+    accepted: bool,
+}
+
+impl IncomingDelivery {
+    /// Add a transfer frame to the delivery in progress. The transfer and payload of the
+    /// complete delivery are returned once the last frame has been received.
+    fn assemble(
+        incoming: &mut Option<IncomingDelivery>,
+        transfer: Transfer,
+        payload: Option<Vec<u8>>,
+        accepted: bool,
+        max_message_size: Option<u64>,
+    ) -> Result<Option<(Transfer, Vec<u8>)>> {
+        let delivery = match incoming.take() {
+            Some(mut delivery) => {
+                // The settlement and state may be updated by any frame of the delivery
+                if transfer.settled == Some(true) {
+                    delivery.transfer.settled = Some(true);
+                }
+                if transfer.state.is_some() {
+                    delivery.transfer.state = transfer.state;
+                }
+                delivery.transfer.more = transfer.more;
+                delivery.transfer.aborted = transfer.aborted;
+                if let (true, Some(payload)) = (delivery.accepted, payload) {
+                    delivery.payload.extend_from_slice(&payload[..]);
+                }
+                delivery
+            }
+            None => IncomingDelivery {
+                transfer,
+                payload: payload.unwrap_or_default(),
+                accepted,
+            },
+        };
+
+        if delivery.transfer.aborted == Some(true) {
+            return Ok(None);
+        }
+
+        if let Some(max_message_size) = max_message_size.filter(|m| *m > 0) {
+            if delivery.payload.len() as u64 > max_message_size {
+                return Err(AmqpError::Amqp(ErrorCondition::message_size_exceeded(
+                    delivery.payload.len(),
+                    max_message_size,
+                )));
+            }
+        }
+
+        if delivery.transfer.more == Some(true) {
+            *incoming = Some(delivery);
+            Ok(None)
+        } else if delivery.accepted {
+            Ok(Some((delivery.transfer, delivery.payload)))
+        } else {
+            Ok(None)
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -215,6 +307,7 @@ impl ConnectionDriver {
             for (_id, link) in core::mem::take(&mut *session.links.lock().unwrap()) {
                 let _ = link.close(None);
                 link.rx.close();
+                link.window_opened.0.close();
             }
             let _ = session.close(None);
             session.rx.close();
@@ -306,6 +399,7 @@ impl ConnectionDriver {
                     initial_outgoing_id: 0,
 
                     did_to_delivery: Arc::new(Mutex::new(HashMap::new())),
+                    remote_settlements: Arc::new(Mutex::new(HashMap::new())),
                 });
                 entry.insert(session.clone());
                 return Some(session);
@@ -377,62 +471,80 @@ impl SessionDriver {
             }
             Some(Performative::Detach(ref detach)) => {
                 if let Some(link) = self.links.lock().unwrap().remove(&detach.handle) {
+                    // Deliveries of the link can no longer be settled by the remote sender
+                    self.remote_settlements
+                        .lock()
+                        .unwrap()
+                        .retain(|_, (handle, _)| *handle != link.handle);
                     link.rx.send(frame)?;
                 } else {
                     warn!("Detach request with unknown handle received: {:?}", detach)
                 }
             }
             Some(Performative::Transfer(ref transfer)) => {
-                // Session flow control
-                if let Some(delivery_id) = transfer.delivery_id {
-                    loop {
-                        let result = self.flow_control.lock().unwrap().accept(delivery_id);
-                        match result {
-                            Err(AmqpError::Amqp(cond)) => {
-                                error!("Transfer error: {:?}", cond);
-                                self.close(Some(cond))?;
-                            }
-                            Err(e) => {
-                                error!("Transfer error: {:?}", e);
-                                self.close(None)?;
-                            }
-                            Ok(false) => {}
-                            Ok(true) => break,
-                        }
-                    }
-                }
-
                 let link = {
                     let mut m = self.links.lock().unwrap();
                     m.get_mut(&transfer.handle).unwrap().clone()
                 };
 
-                let count_down = |x| {
-                    if x == 0 {
-                        Some(0)
-                    } else {
-                        Some(x - 1)
+                // Flow control applies to the first frame of a delivery only
+                let reserved = link.reserves_window(transfer);
+                let accepted = link.receiving() || self.accept_transfer(&link, transfer)?;
+
+                let aborted = transfer.aborted == Some(true);
+                match link.assemble(transfer.clone(), frame.payload, accepted) {
+                    Ok(Some((transfer, payload))) => {
+                        link.rx.send(AmqpFrame {
+                            channel: frame.channel,
+                            performative: Some(Performative::Transfer(transfer)),
+                            payload: Some(payload),
+                        })?;
                     }
-                };
-                // Link flow control
-                if link
-                    .credit
-                    .fetch_update(Ordering::SeqCst, Ordering::SeqCst, count_down)
-                    == Ok(0)
-                {
-                    trace!("Transfer but no space left!");
-                } else {
-                    trace!(
-                        "Received transfer. Credit: {:?}",
-                        link.credit.load(Ordering::SeqCst)
-                    );
-                    link.delivery_count.fetch_add(1, Ordering::SeqCst);
-                    link.rx.send(frame)?;
+                    Ok(None) => {
+                        if aborted {
+                            trace!("Transfer aborted on link {}", link.handle);
+                            // Aborted deliveries are never settled by a disposition
+                            if reserved {
+                                self.flow_control.lock().unwrap().incoming_window += 1;
+                            }
+                        }
+                    }
+                    Err(AmqpError::Amqp(cond)) => {
+                        error!("Transfer error: {:?}", cond);
+                        link.close(Some(cond.clone()))?;
+                        // Notify the receiver of the link error
+                        link.rx.send(AmqpFrame {
+                            channel: frame.channel,
+                            performative: Some(Performative::Detach(Detach {
+                                handle: link.handle,
+                                closed: Some(true),
+                                error: Some(cond),
+                            })),
+                            payload: None,
+                        })?;
+                    }
+                    Err(e) => return Err(e),
                 }
             }
             Some(Performative::Disposition(ref disposition)) => {
                 trace!("Received disposition: {:?}", disposition);
                 let last = disposition.last.unwrap_or(disposition.first);
+                // Decoded roles are inverted (see `TryFromValue for LinkRole`): a disposition
+                // sent by the remote sender decodes with the receiver role, and one sent by
+                // the remote receiver with the sender role of our sending links.
+                if disposition.role == LinkRole::Receiver {
+                    // Settlement of incoming deliveries by the remote sender
+                    if disposition.settled == Some(true) {
+                        for id in disposition.first..=last {
+                            let settlement = self.remote_settlements.lock().unwrap().remove(&id);
+                            if let Some((_, settled)) = settlement {
+                                self.flow_control.lock().unwrap().incoming_window += 1;
+                                let _ = settled.try_send(());
+                            }
+                        }
+                    }
+                    return Ok(());
+                }
                 for id in disposition.first..=last {
                     if let Some((handle, _delivery)) =
                         self.did_to_delivery.lock().unwrap().remove(&id)
@@ -462,6 +574,11 @@ impl SessionDriver {
                             + flow.incoming_window
                             - control.next_outgoing_id;
                     }
+                    if control.remote_incoming_window > 0 {
+                        for link in self.links.lock().unwrap().values() {
+                            let _ = link.window_opened.0.try_send(());
+                        }
+                    }
                 }
                 if let Some(handle) = flow.handle {
                     let link = {
@@ -472,6 +589,9 @@ impl SessionDriver {
                         let credit = flow.delivery_count.unwrap_or(0) + credit
                             - link.delivery_count.load(Ordering::SeqCst);
                         link.credit.store(credit, Ordering::SeqCst);
+                        if credit > 0 {
+                            let _ = link.credit_granted.0.try_send(());
+                        }
                     }
                 }
             }
@@ -482,6 +602,53 @@ impl SessionDriver {
         Ok(())
     }
 
+    /// Apply session and link flow control to a new incoming delivery, returning whether it
+    /// was accepted.
+    fn accept_transfer(&self, link: &LinkDriver, transfer: &Transfer) -> Result<bool> {
+        // Session flow control
+        if let Some(delivery_id) = transfer.delivery_id {
+            loop {
+                let result = self.flow_control.lock().unwrap().accept(delivery_id);
+                match result {
+                    Err(AmqpError::Amqp(cond)) => {
+                        error!("Transfer error: {:?}", cond);
+                        self.close(Some(cond))?;
+                    }
+                    Err(e) => {
+                        error!("Transfer error: {:?}", e);
+                        self.close(None)?;
+                    }
+                    Ok(false) => {}
+                    Ok(true) => break,
+                }
+            }
+        }
+
+        let count_down = |x| {
+            if x == 0 {
+                Some(0)
+            } else {
+                Some(x - 1)
+            }
+        };
+        // Link flow control
+        if link
+            .credit
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, count_down)
+            == Ok(0)
+        {
+            trace!("Transfer but no space left!");
+            Ok(false)
+        } else {
+            trace!(
+                "Received transfer. Credit: {:?}",
+                link.credit.load(Ordering::SeqCst)
+            );
+            link.delivery_count.fetch_add(1, Ordering::SeqCst);
+            Ok(true)
+        }
+    }
+
     pub fn close(&self, error: Option<ErrorCondition>) -> Result<()> {
         self.connection.end(self.local_channel, End { error })
     }
@@ -525,6 +692,7 @@ impl SessionDriver {
                 dynamic_node_properties: None,
                 capabilities: None,
             }),
+            coordinator: None,
             unsettled: None,
             incomplete_unsettled: None,
             initial_delivery_count: if role == LinkRole::Sender {
@@ -548,8 +716,17 @@ impl SessionDriver {
             rx: Channel::new(),
             session_flow_control: self.flow_control.clone(),
             did_to_delivery: self.did_to_delivery.clone(),
+            remote_settlements: self.remote_settlements.clone(),
             credit: AtomicU32::new(0),
             delivery_count: AtomicU32::new(0),
+            credit_granted: async_channel::bounded(1),
+            window_opened: async_channel::bounded(1),
+            settle_modes: Mutex::new((
+                attach.snd_settle_mode.unwrap_or(SenderSettleMode::Mixed),
+                attach.rcv_settle_mode.unwrap_or(ReceiverSettleMode::First),
+            )),
+            max_message_size: attach.max_message_size,
+            incoming: Mutex::new(None),
         });
 
         self.links_in_flight
@@ -567,10 +744,29 @@ impl SessionDriver {
                 response.handle
             );
 
+            // the remote peer decides on the settle modes of the link
+            *link.settle_modes.lock().unwrap() = (
+                response.snd_settle_mode.unwrap_or(SenderSettleMode::Mixed),
+                response
+                    .rcv_settle_mode
+                    .unwrap_or(ReceiverSettleMode::First),
+            );
+
             // if it is not dynamic, we need to check whether the attach was successful
             let requested_address = address;
             let dynamic = matches!(options.dynamic(), Some(true));
 
+            // a controller link is refused by peers without a transaction coordinator
+            if let LinkOptions::Coordinator = options {
+                return if response.coordinator.is_some() {
+                    Ok((requested_address.to_string(), link))
+                } else {
+                    warn!("Transaction coordinator not available on {}", link_name);
+                    link.close(None)?;
+                    Err(AmqpError::not_implemented())
+                };
+            }
+
             let address_response = match response.role {
                 LinkRole::Sender => response.target.and_then(|t| t.address),
                 LinkRole::Receiver => response.source.and_then(|s| s.address),
@@ -654,11 +850,130 @@ impl LinkDriver {
         self.credit.load(Ordering::SeqCst)
     }
 
+    /// The sender settle mode of the link, as attached by the remote peer.
+    pub fn snd_settle_mode(&self) -> SenderSettleMode {
+        self.settle_modes.lock().unwrap().0
+    }
+
+    /// The receiver settle mode of the link, as attached by the remote peer.
+    pub fn rcv_settle_mode(&self) -> ReceiverSettleMode {
+        self.settle_modes.lock().unwrap().1
+    }
+
+    /// Wait until the remote receiver has granted credit.
+    pub async fn wait_for_credit(&self) -> Result<()> {
+        while self.credits() == 0 {
+            self.credit_granted.1.recv().await?;
+        }
+        Ok(())
+    }
+
+    /// Whether a delivery is partially received.
+    fn receiving(&self) -> bool {
+        self.incoming.lock().unwrap().is_some()
+    }
+
+    /// Whether the delivery the transfer belongs to holds a slot of the session incoming
+    /// window. Slots are reserved by the first frame of a delivery, which carries its id.
+    fn reserves_window(&self, transfer: &Transfer) -> bool {
+        match &*self.incoming.lock().unwrap() {
+            Some(delivery) => delivery.transfer.delivery_id.is_some(),
+            None => transfer.delivery_id.is_some(),
+        }
+    }
+
+    fn assemble(
+        &self,
+        transfer: Transfer,
+        payload: Option<Vec<u8>>,
+        accepted: bool,
+    ) -> Result<Option<(Transfer, Vec<u8>)>> {
+        IncomingDelivery::assemble(
+            &mut self.incoming.lock().unwrap(),
+            transfer,
+            payload,
+            accepted,
+            self.max_message_size,
+        )
+    }
+
     pub async fn send_message(
         &self,
         message: Message,
         settled: bool,
+        state: Option<DeliveryState>,
     ) -> Result<Arc<DeliveryDriver>> {
+        if !self.acquire_credit() {
+            return Err(AmqpError::NotEnoughCreditsToSend(Box::new(message)));
+        }
+
+        let mut msgbuf = Vec::new();
+        message.encode(&mut msgbuf)?;
+
+        let (delivery, mut transfer) = self.new_delivery(Some(message), settled).await?;
+        transfer.state = state;
+        self.send_payload(transfer, msgbuf, false).await?;
+
+        Ok(delivery)
+    }
+
+    /// Start a delivery with a payload sent in multiple calls to [`LinkDriver::send_chunk`].
+    /// The returned transfer is passed with the first chunk.
+    pub async fn start_delivery(&self, settled: bool) -> Result<(Arc<DeliveryDriver>, Transfer)> {
+        if !self.acquire_credit() {
+            return Err(AmqpError::NotEnoughCredits);
+        }
+        self.new_delivery(None, settled).await
+    }
+
+    /// Send a chunk of the payload of a delivery started with [`LinkDriver::start_delivery`].
+    /// The transfer returned when starting the delivery is passed with the first chunk only.
+    pub async fn send_chunk(
+        &self,
+        transfer: Option<Transfer>,
+        payload: Vec<u8>,
+        more: bool,
+    ) -> Result<()> {
+        let transfer = match transfer {
+            Some(transfer) => transfer,
+            None => self.continuation().await?,
+        };
+        self.send_payload(transfer, payload, more).await
+    }
+
+    /// Abort a delivery started with [`LinkDriver::start_delivery`], passing the transfer
+    /// if no chunk has been sent. The aborted delivery is settled, but still consumes credit.
+    pub async fn abort(&self, delivery: &DeliveryDriver, transfer: Option<Transfer>) -> Result<()> {
+        let transfer = match transfer {
+            Some(transfer) => transfer,
+            None => self.continuation().await?,
+        };
+        self.send_abort(delivery, transfer)
+    }
+
+    /// Abort a delivery like [`LinkDriver::abort`] without waiting for the session window.
+    /// Returns false if the window is closed and no abort was sent.
+    pub fn try_abort(&self, delivery: &DeliveryDriver, transfer: Option<Transfer>) -> Result<bool> {
+        let transfer = match transfer {
+            Some(transfer) => transfer,
+            None if self.session_flow_control.lock().unwrap().next().is_some() => {
+                Transfer::new(self.handle)
+            }
+            None => return Ok(false),
+        };
+        self.send_abort(delivery, transfer)?;
+        Ok(true)
+    }
+
+    fn send_abort(&self, delivery: &DeliveryDriver, mut transfer: Transfer) -> Result<()> {
+        transfer.more = Some(false);
+        transfer.aborted = Some(true);
+        self.did_to_delivery.lock().unwrap().remove(&delivery.id);
+        self.connection.transfer(self.channel, transfer, None)
+    }
+
+    /// Consume a link credit, returning false if none is available.
+    fn acquire_credit(&self) -> bool {
         let semaphore_fn = |x| {
             if x == 0 {
                 Some(0)
@@ -668,29 +983,27 @@ impl LinkDriver {
         };
 
         // Link flow control
-        if self
-            .credit
+        self.credit
             .fetch_update(Ordering::SeqCst, Ordering::SeqCst, semaphore_fn)
-            == Ok(0)
-        {
-            return Err(AmqpError::NotEnoughCreditsToSend(Box::new(message)));
-        }
+            != Ok(0)
+    }
 
+    /// Allocate a delivery and the transfer of its first frame, which is reserved in the
+    /// session window.
+    async fn new_delivery(
+        &self,
+        message: Option<Message>,
+        settled: bool,
+    ) -> Result<(Arc<DeliveryDriver>, Transfer)> {
         // Session flow control
-        let next_outgoing_id = loop {
-            let props = self.session_flow_control.lock().unwrap().next();
-            if let Some(props) = props {
-                break props.next_outgoing_id;
-            }
-            warn!("No next_outgoing_id, busy looping");
-            // std::thread::sleep(Duration::from_millis(500));
-        };
+        self.reserve_transfer().await?;
+        let delivery_id = self.session_flow_control.lock().unwrap().next_delivery_id();
 
         self.delivery_count.fetch_add(1, Ordering::SeqCst);
         let delivery_tag = rand::thread_rng().gen::<[u8; 16]>().to_vec();
         let delivery = Arc::new(DeliveryDriver {
-            message: Some(message),
-            id: next_outgoing_id,
+            message,
+            id: delivery_id,
             tag: delivery_tag.clone(),
             state: None,
             remotely_settled: false,
@@ -701,12 +1014,12 @@ impl LinkDriver {
             self.did_to_delivery
                 .lock()
                 .unwrap()
-                .insert(next_outgoing_id, (self.handle, delivery.clone()));
+                .insert(delivery_id, (self.handle, delivery.clone()));
         }
 
         let transfer = Transfer {
             handle: self.handle,
-            delivery_id: Some(next_outgoing_id),
+            delivery_id: Some(delivery_id),
             delivery_tag: Some(delivery_tag),
             message_format: Some(0),
             settled: Some(settled),
@@ -717,16 +1030,70 @@ impl LinkDriver {
             aborted: None,
             batchable: None,
         };
+        Ok((delivery, transfer))
+    }
 
-        let mut msgbuf = Vec::new();
-        if let Some(message) = delivery.message.as_ref() {
-            message.encode(&mut msgbuf)?;
+    /// The transfer of a continuation frame, which is reserved in the session window.
+    async fn continuation(&self) -> Result<Transfer> {
+        self.reserve_transfer().await?;
+        let mut transfer = Transfer::new(self.handle);
+        transfer.more = Some(false);
+        Ok(transfer)
+    }
+
+    /// Reserve a transfer frame in the session outgoing window, waiting for the remote
+    /// session to open the window with a flow.
+    async fn reserve_transfer(&self) -> Result<()> {
+        while self.session_flow_control.lock().unwrap().next().is_none() {
+            trace!("({}) waiting for session window", self.handle);
+            self.window_opened.1.recv().await?;
         }
+        Ok(())
+    }
 
-        self.connection
-            .transfer(self.channel, transfer, Some(msgbuf))?;
+    /// Send the payload of a delivery, split into transfer frames that fit within the largest
+    /// frame accepted by the remote peer. The first frame carries the given transfer, which
+    /// must already be reserved in the session window. The last frame indicates whether more
+    /// payload follows.
+    async fn send_payload(
+        &self,
+        mut transfer: Transfer,
+        payload: Vec<u8>,
+        more: bool,
+    ) -> Result<()> {
+        let max_payload_size = self.max_payload_size(&transfer)?;
+        if payload.len() <= max_payload_size {
+            transfer.more = Some(more);
+            return self
+                .connection
+                .transfer(self.channel, transfer, Some(payload));
+        }
 
-        Ok(delivery)
+        let mut chunks = payload.chunks(max_payload_size).peekable();
+        while let Some(chunk) = chunks.next() {
+            let last = chunks.peek().is_none();
+            transfer.more = Some(more || !last);
+            self.connection
+                .transfer(self.channel, transfer, Some(chunk.to_vec()))?;
+            if last {
+                break;
+            }
+            transfer = self.continuation().await?;
+        }
+        Ok(())
+    }
+
+    /// The largest payload that fits in a frame with the given transfer performative.
+    fn max_payload_size(&self, transfer: &Transfer) -> Result<usize> {
+        let mut performative = Vec::new();
+        transfer.encode(&mut performative)?;
+        self.connection
+            .max_frame_size()
+            .checked_sub(FRAME_HEADER_SIZE + performative.len())
+            .filter(|size| *size > 0)
+            .ok_or_else(|| {
+                AmqpError::framing_error(Some("Maximum frame size is too small for transfer"))
+            })
     }
 
     pub fn flow(&self, credit: u32) -> Result<()> {
@@ -780,7 +1147,7 @@ impl LinkDriver {
         settled: bool,
         state: DeliveryState,
     ) -> Result<()> {
-        if settled {
+        if settled && self.role == LinkRole::Receiver {
             self.session_flow_control.lock().unwrap().incoming_window += 1;
         }
         self.connection().disposition(
@@ -795,6 +1162,23 @@ impl LinkDriver {
             },
         )
     }
+
+    /// Send the outcome of a delivery received in the second receiver settle mode, leaving
+    /// it unsettled. The returned channel is signalled once the remote sender has settled
+    /// the delivery, and closed if the link is detached before.
+    pub fn send_outcome(
+        &self,
+        delivery: &DeliveryDriver,
+        state: DeliveryState,
+    ) -> Result<async_channel::Receiver<()>> {
+        let (settled, settlement) = async_channel::bounded(1);
+        self.remote_settlements
+            .lock()
+            .unwrap()
+            .insert(delivery.id, (self.handle, settled));
+        self.disposition(delivery, false, state)?;
+        Ok(settlement)
+    }
 }
 
 #[derive(Debug)]
@@ -845,7 +1229,292 @@ impl<T> Channel<T> {
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
+
+    use super::*;
+    use crate::connection::ConnectionWaker;
+    use crate::transport::TransportInfo;
+    use crate::types::Value;
+    use futures::executor::block_on;
+
+    #[derive(Debug)]
+    struct NoopWaker;
+
+    impl ConnectionWaker for NoopWaker {
+        fn wake(&self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    /// A session whose outgoing frames are collected in the returned channel.
+    pub(crate) fn new_session() -> (Arc<SessionDriver>, Channel<Frame>) {
+        let frames = Channel::new();
+        let connection: ConnectionHandle =
+            frames.handle_with((Arc::new(TransportInfo::default()), Arc::new(NoopWaker)));
+        let session = Arc::new(SessionDriver {
+            connection,
+            local_channel: 0,
+            rx: Channel::new(),
+            links_in_flight: Mutex::new(HashMap::new()),
+            links: Mutex::new(HashMap::new()),
+            handle_generator: AtomicU32::new(0),
+            did_to_delivery: Arc::new(Mutex::new(HashMap::new())),
+            remote_settlements: Arc::new(Mutex::new(HashMap::new())),
+            initial_outgoing_id: 0,
+            flow_control: Arc::new(Mutex::new(SessionFlowControl::new())),
+        });
+        {
+            let mut control = session.flow_control.lock().unwrap();
+            control.remote_incoming_window = 10;
+            control.remote_outgoing_window = 10;
+        }
+        (session, frames)
+    }
+
+    pub(crate) fn set_remote_incoming_window(session: &SessionDriver, window: u32) {
+        session.flow_control.lock().unwrap().remote_incoming_window = window;
+    }
+
+    /// Attach a link, answering the attach with the response returned by the given function.
+    pub(crate) fn attach(
+        session: &SessionDriver,
+        frames: &Channel<Frame>,
+        options: impl Into<LinkOptions>,
+        respond: impl FnOnce(Attach) -> Attach,
+    ) -> Arc<LinkDriver> {
+        let (link, _) = block_on(async {
+            futures::join!(session.new_link("queue1", options), async {
+                let attach = match frames.recv().await.unwrap() {
+                    Frame::AMQP(AmqpFrame {
+                        performative: Some(Performative::Attach(attach)),
+                        ..
+                    }) => attach,
+                    frame => panic!("unexpected frame {:?}", frame),
+                };
+                // The decoded role of the response equals the local role
+                session
+                    .dispatch(AmqpFrame {
+                        channel: 0,
+                        performative: Some(Performative::Attach(respond(attach))),
+                        payload: None,
+                    })
+                    .unwrap();
+            })
+        });
+        link.unwrap().1
+    }
+
+    pub(crate) fn dispatch(
+        session: &SessionDriver,
+        performative: Performative,
+        payload: Option<Vec<u8>>,
+    ) {
+        session
+            .dispatch(AmqpFrame {
+                channel: 0,
+                performative: Some(performative),
+                payload,
+            })
+            .unwrap();
+    }
+
     #[test]
     fn check_handle_map() {}
+
+    #[test]
+    fn check_aborted_transfer_window() {
+        let (session, frames) = new_session();
+        let link = attach(&session, &frames, LinkRole::Receiver, |a| a);
+        link.flow(10).unwrap();
+        let window = || session.flow_control.lock().unwrap().incoming_window;
+        let initial = window();
+
+        let mut first = Transfer::new(link.handle);
+        first.delivery_id = Some(0);
+        first.delivery_tag = Some(vec![0]);
+        first.more = Some(true);
+        dispatch(&session, Performative::Transfer(first), Some(vec![1]));
+        assert_eq!(initial - 1, window());
+
+        // Aborting the delivery releases its slot of the window
+        let mut aborted = Transfer::new(link.handle);
+        aborted.aborted = Some(true);
+        dispatch(&session, Performative::Transfer(aborted.clone()), None);
+        assert_eq!(initial, window());
+
+        // An aborted frame of no delivery in progress reserved nothing
+        dispatch(&session, Performative::Transfer(aborted), None);
+        assert_eq!(initial, window());
+    }
+
+    pub(crate) fn flow(handle: u32, link_credit: u32, incoming_window: u32) -> Performative {
+        Performative::Flow(Flow {
+            next_incoming_id: Some(0),
+            incoming_window,
+            next_outgoing_id: 0,
+            outgoing_window: 10,
+            handle: Some(handle),
+            delivery_count: Some(0),
+            link_credit: Some(link_credit),
+            available: None,
+            drain: None,
+            echo: None,
+            properties: None,
+        })
+    }
+
+    #[test]
+    fn check_session_window_wait() {
+        let (session, frames) = new_session();
+        let link = attach(&session, &frames, LinkRole::Sender, |a| a);
+        dispatch(&session, flow(link.handle, 1, 0), None);
+
+        let (delivery, _) = block_on(async {
+            futures::join!(
+                link.send_message(Message::amqp_value(Value::Int(1)), true, None),
+                async {
+                    // The transfer waits for the remote session to open its window
+                    assert!(frames.try_recv().is_err());
+                    dispatch(&session, flow(link.handle, 1, 1), None);
+                }
+            )
+        });
+        assert_eq!(0, delivery.unwrap().id);
+        match frames.try_recv().unwrap() {
+            Frame::AMQP(AmqpFrame {
+                performative: Some(Performative::Transfer(transfer)),
+                ..
+            }) => assert_eq!(Some(0), transfer.delivery_id),
+            frame => panic!("unexpected frame {:?}", frame),
+        }
+    }
+
+    #[test]
+    fn check_second_settle_mode() {
+        let (session, frames) = new_session();
+        let link = attach(&session, &frames, LinkRole::Receiver, |mut a| {
+            a.rcv_settle_mode = Some(ReceiverSettleMode::Second);
+            a
+        });
+        assert_eq!(ReceiverSettleMode::Second, link.rcv_settle_mode());
+        link.flow(10).unwrap();
+        let window = || session.flow_control.lock().unwrap().incoming_window;
+
+        let mut transfer = Transfer::new(link.handle);
+        transfer.delivery_id = Some(0);
+        transfer.delivery_tag = Some(vec![0]);
+        dispatch(&session, Performative::Transfer(transfer), Some(vec![1]));
+        let initial = window();
+
+        let delivery = DeliveryDriver {
+            message: None,
+            id: 0,
+            tag: vec![0],
+            state: None,
+            remotely_settled: false,
+            settled: false,
+        };
+        let settlement = link
+            .send_outcome(&delivery, DeliveryState::Accepted)
+            .unwrap();
+        let disposition = loop {
+            if let Frame::AMQP(AmqpFrame {
+                performative: Some(Performative::Disposition(disposition)),
+                ..
+            }) = frames.try_recv().unwrap()
+            {
+                break disposition;
+            }
+        };
+        // The outcome leaves the delivery unsettled until the sender settles it
+        assert_eq!(Some(false), disposition.settled);
+        assert_eq!(Some(DeliveryState::Accepted), disposition.state);
+        assert!(settlement.try_recv().is_err());
+        assert_eq!(initial, window());
+
+        // The decoded role of a disposition from the sender is the receiver role
+        let settled = framing::Disposition {
+            role: LinkRole::Receiver,
+            first: 0,
+            last: None,
+            settled: Some(true),
+            state: Some(DeliveryState::Accepted),
+            batchable: None,
+        };
+        dispatch(&session, Performative::Disposition(settled), None);
+        assert!(settlement.try_recv().is_ok());
+        assert_eq!(initial + 1, window());
+    }
+
+    #[test]
+    fn check_incoming_delivery() {
+        let transfer = |delivery_id: Option<u32>, more: bool| {
+            let mut transfer = Transfer::new(0);
+            transfer.delivery_id = delivery_id;
+            transfer.delivery_tag = delivery_id.map(|id| vec![id as u8]);
+            transfer.more = Some(more);
+            transfer
+        };
+
+        let mut incoming = None;
+        let result = IncomingDelivery::assemble(
+            &mut incoming,
+            transfer(Some(1), true),
+            Some(vec![1, 2]),
+            true,
+            None,
+        )
+        .unwrap();
+        assert!(result.is_none());
+        let mut last = transfer(None, false);
+        last.settled = Some(true);
+        let (transfer_1, payload) =
+            IncomingDelivery::assemble(&mut incoming, last, Some(vec![3]), true, None)
+                .unwrap()
+                .unwrap();
+        assert_eq!(Some(1), transfer_1.delivery_id);
+        assert_eq!(Some(true), transfer_1.settled);
+        assert_eq!(vec![1, 2, 3], payload);
+        assert!(incoming.is_none());
+
+        // Aborted deliveries are discarded
+        IncomingDelivery::assemble(
+            &mut incoming,
+            transfer(Some(2), true),
+            Some(vec![1]),
+            true,
+            None,
+        )
+        .unwrap();
+        let mut aborted = transfer(None, false);
+        aborted.aborted = Some(true);
+        assert!(
+            IncomingDelivery::assemble(&mut incoming, aborted, None, true, None)
+                .unwrap()
+                .is_none()
+        );
+        assert!(incoming.is_none());
+
+        // Deliveries exceeding the maximum message size are rejected
+        IncomingDelivery::assemble(
+            &mut incoming,
+            transfer(Some(3), true),
+            Some(vec![1; 3]),
+            true,
+            Some(4),
+        )
+        .unwrap();
+        match IncomingDelivery::assemble(
+            &mut incoming,
+            transfer(None, true),
+            Some(vec![1; 2]),
+            true,
+            Some(4),
+        ) {
+            Err(AmqpError::Amqp(cond)) => {
+                assert_eq!("amqp:link:message-size-exceeded", cond.condition)
+            }
+            r => panic!("unexpected result {:?}", r),
+        }
+    }
 }
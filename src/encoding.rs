@@ -10,350 +10,592 @@ use byteorder::WriteBytesExt;
 use std::collections::BTreeMap;
 use std::io::Write;
 use std::vec::Vec;
+use uuid::Uuid;
 
+use crate::decimal::*;
 use crate::error::*;
 use crate::frame_codec::*;
 use crate::symbol::*;
 use crate::types::*;
 
+impl Encoder for ValueRef<'_> {
+    fn encode(&self, writer: &mut dyn Write) -> Result<TypeCode> {
+        encode_ref(self, &EncodeOptions::default(), writer)
+    }
+
+    fn encode_with_options(
+        &self,
+        writer: &mut dyn Write,
+        options: &EncodeOptions,
+    ) -> Result<TypeCode> {
+        encode_ref(self, options, writer)
+    }
+}
+
 /**
  * This is the main encoder implementation for AMQP types.
  */
-impl Encoder for ValueRef<'_> {
-    fn encode(&self, writer: &mut dyn Write) -> Result<TypeCode> {
-        let value = self;
-        match *value {
-            ValueRef::Described(ref descriptor, ref value) => {
-                writer.write_u8(0)?;
-                descriptor.encode(writer)?;
-                value.encode(writer)?;
-                Ok(TypeCode::Described)
-            }
-            ValueRef::Null => {
-                writer.write_u8(TypeCode::Null as u8)?;
-                Ok(TypeCode::Null)
-            }
-            ValueRef::Bool(value) => {
-                let code = if *value {
-                    TypeCode::BooleanTrue
-                } else {
-                    TypeCode::BooleanFalse
+fn encode_ref(
+    value: &ValueRef,
+    options: &EncodeOptions,
+    writer: &mut dyn Write,
+) -> Result<TypeCode> {
+    match *value {
+        ValueRef::Described(ref descriptor, ref value) => {
+            writer.write_u8(0)?;
+            encode_ref(descriptor, options, writer)?;
+            if options.mode != EncodeMode::Default {
+                // Composite types may omit trailing null fields
+                let fields: Option<Vec<ValueRef>> = match &**value {
+                    ValueRef::List(v) => Some(v.iter().map(Value::value_ref).collect()),
+                    ValueRef::ListRef(v) => Some(v.to_vec()),
+                    _ => None,
                 };
-                writer.write_u8(code as u8)?;
-
-                Ok(code)
-            }
-            ValueRef::String(val) => {
-                if val.len() > U8_MAX {
-                    writer.write_u8(TypeCode::Str32 as u8)?;
-                    writer.write_u32::<NetworkEndian>(val.len() as u32)?;
-                    writer.write_all(val.as_bytes())?;
-                    Ok(TypeCode::Str32)
-                } else {
-                    writer.write_u8(TypeCode::Str8 as u8)?;
-                    writer.write_u8(val.len() as u8)?;
-                    writer.write_all(val.as_bytes())?;
-                    Ok(TypeCode::Str8)
-                }
-            }
-            ValueRef::SymbolRef(val) => {
-                if val.len() > U8_MAX {
-                    writer.write_u8(TypeCode::Sym32 as u8)?;
-                    writer.write_u32::<NetworkEndian>(val.len() as u32)?;
-                    writer.write_all(val.as_bytes())?;
-                    Ok(TypeCode::Sym32)
-                } else {
-                    writer.write_u8(TypeCode::Sym8 as u8)?;
-                    writer.write_u8(val.len() as u8)?;
-                    writer.write_all(val.as_bytes())?;
-                    Ok(TypeCode::Sym8)
+                if let Some(mut fields) = fields {
+                    while let Some(ValueRef::Null) = fields.last() {
+                        fields.pop();
+                    }
+                    encode_list(&fields, options, writer)?;
+                    return Ok(TypeCode::Described);
                 }
             }
-            ValueRef::Symbol(val) => {
-                if val.len() > U8_MAX {
-                    writer.write_u8(TypeCode::Sym32 as u8)?;
-                    writer.write_u32::<NetworkEndian>(val.len() as u32)?;
-                    writer.write_all(val)?;
-                    Ok(TypeCode::Sym32)
-                } else {
-                    writer.write_u8(TypeCode::Sym8 as u8)?;
-                    writer.write_u8(val.len() as u8)?;
-                    writer.write_all(val)?;
-                    Ok(TypeCode::Sym8)
-                }
+            encode_ref(value, options, writer)?;
+            Ok(TypeCode::Described)
+        }
+        ValueRef::Null => {
+            writer.write_u8(TypeCode::Null as u8)?;
+            Ok(TypeCode::Null)
+        }
+        ValueRef::Bool(value) => {
+            let code = if *value {
+                TypeCode::BooleanTrue
+            } else {
+                TypeCode::BooleanFalse
+            };
+            writer.write_u8(code as u8)?;
+
+            Ok(code)
+        }
+        ValueRef::String(val) => {
+            if val.len() > U8_MAX {
+                writer.write_u8(TypeCode::Str32 as u8)?;
+                writer.write_u32::<NetworkEndian>(val.len() as u32)?;
+                writer.write_all(val.as_bytes())?;
+                Ok(TypeCode::Str32)
+            } else {
+                writer.write_u8(TypeCode::Str8 as u8)?;
+                writer.write_u8(val.len() as u8)?;
+                writer.write_all(val.as_bytes())?;
+                Ok(TypeCode::Str8)
             }
-            ValueRef::Binary(val) => {
-                if val.len() > U8_MAX {
-                    writer.write_u8(TypeCode::Bin32 as u8)?;
-                    writer.write_u32::<NetworkEndian>(val.len() as u32)?;
-                    writer.write_all(val)?;
-                    Ok(TypeCode::Bin32)
-                } else {
-                    writer.write_u8(TypeCode::Bin8 as u8)?;
-                    writer.write_u8(val.len() as u8)?;
-                    writer.write_all(val)?;
-                    Ok(TypeCode::Bin8)
-                }
+        }
+        ValueRef::SymbolRef(val) => {
+            if val.len() > U8_MAX {
+                writer.write_u8(TypeCode::Sym32 as u8)?;
+                writer.write_u32::<NetworkEndian>(val.len() as u32)?;
+                writer.write_all(val.as_bytes())?;
+                Ok(TypeCode::Sym32)
+            } else {
+                writer.write_u8(TypeCode::Sym8 as u8)?;
+                writer.write_u8(val.len() as u8)?;
+                writer.write_all(val.as_bytes())?;
+                Ok(TypeCode::Sym8)
             }
-            ValueRef::Ubyte(val) => {
-                writer.write_u8(TypeCode::Ubyte as u8)?;
-                writer.write_u8(*val)?;
-                Ok(TypeCode::Ubyte)
+        }
+        ValueRef::Symbol(val) => {
+            if val.len() > U8_MAX {
+                writer.write_u8(TypeCode::Sym32 as u8)?;
+                writer.write_u32::<NetworkEndian>(val.len() as u32)?;
+                writer.write_all(val)?;
+                Ok(TypeCode::Sym32)
+            } else {
+                writer.write_u8(TypeCode::Sym8 as u8)?;
+                writer.write_u8(val.len() as u8)?;
+                writer.write_all(val)?;
+                Ok(TypeCode::Sym8)
             }
-            ValueRef::Ushort(val) => {
-                writer.write_u8(TypeCode::Ushort as u8)?;
-                writer.write_u16::<NetworkEndian>(*val)?;
-                Ok(TypeCode::Ushort)
+        }
+        ValueRef::Binary(val) => {
+            if val.len() > U8_MAX {
+                writer.write_u8(TypeCode::Bin32 as u8)?;
+                writer.write_u32::<NetworkEndian>(val.len() as u32)?;
+                writer.write_all(val)?;
+                Ok(TypeCode::Bin32)
+            } else {
+                writer.write_u8(TypeCode::Bin8 as u8)?;
+                writer.write_u8(val.len() as u8)?;
+                writer.write_all(val)?;
+                Ok(TypeCode::Bin8)
             }
-            ValueRef::Uint(val) => {
-                if *val > U8_MAX as u32 {
-                    writer.write_u8(TypeCode::Uint as u8)?;
-                    writer.write_u32::<NetworkEndian>(*val)?;
-                    Ok(TypeCode::Uint)
-                } else if *val > 0 {
-                    writer.write_u8(TypeCode::Uintsmall as u8)?;
-                    writer.write_u8(*val as u8)?;
-                    Ok(TypeCode::Uintsmall)
-                } else {
-                    writer.write_u8(TypeCode::Uint0 as u8)?;
-                    Ok(TypeCode::Uint0)
-                }
+        }
+        ValueRef::Ubyte(val) => {
+            writer.write_u8(TypeCode::Ubyte as u8)?;
+            writer.write_u8(*val)?;
+            Ok(TypeCode::Ubyte)
+        }
+        ValueRef::Ushort(val) => {
+            writer.write_u8(TypeCode::Ushort as u8)?;
+            writer.write_u16::<NetworkEndian>(*val)?;
+            Ok(TypeCode::Ushort)
+        }
+        ValueRef::Uint(val) => {
+            if *val > U8_MAX as u32 {
+                writer.write_u8(TypeCode::Uint as u8)?;
+                writer.write_u32::<NetworkEndian>(*val)?;
+                Ok(TypeCode::Uint)
+            } else if *val > 0 {
+                writer.write_u8(TypeCode::Uintsmall as u8)?;
+                writer.write_u8(*val as u8)?;
+                Ok(TypeCode::Uintsmall)
+            } else {
+                writer.write_u8(TypeCode::Uint0 as u8)?;
+                Ok(TypeCode::Uint0)
             }
-            ValueRef::Ulong(val) => {
-                if *val > U8_MAX as u64 {
-                    writer.write_u8(TypeCode::Ulong as u8)?;
-                    writer.write_u64::<NetworkEndian>(*val)?;
-                    Ok(TypeCode::Ulong)
-                } else if *val > 0 {
-                    writer.write_u8(TypeCode::Ulongsmall as u8)?;
-                    writer.write_u8(*val as u8)?;
-                    Ok(TypeCode::Ulongsmall)
-                } else {
-                    writer.write_u8(TypeCode::Ulong0 as u8)?;
-                    Ok(TypeCode::Ulong0)
-                }
+        }
+        ValueRef::Ulong(val) => {
+            if *val > U8_MAX as u64 {
+                writer.write_u8(TypeCode::Ulong as u8)?;
+                writer.write_u64::<NetworkEndian>(*val)?;
+                Ok(TypeCode::Ulong)
+            } else if *val > 0 {
+                writer.write_u8(TypeCode::Ulongsmall as u8)?;
+                writer.write_u8(*val as u8)?;
+                Ok(TypeCode::Ulongsmall)
+            } else {
+                writer.write_u8(TypeCode::Ulong0 as u8)?;
+                Ok(TypeCode::Ulong0)
             }
-            ValueRef::Byte(val) => {
-                writer.write_u8(TypeCode::Byte as u8)?;
-                writer.write_i8(*val)?;
-                Ok(TypeCode::Byte)
+        }
+        ValueRef::Byte(val) => {
+            writer.write_u8(TypeCode::Byte as u8)?;
+            writer.write_i8(*val)?;
+            Ok(TypeCode::Byte)
+        }
+        ValueRef::Short(val) => {
+            writer.write_u8(TypeCode::Short as u8)?;
+            writer.write_i16::<NetworkEndian>(*val)?;
+            Ok(TypeCode::Short)
+        }
+        ValueRef::Int(val) => {
+            if i8::try_from(*val).is_err() {
+                writer.write_u8(TypeCode::Int as u8)?;
+                writer.write_i32::<NetworkEndian>(*val)?;
+                Ok(TypeCode::Int)
+            } else {
+                writer.write_u8(TypeCode::Intsmall as u8)?;
+                writer.write_i8(*val as i8)?;
+                Ok(TypeCode::Intsmall)
             }
-            ValueRef::Short(val) => {
-                writer.write_u8(TypeCode::Short as u8)?;
-                writer.write_i16::<NetworkEndian>(*val)?;
-                Ok(TypeCode::Short)
+        }
+        ValueRef::Long(val) => {
+            if i8::try_from(*val).is_err() {
+                writer.write_u8(TypeCode::Long as u8)?;
+                writer.write_i64::<NetworkEndian>(*val)?;
+                Ok(TypeCode::Long)
+            } else {
+                writer.write_u8(TypeCode::Longsmall as u8)?;
+                writer.write_i8(*val as i8)?;
+                Ok(TypeCode::Longsmall)
             }
-            ValueRef::Int(val) => {
-                if *val > I8_MAX as i32 {
-                    writer.write_u8(TypeCode::Int as u8)?;
-                    writer.write_i32::<NetworkEndian>(*val)?;
-                    Ok(TypeCode::Int)
-                } else {
-                    writer.write_u8(TypeCode::Intsmall as u8)?;
-                    writer.write_i8(*val as i8)?;
-                    Ok(TypeCode::Intsmall)
-                }
+        }
+        ValueRef::Float(val) => {
+            writer.write_u8(TypeCode::Float as u8)?;
+            writer.write_f32::<NetworkEndian>(val.0)?;
+            Ok(TypeCode::Float)
+        }
+        ValueRef::Double(val) => {
+            writer.write_u8(TypeCode::Double as u8)?;
+            writer.write_f64::<NetworkEndian>(val.0)?;
+            Ok(TypeCode::Double)
+        }
+        ValueRef::Decimal32(val) => {
+            writer.write_u8(TypeCode::Decimal32 as u8)?;
+            writer.write_u32::<NetworkEndian>(val.0)?;
+            Ok(TypeCode::Decimal32)
+        }
+        ValueRef::Decimal64(val) => {
+            writer.write_u8(TypeCode::Decimal64 as u8)?;
+            writer.write_u64::<NetworkEndian>(val.0)?;
+            Ok(TypeCode::Decimal64)
+        }
+        ValueRef::Decimal128(val) => {
+            writer.write_u8(TypeCode::Decimal128 as u8)?;
+            writer.write_u128::<NetworkEndian>(val.0)?;
+            Ok(TypeCode::Decimal128)
+        }
+        ValueRef::Array(vec) => {
+            let values: Vec<ValueRef> = vec.iter().map(Value::value_ref).collect();
+            encode_array(&values, options, writer)
+        }
+        ValueRef::List(vec) => {
+            let values: Vec<ValueRef> = vec.iter().map(Value::value_ref).collect();
+            encode_list(&values, options, writer)
+        }
+        ValueRef::Map(m) => {
+            let entries: Vec<(ValueRef, ValueRef)> = m
+                .iter()
+                .map(|(k, v)| (k.value_ref(), v.value_ref()))
+                .collect();
+            encode_map(&entries, options, writer)
+        }
+        ValueRef::ArrayRef(vec) => encode_array(vec, options, writer),
+        ValueRef::ListRef(vec) => encode_list(vec, options, writer),
+        ValueRef::MapRef(m) => encode_map(m, options, writer),
+        ValueRef::Char(val) => {
+            writer.write_u8(TypeCode::Char as u8)?;
+            writer.write_u32::<NetworkEndian>(*val as u32)?;
+            Ok(TypeCode::Char)
+        }
+        ValueRef::Timestamp(val) => {
+            writer.write_u8(TypeCode::Timestamp as u8)?;
+            writer.write_u64::<NetworkEndian>(*val)?;
+            Ok(TypeCode::Timestamp)
+        }
+        ValueRef::Uuid(val) => {
+            writer.write_u8(TypeCode::Uuid as u8)?;
+            writer.write_all(val.as_bytes())?;
+            Ok(TypeCode::Uuid)
+        }
+    }
+}
+
+fn encode_list(
+    values: &[ValueRef],
+    options: &EncodeOptions,
+    writer: &mut dyn Write,
+) -> Result<TypeCode> {
+    let mut listbuf = Vec::new();
+    for v in values.iter() {
+        encode_ref(v, options, &mut listbuf)?;
+    }
+
+    if listbuf.len() > LIST32_MAX {
+        Err(AmqpError::decode_error(Some(
+            "Encoded list size cannot be longer than 4294967291 bytes",
+        )))
+    } else if listbuf.len() > LIST8_MAX {
+        writer.write_u8(TypeCode::List32 as u8)?;
+        writer.write_u32::<NetworkEndian>((4 + listbuf.len()) as u32)?;
+        writer.write_u32::<NetworkEndian>(values.len() as u32)?;
+        writer.write_all(&listbuf[..])?;
+        Ok(TypeCode::List32)
+    } else if !listbuf.is_empty() {
+        writer.write_u8(TypeCode::List8 as u8)?;
+        writer.write_u8((1 + listbuf.len()) as u8)?;
+        writer.write_u8(values.len() as u8)?;
+        writer.write_all(&listbuf[..])?;
+        Ok(TypeCode::List8)
+    } else {
+        writer.write_u8(TypeCode::List0 as u8)?;
+        Ok(TypeCode::List0)
+    }
+}
+
+fn encode_map(
+    entries: &[(ValueRef, ValueRef)],
+    options: &EncodeOptions,
+    writer: &mut dyn Write,
+) -> Result<TypeCode> {
+    let mut encoded = Vec::with_capacity(entries.len());
+    for (key, value) in entries.iter() {
+        let mut entry = Vec::new();
+        encode_ref(key, options, &mut entry)?;
+        let key_len = entry.len();
+        encode_ref(value, options, &mut entry)?;
+        encoded.push((key_len, entry));
+    }
+    if options.mode == EncodeMode::Canonical {
+        // Order entries by their encoded keys
+        encoded.sort_by(|(l1, e1), (l2, e2)| e1[..*l1].cmp(&e2[..*l2]));
+    }
+    let listbuf: Vec<u8> = encoded.into_iter().flat_map(|(_, entry)| entry).collect();
+
+    let n_items = entries.len() * 2;
+
+    if listbuf.len() > LIST32_MAX {
+        Err(AmqpError::decode_error(Some(
+            "Encoded map size cannot be longer than 4294967291 bytes",
+        )))
+    } else if listbuf.len() > LIST8_MAX || n_items > U8_MAX {
+        writer.write_u8(TypeCode::Map32 as u8)?;
+        writer.write_u32::<NetworkEndian>((4 + listbuf.len()) as u32)?;
+        writer.write_u32::<NetworkEndian>(n_items as u32)?;
+        writer.write_all(&listbuf[..])?;
+        Ok(TypeCode::Map32)
+    } else {
+        writer.write_u8(TypeCode::Map8 as u8)?;
+        writer.write_u8((1 + listbuf.len()) as u8)?;
+        writer.write_u8(n_items as u8)?;
+        writer.write_all(&listbuf[..])?;
+        Ok(TypeCode::Map8)
+    }
+}
+
+/// The constructor shared by all elements of an array.
+#[derive(Debug)]
+enum ElementConstructor<'a, 'b> {
+    Primitive(TypeCode),
+    Described(&'b ValueRef<'a>, Box<ElementConstructor<'a, 'b>>),
+}
+
+impl<'a, 'b> ElementConstructor<'a, 'b> {
+    /// Find the constructor for a single element. Fixed width types use their full width
+    /// encoding and compound types use their 32 bit encoding, as array elements cannot use
+    /// the compact encodings that vary with the value.
+    fn of(value: &'b ValueRef<'a>) -> Self {
+        let sized = |len: usize, code8, code32| {
+            if len > U8_MAX {
+                code32
+            } else {
+                code8
             }
-            ValueRef::Long(val) => {
-                if *val > I8_MAX as i64 {
-                    writer.write_u8(TypeCode::Long as u8)?;
-                    writer.write_i64::<NetworkEndian>(*val)?;
-                    Ok(TypeCode::Long)
-                } else {
-                    writer.write_u8(TypeCode::Longsmall as u8)?;
-                    writer.write_i8(*val as i8)?;
-                    Ok(TypeCode::Longsmall)
-                }
+        };
+        let code = match value {
+            ValueRef::Described(descriptor, value) => {
+                return ElementConstructor::Described(
+                    descriptor,
+                    Box::new(ElementConstructor::of(value)),
+                )
             }
-            ValueRef::Array(vec) => {
-                let mut arraybuf = Vec::new();
-                let mut code = 0;
-                for v in vec.iter() {
-                    let mut valuebuf = Vec::new();
-                    v.encode(&mut valuebuf)?;
-                    if code == 0 {
-                        code = valuebuf[0];
-                    }
-                    arraybuf.extend_from_slice(&valuebuf[1..]);
-                }
+            ValueRef::Null => TypeCode::Null,
+            ValueRef::Bool(_) => TypeCode::Boolean,
+            ValueRef::Ubyte(_) => TypeCode::Ubyte,
+            ValueRef::Ushort(_) => TypeCode::Ushort,
+            ValueRef::Uint(_) => TypeCode::Uint,
+            ValueRef::Ulong(_) => TypeCode::Ulong,
+            ValueRef::Byte(_) => TypeCode::Byte,
+            ValueRef::Short(_) => TypeCode::Short,
+            ValueRef::Int(_) => TypeCode::Int,
+            ValueRef::Long(_) => TypeCode::Long,
+            ValueRef::Float(_) => TypeCode::Float,
+            ValueRef::Double(_) => TypeCode::Double,
+            ValueRef::Decimal32(_) => TypeCode::Decimal32,
+            ValueRef::Decimal64(_) => TypeCode::Decimal64,
+            ValueRef::Decimal128(_) => TypeCode::Decimal128,
+            ValueRef::Char(_) => TypeCode::Char,
+            ValueRef::Timestamp(_) => TypeCode::Timestamp,
+            ValueRef::Uuid(_) => TypeCode::Uuid,
+            ValueRef::Binary(v) => sized(v.len(), TypeCode::Bin8, TypeCode::Bin32),
+            ValueRef::String(v) => sized(v.len(), TypeCode::Str8, TypeCode::Str32),
+            ValueRef::Symbol(v) => sized(v.len(), TypeCode::Sym8, TypeCode::Sym32),
+            ValueRef::SymbolRef(v) => sized(v.len(), TypeCode::Sym8, TypeCode::Sym32),
+            ValueRef::List(_) | ValueRef::ListRef(_) => TypeCode::List32,
+            ValueRef::Map(_) | ValueRef::MapRef(_) => TypeCode::Map32,
+            ValueRef::Array(_) | ValueRef::ArrayRef(_) => TypeCode::Array32,
+        };
+        ElementConstructor::Primitive(code)
+    }
 
-                if arraybuf.len() > LIST32_MAX {
-                    Err(AmqpError::decode_error(Some(
-                        "Encoded array size cannot be longer than 4294967291 bytes",
-                    )))
-                } else if arraybuf.len() > LIST8_MAX {
-                    writer.write_u8(TypeCode::Array32 as u8)?;
-                    writer.write_u32::<NetworkEndian>((5 + arraybuf.len()) as u32)?;
-                    writer.write_u32::<NetworkEndian>(vec.len() as u32)?;
-                    writer.write_u8(code)?;
-                    writer.write_all(&arraybuf[..])?;
-                    Ok(TypeCode::Array32)
-                } else if !arraybuf.is_empty() {
-                    writer.write_u8(TypeCode::Array8 as u8)?;
-                    writer.write_u8((2 + arraybuf.len()) as u8)?;
-                    writer.write_u8(vec.len() as u8)?;
-                    writer.write_u8(code)?;
-                    writer.write_all(&arraybuf[..])?;
-                    Ok(TypeCode::Array8)
-                } else {
-                    writer.write_u8(TypeCode::Null as u8)?;
-                    Ok(TypeCode::Null)
+    /// Combine with the constructor of another element, widening variable width
+    /// constructors when needed. Fails if the elements are of different types.
+    fn merge(self, other: Self) -> Result<Self> {
+        match (self, other) {
+            (
+                ElementConstructor::Described(d1, inner1),
+                ElementConstructor::Described(d2, inner2),
+            ) if d1 == d2 => Ok(ElementConstructor::Described(
+                d1,
+                Box::new(inner1.merge(*inner2)?),
+            )),
+            (ElementConstructor::Primitive(c1), ElementConstructor::Primitive(c2)) => {
+                match (c1, c2) {
+                    (c1, c2) if c1 == c2 => Ok(ElementConstructor::Primitive(c1)),
+                    (TypeCode::Bin8, TypeCode::Bin32) | (TypeCode::Bin32, TypeCode::Bin8) => {
+                        Ok(ElementConstructor::Primitive(TypeCode::Bin32))
+                    }
+                    (TypeCode::Str8, TypeCode::Str32) | (TypeCode::Str32, TypeCode::Str8) => {
+                        Ok(ElementConstructor::Primitive(TypeCode::Str32))
+                    }
+                    (TypeCode::Sym8, TypeCode::Sym32) | (TypeCode::Sym32, TypeCode::Sym8) => {
+                        Ok(ElementConstructor::Primitive(TypeCode::Sym32))
+                    }
+                    (c1, c2) => Err(AmqpError::Generic(format!(
+                        "Array elements must be of the same type, found {:?} and {:?}",
+                        c1, c2
+                    ))),
                 }
             }
-            ValueRef::List(vec) => {
-                let mut listbuf = Vec::new();
-                for v in vec.iter() {
-                    v.encode(&mut listbuf)?;
-                }
+            (c1, c2) => Err(AmqpError::Generic(format!(
+                "Array elements must be of the same type, found {:?} and {:?}",
+                c1, c2
+            ))),
+        }
+    }
 
-                if listbuf.len() > LIST32_MAX {
-                    Err(AmqpError::decode_error(Some(
-                        "Encoded list size cannot be longer than 4294967291 bytes",
-                    )))
-                } else if listbuf.len() > LIST8_MAX {
-                    writer.write_u8(TypeCode::List32 as u8)?;
-                    writer.write_u32::<NetworkEndian>((4 + listbuf.len()) as u32)?;
-                    writer.write_u32::<NetworkEndian>(vec.len() as u32)?;
-                    writer.write_all(&listbuf[..])?;
-                    Ok(TypeCode::List32)
-                } else if !listbuf.is_empty() {
-                    writer.write_u8(TypeCode::List8 as u8)?;
-                    writer.write_u8((1 + listbuf.len()) as u8)?;
-                    writer.write_u8(vec.len() as u8)?;
-                    writer.write_all(&listbuf[..])?;
-                    Ok(TypeCode::List8)
-                } else {
-                    writer.write_u8(TypeCode::List0 as u8)?;
-                    Ok(TypeCode::List0)
-                }
+    fn encode(&self, options: &EncodeOptions, writer: &mut dyn Write) -> Result<()> {
+        match self {
+            ElementConstructor::Primitive(code) => writer.write_u8(*code as u8)?,
+            ElementConstructor::Described(descriptor, inner) => {
+                writer.write_u8(TypeCode::Described as u8)?;
+                encode_ref(descriptor, options, writer)?;
+                inner.encode(options, writer)?;
             }
-            ValueRef::Map(m) => {
-                let mut listbuf = Vec::new();
-                for (key, value) in m {
-                    key.encode(&mut listbuf)?;
-                    value.encode(&mut listbuf)?;
-                }
+        }
+        Ok(())
+    }
 
-                let n_items = m.len() * 2;
-
-                if listbuf.len() > LIST32_MAX {
-                    Err(AmqpError::decode_error(Some(
-                        "Encoded map size cannot be longer than 4294967291 bytes",
-                    )))
-                } else if listbuf.len() > LIST8_MAX || n_items > U8_MAX {
-                    writer.write_u8(TypeCode::Map32 as u8)?;
-                    writer.write_u32::<NetworkEndian>((4 + listbuf.len()) as u32)?;
-                    writer.write_u32::<NetworkEndian>(n_items as u32)?;
-                    writer.write_all(&listbuf[..])?;
-                    Ok(TypeCode::Map32)
-                } else {
-                    writer.write_u8(TypeCode::Map8 as u8)?;
-                    writer.write_u8((1 + listbuf.len()) as u8)?;
-                    writer.write_u8(n_items as u8)?;
-                    writer.write_all(&listbuf[..])?;
-                    Ok(TypeCode::Map8)
-                }
+    /// Encode an element without its constructor.
+    fn encode_element(
+        &self,
+        value: &ValueRef,
+        options: &EncodeOptions,
+        writer: &mut dyn Write,
+    ) -> Result<()> {
+        let code = match (self, value) {
+            (ElementConstructor::Described(_, inner), ValueRef::Described(_, value)) => {
+                return inner.encode_element(value, options, writer)
             }
-            ValueRef::ArrayRef(vec) => {
-                let mut arraybuf = Vec::new();
-                let mut code = 0;
-                for v in vec.iter() {
-                    let mut valuebuf = Vec::new();
-                    v.encode(&mut valuebuf)?;
-                    if code == 0 {
-                        code = valuebuf[0];
-                    }
-                    arraybuf.extend_from_slice(&valuebuf[1..]);
-                }
-
-                if arraybuf.len() > LIST32_MAX {
-                    Err(AmqpError::decode_error(Some(
-                        "Encoded array size cannot be longer than 4294967291 bytes",
-                    )))
-                } else if arraybuf.len() > LIST8_MAX {
-                    writer.write_u8(TypeCode::Array32 as u8)?;
-                    writer.write_u32::<NetworkEndian>((5 + arraybuf.len()) as u32)?;
-                    writer.write_u32::<NetworkEndian>(vec.len() as u32)?;
-                    writer.write_u8(code)?;
-                    writer.write_all(&arraybuf[..])?;
-                    Ok(TypeCode::Array32)
-                } else if !arraybuf.is_empty() {
-                    writer.write_u8(TypeCode::Array8 as u8)?;
-                    writer.write_u8((2 + arraybuf.len()) as u8)?;
-                    writer.write_u8(vec.len() as u8)?;
-                    writer.write_u8(code)?;
-                    writer.write_all(&arraybuf[..])?;
-                    Ok(TypeCode::Array8)
-                } else {
-                    writer.write_u8(TypeCode::Null as u8)?;
-                    Ok(TypeCode::Null)
-                }
+            (ElementConstructor::Primitive(code), _) => *code,
+            _ => {
+                return Err(AmqpError::Generic(format!(
+                    "Array element {:?} does not match constructor {:?}",
+                    value, self
+                )))
             }
-            ValueRef::ListRef(vec) => {
-                let mut listbuf = Vec::new();
-                for v in vec.iter() {
-                    v.encode(&mut listbuf)?;
-                }
-
-                if listbuf.len() > LIST32_MAX {
-                    Err(AmqpError::decode_error(Some(
-                        "Encoded list size cannot be longer than 4294967291 bytes",
-                    )))
-                } else if listbuf.len() > LIST8_MAX {
-                    writer.write_u8(TypeCode::List32 as u8)?;
-                    writer.write_u32::<NetworkEndian>((4 + listbuf.len()) as u32)?;
-                    writer.write_u32::<NetworkEndian>(vec.len() as u32)?;
-                    writer.write_all(&listbuf[..])?;
-                    Ok(TypeCode::List32)
-                } else if !listbuf.is_empty() {
-                    writer.write_u8(TypeCode::List8 as u8)?;
-                    writer.write_u8((1 + listbuf.len()) as u8)?;
-                    writer.write_u8(vec.len() as u8)?;
-                    writer.write_all(&listbuf[..])?;
-                    Ok(TypeCode::List8)
-                } else {
-                    writer.write_u8(TypeCode::List0 as u8)?;
-                    Ok(TypeCode::List0)
-                }
+        };
+        match (code, value) {
+            (TypeCode::Null, ValueRef::Null) => {}
+            (TypeCode::Boolean, ValueRef::Bool(v)) => writer.write_u8(u8::from(**v))?,
+            (TypeCode::Ubyte, ValueRef::Ubyte(v)) => writer.write_u8(**v)?,
+            (TypeCode::Ushort, ValueRef::Ushort(v)) => writer.write_u16::<NetworkEndian>(**v)?,
+            (TypeCode::Uint, ValueRef::Uint(v)) => writer.write_u32::<NetworkEndian>(**v)?,
+            (TypeCode::Ulong, ValueRef::Ulong(v)) => writer.write_u64::<NetworkEndian>(**v)?,
+            (TypeCode::Byte, ValueRef::Byte(v)) => writer.write_i8(**v)?,
+            (TypeCode::Short, ValueRef::Short(v)) => writer.write_i16::<NetworkEndian>(**v)?,
+            (TypeCode::Int, ValueRef::Int(v)) => writer.write_i32::<NetworkEndian>(**v)?,
+            (TypeCode::Long, ValueRef::Long(v)) => writer.write_i64::<NetworkEndian>(**v)?,
+            (TypeCode::Float, ValueRef::Float(v)) => writer.write_f32::<NetworkEndian>(v.0)?,
+            (TypeCode::Double, ValueRef::Double(v)) => writer.write_f64::<NetworkEndian>(v.0)?,
+            (TypeCode::Decimal32, ValueRef::Decimal32(v)) => {
+                writer.write_u32::<NetworkEndian>(v.0)?
             }
-            ValueRef::MapRef(m) => {
-                let mut listbuf = Vec::new();
-                for (key, value) in m {
-                    key.encode(&mut listbuf)?;
-                    value.encode(&mut listbuf)?;
-                }
-
-                let n_items = m.len() * 2;
-
-                if listbuf.len() > LIST32_MAX {
-                    Err(AmqpError::decode_error(Some(
-                        "Encoded map size cannot be longer than 4294967291 bytes",
-                    )))
-                } else if listbuf.len() > LIST8_MAX || n_items > U8_MAX {
-                    writer.write_u8(TypeCode::Map32 as u8)?;
-                    writer.write_u32::<NetworkEndian>((4 + listbuf.len()) as u32)?;
-                    writer.write_u32::<NetworkEndian>(n_items as u32)?;
-                    writer.write_all(&listbuf[..])?;
-                    Ok(TypeCode::Map32)
-                } else {
-                    writer.write_u8(TypeCode::Map8 as u8)?;
-                    writer.write_u8((1 + listbuf.len()) as u8)?;
-                    writer.write_u8(n_items as u8)?;
-                    writer.write_all(&listbuf[..])?;
-                    Ok(TypeCode::Map8)
-                }
+            (TypeCode::Decimal64, ValueRef::Decimal64(v)) => {
+                writer.write_u64::<NetworkEndian>(v.0)?
             }
-            ValueRef::Char(val) => {
-                writer.write_u8(TypeCode::Char as u8)?;
-                writer.write_u32::<NetworkEndian>(*val as u32)?;
-                Ok(TypeCode::Char)
+            (TypeCode::Decimal128, ValueRef::Decimal128(v)) => {
+                writer.write_u128::<NetworkEndian>(v.0)?
             }
-            ValueRef::Timestamp(val) => {
-                writer.write_u8(TypeCode::Timestamp as u8)?;
-                writer.write_u64::<NetworkEndian>(*val)?;
-                Ok(TypeCode::Timestamp)
+            (TypeCode::Char, ValueRef::Char(v)) => writer.write_u32::<NetworkEndian>(**v as u32)?,
+            (TypeCode::Timestamp, ValueRef::Timestamp(v)) => {
+                writer.write_u64::<NetworkEndian>(**v)?
+            }
+            (TypeCode::Uuid, ValueRef::Uuid(v)) => writer.write_all(v.as_bytes())?,
+            (TypeCode::Bin8 | TypeCode::Bin32, ValueRef::Binary(v))
+            | (TypeCode::Sym8 | TypeCode::Sym32, ValueRef::Symbol(v)) => {
+                write_sized(code, v, writer)?
+            }
+            (TypeCode::Str8 | TypeCode::Str32, ValueRef::String(v))
+            | (TypeCode::Sym8 | TypeCode::Sym32, ValueRef::SymbolRef(v)) => {
+                write_sized(code, v.as_bytes(), writer)?
+            }
+            (TypeCode::List32, ValueRef::List(_) | ValueRef::ListRef(_))
+            | (TypeCode::Map32, ValueRef::Map(_) | ValueRef::MapRef(_)) => {
+                // Re-encode the compound value and widen its size and count fields
+                let mut buf = Vec::new();
+                let encoded = encode_ref(value, options, &mut buf)?;
+                let (count, body) = match encoded {
+                    TypeCode::List0 => (0, &buf[1..]),
+                    TypeCode::List8 | TypeCode::Map8 => (buf[2] as u32, &buf[3..]),
+                    _ => {
+                        writer.write_all(&buf[1..])?;
+                        return Ok(());
+                    }
+                };
+                writer.write_u32::<NetworkEndian>((4 + body.len()) as u32)?;
+                writer.write_u32::<NetworkEndian>(count)?;
+                writer.write_all(body)?;
+            }
+            (TypeCode::Array32, ValueRef::Array(v)) => {
+                let values: Vec<ValueRef> = v.iter().map(Value::value_ref).collect();
+                encode_array_body(&values, true, options, writer)?;
+            }
+            (TypeCode::Array32, ValueRef::ArrayRef(v)) => {
+                encode_array_body(v, true, options, writer)?;
+            }
+            _ => {
+                return Err(AmqpError::Generic(format!(
+                    "Array element {:?} does not match constructor {:?}",
+                    value, code
+                )))
             }
         }
+        Ok(())
+    }
+}
+
+fn write_sized(code: TypeCode, data: &[u8], writer: &mut dyn Write) -> Result<()> {
+    match code {
+        TypeCode::Bin8 | TypeCode::Str8 | TypeCode::Sym8 => writer.write_u8(data.len() as u8)?,
+        _ => writer.write_u32::<NetworkEndian>(data.len() as u32)?,
+    }
+    writer.write_all(data)?;
+    Ok(())
+}
+
+/**
+ * Encode an array with a single element constructor. Empty arrays are encoded as null,
+ * as there is no element type to describe.
+ */
+fn encode_array(
+    values: &[ValueRef],
+    options: &EncodeOptions,
+    writer: &mut dyn Write,
+) -> Result<TypeCode> {
+    if values.is_empty() {
+        writer.write_u8(TypeCode::Null as u8)?;
+        return Ok(TypeCode::Null);
+    }
+    let mut buf = Vec::new();
+    let wide = encode_array_body(values, false, options, &mut buf)?;
+    let code = if wide {
+        TypeCode::Array32
+    } else {
+        TypeCode::Array8
+    };
+    writer.write_u8(code as u8)?;
+    writer.write_all(&buf[..])?;
+    Ok(code)
+}
+
+/// Encode the size, count, element constructor and elements of an array. The size and count are
+/// encoded as 32 bit values if `wide` is set or they do not fit into 8 bits. Returns whether the
+/// 32 bit encoding was used.
+fn encode_array_body(
+    values: &[ValueRef],
+    wide: bool,
+    options: &EncodeOptions,
+    writer: &mut dyn Write,
+) -> Result<bool> {
+    let mut constructor: Option<ElementConstructor> = None;
+    for value in values.iter() {
+        let element = ElementConstructor::of(value);
+        constructor = Some(match constructor {
+            None => element,
+            Some(c) => c.merge(element)?,
+        });
+    }
+    let constructor = constructor.unwrap_or(ElementConstructor::Primitive(TypeCode::Null));
+
+    let mut buf = Vec::new();
+    constructor.encode(options, &mut buf)?;
+    for value in values.iter() {
+        constructor.encode_element(value, options, &mut buf)?;
+    }
+
+    if buf.len() > LIST32_MAX {
+        Err(AmqpError::decode_error(Some(
+            "Encoded array size cannot be longer than 4294967291 bytes",
+        )))
+    } else if wide || buf.len() > LIST8_MAX || values.len() > U8_MAX {
+        writer.write_u32::<NetworkEndian>((4 + buf.len()) as u32)?;
+        writer.write_u32::<NetworkEndian>(values.len() as u32)?;
+        writer.write_all(&buf[..])?;
+        Ok(true)
+    } else {
+        writer.write_u8((1 + buf.len()) as u8)?;
+        writer.write_u8(values.len() as u8)?;
+        writer.write_all(&buf[..])?;
+        Ok(false)
     }
 }
 
@@ -368,6 +610,14 @@ impl Encoder for Value {
         let value = self;
         value.value_ref().encode(writer)
     }
+
+    fn encode_with_options(
+        &self,
+        writer: &mut dyn Write,
+        options: &EncodeOptions,
+    ) -> Result<TypeCode> {
+        self.value_ref().encode_with_options(writer, options)
+    }
 }
 
 impl Encoder for ErrorCondition {
@@ -440,6 +690,42 @@ impl Encoder for u8 {
     }
 }
 
+impl Encoder for f32 {
+    fn encode(&self, writer: &mut dyn Write) -> Result<TypeCode> {
+        ValueRef::Float(&Float(*self)).encode(writer)
+    }
+}
+
+impl Encoder for f64 {
+    fn encode(&self, writer: &mut dyn Write) -> Result<TypeCode> {
+        ValueRef::Double(&Double(*self)).encode(writer)
+    }
+}
+
+impl Encoder for Decimal32 {
+    fn encode(&self, writer: &mut dyn Write) -> Result<TypeCode> {
+        ValueRef::Decimal32(self).encode(writer)
+    }
+}
+
+impl Encoder for Decimal64 {
+    fn encode(&self, writer: &mut dyn Write) -> Result<TypeCode> {
+        ValueRef::Decimal64(self).encode(writer)
+    }
+}
+
+impl Encoder for Decimal128 {
+    fn encode(&self, writer: &mut dyn Write) -> Result<TypeCode> {
+        ValueRef::Decimal128(self).encode(writer)
+    }
+}
+
+impl Encoder for Uuid {
+    fn encode(&self, writer: &mut dyn Write) -> Result<TypeCode> {
+        ValueRef::Uuid(self).encode(writer)
+    }
+}
+
 impl<T: Encoder> Encoder for Option<T> {
     fn encode(&self, writer: &mut dyn Write) -> Result<TypeCode> {
         match self {
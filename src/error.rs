@@ -7,6 +7,7 @@
 
 use crate::message::Message;
 use crate::sasl::SaslMechanism;
+use crate::types::Value;
 use async_channel::{RecvError, SendError, TryRecvError, TrySendError};
 use std::io;
 
@@ -26,6 +27,23 @@ impl ErrorCondition {
         }
     }
 
+    pub fn message_size_exceeded(size: usize, max_message_size: u64) -> Self {
+        ErrorCondition {
+            condition: AmqpError::AmqpLinkMessageSizeExceeded.to_string(),
+            description: format!(
+                "Message size {} exceeds maximum message size {}",
+                size, max_message_size
+            ),
+        }
+    }
+
+    pub fn framing_error(description: Option<String>) -> Self {
+        ErrorCondition {
+            condition: "amqp:connection:framing-error".to_string(),
+            description: description.unwrap_or_default(),
+        }
+    }
+
     pub fn detach_received() -> Self {
         ErrorCondition {
             condition: "amqp:resource-deleted".to_string(),
@@ -53,6 +71,11 @@ pub enum AmqpError {
     AmqpNotFound,
     #[error("amqp:decode-error (description: {0:?})")]
     AmqpDecodeError(Option<String>),
+    #[error("amqp:decode-error (expected {expected}, found {actual})")]
+    AmqpUnexpectedType {
+        expected: &'static str,
+        actual: &'static str,
+    },
     #[error("amqp:not-implemented")]
     AmqpNotImplemented,
     #[error("amqp:resource-limit-exceeded")]
@@ -65,10 +88,15 @@ pub enum AmqpError {
     #[error("amqp:connection:redirect")]
     AmqpConnectionRedirect,
 
+    #[error("amqp:link:message-size-exceeded")]
+    AmqpLinkMessageSizeExceeded,
+
     /// `Message` is 456 bytes large, the second-largest variant
     /// is `ErrorCondition` with 48 bytes, therefore `Message` is boxed
     #[error("The link does not have enough credits to send a message")]
     NotEnoughCreditsToSend(Box<Message>),
+    #[error("The link does not have enough credits to start a delivery")]
+    NotEnoughCredits,
     #[error("The server expected a SASL configuration but none was supplied")]
     SaslConfigurationExpected,
     #[error("Received a Transfer-Frame without payload but expected some")]
@@ -114,6 +142,13 @@ impl AmqpError {
     pub fn decode_error(description: Option<&str>) -> AmqpError {
         AmqpError::AmqpDecodeError(description.map(ToString::to_string))
     }
+
+    pub fn unexpected_type(expected: &'static str, actual: &Value) -> AmqpError {
+        AmqpError::AmqpUnexpectedType {
+            expected,
+            actual: actual.type_name(),
+        }
+    }
 }
 
 impl<T> std::convert::From<std::sync::mpsc::SendError<T>> for AmqpError {
@@ -158,6 +193,13 @@ impl std::convert::From<std::num::ParseIntError> for AmqpError {
     }
 }
 
+#[cfg(feature = "tls")]
+impl std::convert::From<rustls::Error> for AmqpError {
+    fn from(error: rustls::Error) -> Self {
+        AmqpError::Generic(error.to_string())
+    }
+}
+
 impl<T> From<SendError<T>> for AmqpError {
     fn from(_: SendError<T>) -> Self {
         AmqpError::SendError
@@ -125,3 +125,69 @@ impl Encoder for FrameEncoder {
         Ok(TypeCode::Described)
     }
 }
+
+#[cfg(all(test, feature = "derive"))]
+mod tests {
+
+    use super::*;
+    use crate::decoding::decode_value;
+    use crate::AmqpComposite;
+
+    #[derive(Debug, PartialEq, AmqpComposite)]
+    #[amqp(descriptor = 0x0000_468C_0000_0001, name = "com.example:lease:list")]
+    struct Lease {
+        holder: String,
+        #[amqp(default = "30")]
+        duration: u32,
+        #[amqp(default)]
+        renewals: u16,
+        expires: Option<u64>,
+        owner: Option<String>,
+    }
+
+    #[test]
+    fn check_derived_composite() {
+        let lease = Lease {
+            holder: "a".to_string(),
+            duration: 10,
+            renewals: 0,
+            expires: Some(5),
+            owner: None,
+        };
+
+        let mut output: Vec<u8> = Vec::new();
+        lease.encode(&mut output).unwrap();
+        // Trailing owner is trimmed
+        match decode_value(&mut &output[..]).unwrap() {
+            Value::Described(_, list) => assert_eq!(
+                Value::List(vec![
+                    Value::String("a".to_string()),
+                    Value::Uint(10),
+                    Value::Ushort(0),
+                    Value::Ulong(5),
+                ]),
+                *list
+            ),
+            value => panic!("unexpected value {:?}", value),
+        }
+        let decoded = <Lease as TryFromValue>::try_from(decode_value(&mut &output[..]).unwrap());
+        assert_eq!(lease, decoded.unwrap());
+
+        // Symbolic descriptor with defaults for missing fields
+        let value = Value::Described(
+            Box::new(Value::Symbol(b"com.example:lease:list".to_vec())),
+            Box::new(Value::List(vec![Value::String("b".to_string())])),
+        );
+        let decoded = <Lease as TryFromValue>::try_from(value).unwrap();
+        assert_eq!(30, decoded.duration);
+        assert_eq!(0, decoded.renewals);
+        assert_eq!(None, decoded.expires);
+
+        // Missing required field
+        let value = Value::Described(
+            Box::new(Value::Ulong(0x0000_468C_0000_0001)),
+            Box::new(Value::List(vec![])),
+        );
+        assert!(<Lease as TryFromValue>::try_from(value).is_err());
+    }
+}
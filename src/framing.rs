@@ -23,12 +23,15 @@ use crate::symbol::*;
 use crate::types::*;
 use std::str::FromStr;
 
+/// The size of the frame header preceding the frame body.
+pub const FRAME_HEADER_SIZE: usize = 8;
+
 #[derive(Debug)]
 pub struct FrameHeader {
     pub size: u32,
-    doff: u8,
-    frame_type: u8,
-    ext: u16,
+    pub(crate) doff: u8,
+    pub(crate) frame_type: u8,
+    pub(crate) ext: u16,
 }
 
 #[allow(clippy::large_enum_variant)]
@@ -195,6 +198,8 @@ pub struct Attach {
     pub rcv_settle_mode: Option<ReceiverSettleMode>,
     pub source: Option<Source>,
     pub target: Option<Target>,
+    /// The transaction coordinator, sent in place of the target on controller links
+    pub coordinator: Option<Coordinator>,
     pub unsettled: Option<BTreeMap<Value, Value>>,
     pub incomplete_unsettled: Option<bool>,
     pub initial_delivery_count: Option<u32>,
@@ -258,6 +263,8 @@ pub enum DeliveryState {
     Rejected(Rejected),
     Released,
     Modified(Modified),
+    Declared(Declared),
+    TransactionalState(TransactionalState),
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -278,6 +285,39 @@ pub struct Modified {
     pub message_annotations: Option<BTreeMap<String, Value>>,
 }
 
+/// The outcome of a declare message, carrying the identifier of the declared transaction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Declared {
+    pub txn_id: Vec<u8>,
+}
+
+/// The state of a delivery enlisted in a transaction. The outcome takes effect when the
+/// transaction is committed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransactionalState {
+    pub txn_id: Vec<u8>,
+    pub outcome: Option<Box<DeliveryState>>,
+}
+
+/// The target of a controller link, used for declaring and discharging transactions.
+#[derive(Debug, Default, Clone)]
+pub struct Coordinator {
+    pub capabilities: Option<Vec<Symbol>>,
+}
+
+/// Message body declaring a new transaction.
+#[derive(Debug, Default, Clone)]
+pub struct Declare {
+    pub global_id: Option<Value>,
+}
+
+/// Message body discharging a transaction, committing it unless `fail` is set.
+#[derive(Debug, Clone)]
+pub struct Discharge {
+    pub txn_id: Vec<u8>,
+    pub fail: Option<bool>,
+}
+
 #[derive(Debug, Clone)]
 pub struct Source {
     pub address: Option<String>,
@@ -320,7 +360,7 @@ impl LinkRole {
 }
 
 #[repr(u8)]
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SenderSettleMode {
     Unsettled,
     Settled,
@@ -328,7 +368,7 @@ pub enum SenderSettleMode {
 }
 
 #[repr(u8)]
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ReceiverSettleMode {
     First,
     Second,
@@ -502,6 +542,7 @@ impl Attach {
             rcv_settle_mode: None,
             source: None,
             target: None,
+            coordinator: None,
             unsettled: None,
             incomplete_unsettled: None,
             initial_delivery_count: None,
@@ -522,6 +563,11 @@ impl Attach {
         self
     }
 
+    pub fn coordinator(mut self, coordinator: Coordinator) -> Self {
+        self.coordinator = Some(coordinator);
+        self
+    }
+
     pub fn initial_delivery_count(mut self, initial_delivery_count: u32) -> Self {
         self.initial_delivery_count = Some(initial_delivery_count);
         self
@@ -535,7 +581,17 @@ impl Attach {
         decoder.decode_optional(&mut attach.snd_settle_mode)?;
         decoder.decode_optional(&mut attach.rcv_settle_mode)?;
         decoder.decode_optional(&mut attach.source)?;
-        decoder.decode_optional(&mut attach.target)?;
+        let mut target: Option<Value> = None;
+        decoder.decode_optional(&mut target)?;
+        match target {
+            Some(Value::Described(descriptor, body)) if *descriptor == DESC_COORDINATOR => {
+                attach.coordinator = Some(<Coordinator as TryFromValue>::try_from(
+                    Value::Described(descriptor, body),
+                )?);
+            }
+            Some(target) => attach.target = Some(<Target as TryFromValue>::try_from(target)?),
+            None => {}
+        }
         decoder.decode_optional(&mut attach.unsettled)?;
         decoder.decode_optional(&mut attach.incomplete_unsettled)?;
         decoder.decode_optional(&mut attach.initial_delivery_count)?;
@@ -556,7 +612,11 @@ impl Encoder for Attach {
         encoder.encode_arg(&self.snd_settle_mode.unwrap_or(SenderSettleMode::Mixed))?;
         encoder.encode_arg(&self.rcv_settle_mode.unwrap_or(ReceiverSettleMode::First))?;
         encoder.encode_arg(&self.source)?;
-        encoder.encode_arg(&self.target)?;
+        if let Some(coordinator) = &self.coordinator {
+            encoder.encode_arg(coordinator)?;
+        } else {
+            encoder.encode_arg(&self.target)?;
+        }
         encoder.encode_arg(&self.unsettled)?;
         encoder.encode_arg(&self.incomplete_unsettled.unwrap_or(false))?;
         encoder.encode_arg(&self.initial_delivery_count)?;
@@ -864,6 +924,20 @@ impl Encoder for DeliveryState {
                 encoder.encode_arg(&modified.message_annotations)?;
                 encoder
             }
+            DeliveryState::Declared(declared) => {
+                let mut encoder = FrameEncoder::new(DESC_DECLARED);
+                encoder.encode_arg(&declared.txn_id)?;
+                encoder
+            }
+            DeliveryState::TransactionalState(state) => {
+                let mut encoder = FrameEncoder::new(DESC_TRANSACTIONAL_STATE);
+                encoder.encode_arg(&state.txn_id)?;
+                match &state.outcome {
+                    Some(outcome) => encoder.encode_arg(&**outcome)?,
+                    None => encoder.encode_arg(&Value::Null)?,
+                }
+                encoder
+            }
         };
         value.encode(writer)
     }
@@ -901,6 +975,25 @@ impl TryFromValue for DeliveryState {
                     decoder.decode_optional(&mut modified.message_annotations)?;
                     Ok(DeliveryState::Modified(modified))
                 }
+                DESC_DECLARED => {
+                    let mut declared = Declared { txn_id: Vec::new() };
+                    decoder.decode_required(&mut declared.txn_id)?;
+                    Ok(DeliveryState::Declared(declared))
+                }
+                DESC_TRANSACTIONAL_STATE => {
+                    let mut state = TransactionalState {
+                        txn_id: Vec::new(),
+                        outcome: None,
+                    };
+                    let mut outcome: Option<Value> = None;
+                    decoder.decode_required(&mut state.txn_id)?;
+                    decoder.decode_optional(&mut outcome)?;
+                    state.outcome = outcome
+                        .map(<DeliveryState as TryFromValue>::try_from)
+                        .transpose()?
+                        .map(Box::new);
+                    Ok(DeliveryState::TransactionalState(state))
+                }
                 _ => Err(AmqpError::decode_error(Some(
                     "Error converting value to DeliveryState",
                 ))),
@@ -913,6 +1006,99 @@ impl TryFromValue for DeliveryState {
     }
 }
 
+impl Encoder for Coordinator {
+    fn encode(&self, writer: &mut dyn Write) -> Result<TypeCode> {
+        let mut encoder = FrameEncoder::new(DESC_COORDINATOR);
+        encoder.encode_arg(&self.capabilities)?;
+        encoder.encode(writer)
+    }
+}
+
+impl TryFromValue for Coordinator {
+    fn try_from(value: Value) -> Result<Self> {
+        match value {
+            Value::Described(descriptor, mut body) if *descriptor == DESC_COORDINATOR => {
+                let mut decoder = FrameDecoder::new(&descriptor, &mut body)?;
+                let mut coordinator = Coordinator::default();
+                decoder.decode_optional(&mut coordinator.capabilities)?;
+                Ok(coordinator)
+            }
+            _ => Err(AmqpError::decode_error(Some(
+                "Error converting value to Coordinator",
+            ))),
+        }
+    }
+}
+
+impl From<Declare> for Value {
+    fn from(declare: Declare) -> Self {
+        Value::Described(
+            Box::new(DESC_DECLARE),
+            Box::new(Value::List(vec![declare.global_id.unwrap_or(Value::Null)])),
+        )
+    }
+}
+
+impl Encoder for Declare {
+    fn encode(&self, writer: &mut dyn Write) -> Result<TypeCode> {
+        Value::from(self.clone()).encode(writer)
+    }
+}
+
+impl TryFromValue for Declare {
+    fn try_from(value: Value) -> Result<Self> {
+        match value {
+            Value::Described(descriptor, mut body) if *descriptor == DESC_DECLARE => {
+                let mut decoder = FrameDecoder::new(&descriptor, &mut body)?;
+                let mut declare = Declare::default();
+                decoder.decode_optional(&mut declare.global_id)?;
+                Ok(declare)
+            }
+            _ => Err(AmqpError::decode_error(Some(
+                "Error converting value to Declare",
+            ))),
+        }
+    }
+}
+
+impl From<Discharge> for Value {
+    fn from(discharge: Discharge) -> Self {
+        Value::Described(
+            Box::new(DESC_DISCHARGE),
+            Box::new(Value::List(vec![
+                Value::Binary(discharge.txn_id),
+                discharge.fail.map(Value::Bool).unwrap_or(Value::Null),
+            ])),
+        )
+    }
+}
+
+impl Encoder for Discharge {
+    fn encode(&self, writer: &mut dyn Write) -> Result<TypeCode> {
+        Value::from(self.clone()).encode(writer)
+    }
+}
+
+impl TryFromValue for Discharge {
+    fn try_from(value: Value) -> Result<Self> {
+        match value {
+            Value::Described(descriptor, mut body) if *descriptor == DESC_DISCHARGE => {
+                let mut decoder = FrameDecoder::new(&descriptor, &mut body)?;
+                let mut discharge = Discharge {
+                    txn_id: Vec::new(),
+                    fail: None,
+                };
+                decoder.decode_required(&mut discharge.txn_id)?;
+                decoder.decode_optional(&mut discharge.fail)?;
+                Ok(discharge)
+            }
+            _ => Err(AmqpError::decode_error(Some(
+                "Error converting value to Discharge",
+            ))),
+        }
+    }
+}
+
 impl TerminusDurability {
     pub fn from_int(input: u32) -> Result<TerminusDurability> {
         match input {
@@ -1163,6 +1349,80 @@ impl FrameHeader {
     }
 }
 
+impl Performative {
+    /// Decode a performative, returning `None` if the value is not a described type.
+    pub fn decode_with_limits(
+        reader: &mut dyn Read,
+        limits: &DecodeLimits,
+    ) -> Result<Option<Performative>> {
+        if let Value::Described(descriptor, mut value) = decode_value_with_limits(reader, limits)? {
+            let decoder = FrameDecoder::new(&descriptor, &mut value)?;
+            Some(match *descriptor {
+                DESC_OPEN => {
+                    let open = Open::decode(decoder)?;
+                    Ok(Performative::Open(open))
+                }
+                DESC_CLOSE => {
+                    let close = Close::decode(decoder)?;
+                    Ok(Performative::Close(close))
+                }
+                DESC_BEGIN => {
+                    let begin = Begin::decode(decoder)?;
+                    Ok(Performative::Begin(begin))
+                }
+                DESC_END => {
+                    let end = End::decode(decoder)?;
+                    Ok(Performative::End(end))
+                }
+                DESC_ATTACH => {
+                    let attach = Attach::decode(decoder)?;
+                    Ok(Performative::Attach(attach))
+                }
+                DESC_DETACH => {
+                    let detach = Detach::decode(decoder)?;
+                    Ok(Performative::Detach(detach))
+                }
+                DESC_FLOW => {
+                    let flow = Flow::decode(decoder)?;
+                    Ok(Performative::Flow(flow))
+                }
+                DESC_TRANSFER => {
+                    let transfer = Transfer::decode(decoder)?;
+                    Ok(Performative::Transfer(transfer))
+                }
+                DESC_DISPOSITION => {
+                    let disposition = Disposition::decode(decoder)?;
+                    Ok(Performative::Disposition(disposition))
+                }
+                v => Err(AmqpError::decode_error(Some(
+                    format!("Unexpected descriptor value: {:?}", v).as_str(),
+                ))),
+            })
+            .transpose()
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+impl SaslFrame {
+    /// Decode the body of a SASL frame.
+    pub fn decode_with_limits(reader: &mut dyn Read, limits: &DecodeLimits) -> Result<SaslFrame> {
+        if let Value::Described(descriptor, mut value) = decode_value_with_limits(reader, limits)? {
+            let decoder = FrameDecoder::new(&descriptor, &mut value)?;
+            match *descriptor {
+                DESC_SASL_MECHANISMS => {
+                    Ok(SaslFrame::SaslMechanisms(SaslMechanisms::decode(decoder)?))
+                }
+                DESC_SASL_OUTCOME => Ok(SaslFrame::SaslOutcome(SaslOutcome::decode(decoder)?)),
+                _ => Err(AmqpError::decode_error(Some("Error decoding sasl frame"))),
+            }
+        } else {
+            Err(AmqpError::framing_error(Some("Sasl frame not matched")))
+        }
+    }
+}
+
 impl Frame {
     pub fn encode(&self, writer: &mut dyn Write) -> Result<usize> {
         let mut header: FrameHeader = FrameHeader {
@@ -1242,6 +1502,15 @@ impl Frame {
     }
 
     pub fn decode(header: FrameHeader, reader: &mut Cursor<&mut &[u8]>) -> Result<Frame> {
+        Frame::decode_with_limits(header, reader, &DecodeLimits::default())
+    }
+
+    /// Decode a frame, enforcing the given limits when decoding the performative.
+    pub fn decode_with_limits(
+        header: FrameHeader,
+        reader: &mut Cursor<&mut &[u8]>,
+        limits: &DecodeLimits,
+    ) -> Result<Frame> {
         // Read off extended header not in use
         let mut doff = header.doff;
         while doff > 2 {
@@ -1251,52 +1520,7 @@ impl Frame {
 
         if header.frame_type == 0 {
             let performative = if header.size > 8 {
-                if let Value::Described(descriptor, mut value) = decode_value(reader)? {
-                    let decoder = FrameDecoder::new(&descriptor, &mut value)?;
-                    Some(match *descriptor {
-                        DESC_OPEN => {
-                            let open = Open::decode(decoder)?;
-                            Ok(Performative::Open(open))
-                        }
-                        DESC_CLOSE => {
-                            let close = Close::decode(decoder)?;
-                            Ok(Performative::Close(close))
-                        }
-                        DESC_BEGIN => {
-                            let begin = Begin::decode(decoder)?;
-                            Ok(Performative::Begin(begin))
-                        }
-                        DESC_END => {
-                            let end = End::decode(decoder)?;
-                            Ok(Performative::End(end))
-                        }
-                        DESC_ATTACH => {
-                            let attach = Attach::decode(decoder)?;
-                            Ok(Performative::Attach(attach))
-                        }
-                        DESC_DETACH => {
-                            let detach = Detach::decode(decoder)?;
-                            Ok(Performative::Detach(detach))
-                        }
-                        DESC_FLOW => {
-                            let flow = Flow::decode(decoder)?;
-                            Ok(Performative::Flow(flow))
-                        }
-                        DESC_TRANSFER => {
-                            let transfer = Transfer::decode(decoder)?;
-                            Ok(Performative::Transfer(transfer))
-                        }
-                        DESC_DISPOSITION => {
-                            let disposition = Disposition::decode(decoder)?;
-                            Ok(Performative::Disposition(disposition))
-                        }
-                        v => Err(AmqpError::decode_error(Some(
-                            format!("Unexpected descriptor value: {:?}", v).as_str(),
-                        ))),
-                    }?)
-                } else {
-                    None
-                }
+                Performative::decode_with_limits(reader, limits)?
             } else {
                 None
             };
@@ -1316,25 +1540,7 @@ impl Frame {
             }))
         } else if header.frame_type == 1 {
             if header.size > 8 {
-                if let Value::Described(descriptor, mut value) = decode_value(reader)? {
-                    let decoder = FrameDecoder::new(&descriptor, &mut value)?;
-                    let frame = match *descriptor {
-                        DESC_SASL_MECHANISMS => {
-                            Some(SaslFrame::SaslMechanisms(SaslMechanisms::decode(decoder)?))
-                        }
-                        DESC_SASL_OUTCOME => {
-                            Some(SaslFrame::SaslOutcome(SaslOutcome::decode(decoder)?))
-                        }
-                        _ => None,
-                    };
-
-                    match frame {
-                        Some(frame) => Ok(Frame::SASL(frame)),
-                        None => Err(AmqpError::decode_error(Some("Error decoding sasl frame"))),
-                    }
-                } else {
-                    Err(AmqpError::framing_error(Some("Sasl frame not matched")))
-                }
+                Ok(Frame::SASL(SaslFrame::decode_with_limits(reader, limits)?))
             } else {
                 Err(AmqpError::framing_error(Some("Sasl frame not matched")))
             }
@@ -1361,4 +1567,19 @@ mod tests {
         assert_eq!(None, frm.max_frame_size);
         assert_eq!(None, frm.channel_max);
     }
+
+    #[test]
+    fn check_transactional_state() {
+        let state = DeliveryState::TransactionalState(TransactionalState {
+            txn_id: vec![1, 2, 3],
+            outcome: Some(Box::new(DeliveryState::Accepted)),
+        });
+
+        let mut output: Vec<u8> = Vec::new();
+        state.encode(&mut output).unwrap();
+        let value = crate::decoding::decode_value(&mut &output[..]).unwrap();
+        let decoded = <DeliveryState as TryFromValue>::try_from(value).unwrap();
+
+        assert_eq!(state, decoded);
+    }
 }
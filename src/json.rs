@@ -0,0 +1,305 @@
+/*
+ * Copyright 2020, Ulf Lilleengen
+ * License: Apache License 2.0 (see the file LICENSE or http://apache.org/licenses/LICENSE-2.0.html).
+ */
+
+//! The json module implements a lossless mapping between AMQP values and JSON.
+//!
+//! Null, booleans, strings and lists map to their JSON counterparts. All other types are
+//! represented by an object with a single key naming the AMQP type:
+//!
+//! * Integers, timestamps (milliseconds since the epoch) and finite floating point values
+//!   are numbers, e.g. `{"ulong": 5}`. Non-finite floating point values are the strings
+//!   `"NaN"`, `"Infinity"` and `"-Infinity"`.
+//! * Decimals are the hexadecimal representation of their encoded bits.
+//! * Chars, symbols and uuids are strings, binary is base64 encoded. Symbols that are not valid
+//!   UTF-8 are kept byte-exact as base64 encoded binary, e.g. `{"symbol": {"binary": "/w=="}}`.
+//! * Maps are a list of key and value pairs, e.g. `{"map": [["key", "value"]]}`.
+//! * Arrays are a list of their elements, e.g. `{"array": [{"int": 1}]}`.
+//! * Described values are a pair of descriptor and value, e.g. `{"described": [{"ulong": 112}, []]}`.
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde_json::{Map, Number};
+use uuid::Uuid;
+
+use crate::decimal::*;
+use crate::error::*;
+use crate::types::*;
+
+/// Convert an AMQP value to JSON.
+pub fn to_json(value: &Value) -> serde_json::Value {
+    use serde_json::Value as Json;
+    match value {
+        Value::Null => Json::Null,
+        Value::Bool(v) => Json::Bool(*v),
+        Value::String(v) => Json::String(v.clone()),
+        Value::Str(v) => Json::String(v.to_string()),
+        Value::List(v) => Json::Array(v.iter().map(to_json).collect()),
+        Value::Ubyte(v) => tagged("ubyte", (*v).into()),
+        Value::Ushort(v) => tagged("ushort", (*v).into()),
+        Value::Uint(v) => tagged("uint", (*v).into()),
+        Value::Ulong(v) => tagged("ulong", (*v).into()),
+        Value::Byte(v) => tagged("byte", (*v).into()),
+        Value::Short(v) => tagged("short", (*v).into()),
+        Value::Int(v) => tagged("int", (*v).into()),
+        Value::Long(v) => tagged("long", (*v).into()),
+        Value::Float(v) => tagged("float", float_to_json(v.0 as f64)),
+        Value::Double(v) => tagged("double", float_to_json(v.0)),
+        Value::Decimal32(v) => tagged("decimal32", Json::String(format!("{:08x}", v.0))),
+        Value::Decimal64(v) => tagged("decimal64", Json::String(format!("{:016x}", v.0))),
+        Value::Decimal128(v) => tagged("decimal128", Json::String(format!("{:032x}", v.0))),
+        Value::Char(v) => tagged("char", Json::String(v.to_string())),
+        Value::Timestamp(v) => tagged("timestamp", (*v).into()),
+        Value::Uuid(v) => tagged("uuid", Json::String(v.hyphenated().to_string())),
+        Value::Binary(v) => binary_to_json(v),
+        Value::Symbol(v) => symbol_to_json(v),
+        Value::SymbolSlice(v) => symbol_to_json(v),
+        Value::Map(m) => map_to_json(m),
+        Value::Array(v) => tagged("array", Json::Array(v.iter().map(to_json).collect())),
+        Value::Described(descriptor, value) => tagged(
+            "described",
+            Json::Array(vec![to_json(descriptor), to_json(value)]),
+        ),
+    }
+}
+
+/// Convert JSON produced by [`to_json`] back to an AMQP value.
+pub fn from_json(json: &serde_json::Value) -> Result<Value> {
+    use serde_json::Value as Json;
+    match json {
+        Json::Null => Ok(Value::Null),
+        Json::Bool(v) => Ok(Value::Bool(*v)),
+        Json::String(v) => Ok(Value::String(v.clone())),
+        Json::Array(v) => Ok(Value::List(
+            v.iter().map(from_json).collect::<Result<Vec<Value>>>()?,
+        )),
+        Json::Number(_) => Err(json_error("Untagged number", json)),
+        Json::Object(o) => {
+            let (tag, value) = match (o.len(), o.iter().next()) {
+                (1, Some(entry)) => entry,
+                _ => return Err(json_error("Expected object with a single type key", json)),
+            };
+            match tag.as_str() {
+                "ubyte" => Ok(Value::Ubyte(int_from_json(value)?)),
+                "ushort" => Ok(Value::Ushort(int_from_json(value)?)),
+                "uint" => Ok(Value::Uint(int_from_json(value)?)),
+                "ulong" => Ok(Value::Ulong(int_from_json(value)?)),
+                "byte" => Ok(Value::Byte(int_from_json(value)?)),
+                "short" => Ok(Value::Short(int_from_json(value)?)),
+                "int" => Ok(Value::Int(int_from_json(value)?)),
+                "long" => Ok(Value::Long(int_from_json(value)?)),
+                "float" => Ok(Value::Float(Float(float_from_json(value)? as f32))),
+                "double" => Ok(Value::Double(Double(float_from_json(value)?))),
+                "decimal32" => Ok(Value::Decimal32(Decimal32(
+                    u32::from_str_radix(str_from_json(value)?, 16)
+                        .map_err(|_| json_error("Invalid decimal32", value))?,
+                ))),
+                "decimal64" => Ok(Value::Decimal64(Decimal64(
+                    u64::from_str_radix(str_from_json(value)?, 16)
+                        .map_err(|_| json_error("Invalid decimal64", value))?,
+                ))),
+                "decimal128" => Ok(Value::Decimal128(Decimal128(
+                    u128::from_str_radix(str_from_json(value)?, 16)
+                        .map_err(|_| json_error("Invalid decimal128", value))?,
+                ))),
+                "char" => {
+                    let mut chars = str_from_json(value)?.chars();
+                    match (chars.next(), chars.next()) {
+                        (Some(c), None) => Ok(Value::Char(c)),
+                        _ => Err(json_error("Expected a single character", value)),
+                    }
+                }
+                "timestamp" => Ok(Value::Timestamp(int_from_json(value)?)),
+                "uuid" => Ok(Value::Uuid(
+                    Uuid::parse_str(str_from_json(value)?)
+                        .map_err(|_| json_error("Invalid uuid", value))?,
+                )),
+                "binary" => Ok(Value::Binary(
+                    STANDARD
+                        .decode(str_from_json(value)?)
+                        .map_err(|_| json_error("Invalid base64", value))?,
+                )),
+                "symbol" => match value {
+                    Json::String(v) => Ok(Value::Symbol(v.as_bytes().to_vec())),
+                    _ => match from_json(value)? {
+                        Value::Binary(v) => Ok(Value::Symbol(v)),
+                        _ => Err(json_error("Expected string or binary symbol", value)),
+                    },
+                },
+                "map" => {
+                    let mut m = Vec::new();
+                    for entry in array_from_json(value)?.iter() {
+                        match entry {
+                            Json::Array(pair) if pair.len() == 2 => {
+                                m.push((from_json(&pair[0])?, from_json(&pair[1])?));
+                            }
+                            _ => return Err(json_error("Expected key and value pair", entry)),
+                        }
+                    }
+                    Ok(Value::Map(m))
+                }
+                "array" => Ok(Value::Array(
+                    array_from_json(value)?
+                        .iter()
+                        .map(from_json)
+                        .collect::<Result<Vec<Value>>>()?,
+                )),
+                "described" => match array_from_json(value)?.as_slice() {
+                    [descriptor, value] => Ok(Value::Described(
+                        Box::new(from_json(descriptor)?),
+                        Box::new(from_json(value)?),
+                    )),
+                    _ => Err(json_error("Expected descriptor and value pair", value)),
+                },
+                _ => Err(json_error("Unknown type", json)),
+            }
+        }
+    }
+}
+
+/// Convert binary data to JSON without copying it into a value first.
+pub(crate) fn binary_to_json(v: &[u8]) -> serde_json::Value {
+    tagged("binary", serde_json::Value::String(STANDARD.encode(v)))
+}
+
+/// Convert map entries to JSON without copying them into a value first.
+pub(crate) fn map_to_json(m: &[(Value, Value)]) -> serde_json::Value {
+    tagged(
+        "map",
+        serde_json::Value::Array(
+            m.iter()
+                .map(|(k, v)| serde_json::Value::Array(vec![to_json(k), to_json(v)]))
+                .collect(),
+        ),
+    )
+}
+
+fn symbol_to_json(v: &[u8]) -> serde_json::Value {
+    match std::str::from_utf8(v) {
+        Ok(s) => tagged("symbol", serde_json::Value::String(s.to_string())),
+        Err(_) => tagged("symbol", binary_to_json(v)),
+    }
+}
+
+fn tagged(tag: &str, value: serde_json::Value) -> serde_json::Value {
+    let mut m = Map::new();
+    m.insert(tag.to_string(), value);
+    serde_json::Value::Object(m)
+}
+
+fn float_to_json(v: f64) -> serde_json::Value {
+    match Number::from_f64(v) {
+        Some(n) => serde_json::Value::Number(n),
+        None if v.is_nan() => serde_json::Value::String("NaN".to_string()),
+        None if v > 0.0 => serde_json::Value::String("Infinity".to_string()),
+        None => serde_json::Value::String("-Infinity".to_string()),
+    }
+}
+
+fn float_from_json(json: &serde_json::Value) -> Result<f64> {
+    match json {
+        serde_json::Value::Number(n) => n.as_f64().ok_or_else(|| json_error("Invalid float", json)),
+        serde_json::Value::String(s) => match s.as_str() {
+            "NaN" => Ok(f64::NAN),
+            "Infinity" => Ok(f64::INFINITY),
+            "-Infinity" => Ok(f64::NEG_INFINITY),
+            _ => Err(json_error("Invalid float", json)),
+        },
+        _ => Err(json_error("Expected number", json)),
+    }
+}
+
+fn int_from_json<T: TryFrom<i64> + TryFrom<u64>>(json: &serde_json::Value) -> Result<T> {
+    let value = match json {
+        serde_json::Value::Number(n) => match (n.as_u64(), n.as_i64()) {
+            (Some(v), _) => T::try_from(v).ok(),
+            (None, Some(v)) => T::try_from(v).ok(),
+            _ => None,
+        },
+        _ => None,
+    };
+    value.ok_or_else(|| json_error("Integer out of range", json))
+}
+
+fn str_from_json(json: &serde_json::Value) -> Result<&str> {
+    json.as_str()
+        .ok_or_else(|| json_error("Expected string", json))
+}
+
+fn array_from_json(json: &serde_json::Value) -> Result<&Vec<serde_json::Value>> {
+    json.as_array()
+        .ok_or_else(|| json_error("Expected array", json))
+}
+
+fn json_error(message: &str, json: &serde_json::Value) -> AmqpError {
+    AmqpError::decode_error(Some(format!("{}: {}", message, json).as_str()))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn check_roundtrip() {
+        let values = vec![
+            Value::Null,
+            Value::Bool(true),
+            Value::Ubyte(1),
+            Value::Ushort(2),
+            Value::Uint(3),
+            Value::Ulong(u64::MAX),
+            Value::Byte(-1),
+            Value::Short(-2),
+            Value::Int(-3),
+            Value::Long(i64::MIN),
+            Value::Float(Float(1.5)),
+            Value::Double(Double(f64::NEG_INFINITY)),
+            Value::Decimal32(Decimal32(0x2234_5678)),
+            Value::Decimal64(Decimal64(u64::MAX)),
+            Value::Decimal128(Decimal128(u128::MAX)),
+            Value::Char('ø'),
+            Value::Timestamp(1_600_000_000_000),
+            Value::Uuid(Uuid::from_u128(0x936da01f_9abd_4d9d_80c7_02af85c822a8)),
+            Value::Binary(vec![0, 1, 2, 255]),
+            Value::String(String::from("hello")),
+            Value::Symbol(b"hello".to_vec()),
+            Value::Symbol(vec![0x68, 0xff]),
+            Value::List(vec![Value::Int(1), Value::String(String::from("two"))]),
+            Value::Map(vec![(
+                Value::Symbol(b"key".to_vec()),
+                Value::Map(vec![(Value::Null, Value::Bool(false))]),
+            )]),
+            Value::Array(vec![Value::Int(1), Value::Int(2)]),
+            Value::Described(
+                Box::new(Value::Ulong(0x70)),
+                Box::new(Value::List(vec![Value::Bool(true)])),
+            ),
+        ];
+        for value in values.into_iter() {
+            let json = to_json(&value);
+            let text = json.to_string();
+            let parsed: serde_json::Value = serde_json::from_str(&text).unwrap();
+            assert_eq!(value, from_json(&parsed).unwrap(), "{}", text);
+        }
+
+        let json = to_json(&Value::Double(Double(f64::NAN)));
+        match from_json(&json).unwrap() {
+            Value::Double(d) => assert!(d.0.is_nan()),
+            v => panic!("unexpected value {:?}", v),
+        }
+
+        assert_eq!(
+            r#"{"map":[[{"symbol":"a"},{"binary":"AQI="}]]}"#,
+            to_json(&Value::Map(vec![(
+                Value::Symbol(b"a".to_vec()),
+                Value::Binary(vec![1, 2])
+            )]))
+            .to_string()
+        );
+        assert!(from_json(&serde_json::json!(1)).is_err());
+        assert!(from_json(&serde_json::json!({"ubyte": 256})).is_err());
+        assert!(from_json(&serde_json::json!({"ubyte": 1, "int": 2})).is_err());
+    }
+}
@@ -82,20 +82,41 @@
 #[macro_use]
 extern crate log;
 
+// Allows code generated by the derive macros to refer to this crate as `dove`.
+extern crate self as dove;
+
+#[cfg(feature = "derive")]
+pub use dove_derive::AmqpComposite;
+
 pub mod conn;
 pub mod connection;
 pub mod container;
 pub mod convert;
+pub mod decimal;
 pub mod decoding;
 pub mod driver;
 pub mod encoding;
 pub mod error;
 pub mod frame_codec;
 pub mod framing;
+#[cfg(feature = "json")]
+pub mod json;
 pub mod message;
 pub mod options;
+pub mod registry;
+#[cfg(any(feature = "tokio", feature = "async-io"))]
+pub mod runtime;
 pub mod sasl;
+#[cfg(feature = "serde")]
+pub mod serde_value;
+pub mod streaming;
 pub mod symbol;
+#[cfg(test)]
+mod testing;
+#[cfg(feature = "tls")]
+pub mod tls;
 pub mod transport;
 pub mod types;
 pub mod url;
+#[cfg(feature = "websocket")]
+pub mod websocket;
@@ -5,9 +5,10 @@
 
 //! The message module implements the AMQP 1.0 message format encoding and decoding.
 
-use byteorder::WriteBytesExt;
+use byteorder::{ReadBytesExt, WriteBytesExt};
 use std::io::Cursor;
 use std::io::Write;
+use std::ops::Range;
 use std::vec::Vec;
 
 use crate::decoding::*;
@@ -16,6 +17,23 @@ use crate::frame_codec::*;
 use crate::symbol::*;
 use crate::types::*;
 
+/// Section names used by [`Message::to_json`] and [`Message::from_json`].
+#[cfg(feature = "json")]
+const JSON_SECTIONS: [(&str, Value); 9] = [
+    ("header", DESC_MESSAGE_HEADER),
+    ("delivery_annotations", DESC_MESSAGE_DELIVERY_ANNOTATIONS),
+    ("message_annotations", DESC_MESSAGE_ANNOTATIONS),
+    ("properties", DESC_MESSAGE_PROPERTIES),
+    (
+        "application_properties",
+        DESC_MESSAGE_APPLICATION_PROPERTIES,
+    ),
+    ("data", DESC_MESSAGE_AMQP_DATA),
+    ("amqp_sequence", DESC_MESSAGE_AMQP_SEQUENCE),
+    ("amqp_value", DESC_MESSAGE_AMQP_VALUE),
+    ("footer", DESC_MESSAGE_FOOTER),
+];
+
 #[derive(Debug, Clone)]
 pub struct Message {
     pub header: Option<MessageHeader>,
@@ -27,6 +45,41 @@ pub struct Message {
     pub footer: Option<Vec<(Value, Value)>>,
 }
 
+/// A message together with the sections whose descriptors are not defined by the
+/// specification, such as vendor extensions. Unknown sections are ignored by
+/// [`Message::decode`], but are preserved here and encoded in their original order.
+#[derive(Debug, Clone)]
+pub struct ExtendedMessage {
+    pub message: Message,
+    pub unknown_sections: Vec<UnknownSection>,
+}
+
+/// A message section with a descriptor not defined by the specification.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnknownSection {
+    /// The index of the section among all sections of the encoded message.
+    pub position: usize,
+    /// The section as a described value.
+    pub value: Value,
+}
+
+/// A message kept in its encoded form. The payload is indexed on creation, and individual
+/// sections are only decoded when accessed. The message can be forwarded byte-for-byte
+/// using [`LazyMessage::encode`] or [`LazyMessage::as_bytes`].
+#[derive(Debug, Clone)]
+pub struct LazyMessage {
+    payload: Vec<u8>,
+    sections: Vec<SectionIndex>,
+    limits: DecodeLimits,
+}
+
+#[derive(Debug, Clone)]
+struct SectionIndex {
+    descriptor: Value,
+    section: Range<usize>,
+    value: Range<usize>,
+}
+
 #[derive(Debug, Clone)]
 pub struct MessageHeader {
     pub durable: Option<bool>,
@@ -69,10 +122,44 @@ impl MessageBody {
             _ => None,
         }
     }
+
+    /// Deserialize the body into any deserializable type. An AMQP sequence is deserialized
+    /// as a list, and data sections as binary.
+    #[cfg(feature = "serde")]
+    pub fn deserialize<T: serde::de::DeserializeOwned>(&self) -> Result<T> {
+        let value = match self {
+            MessageBody::AmqpValue(v) => v.clone(),
+            MessageBody::AmqpSequence(v) => Value::List(v.clone()),
+            MessageBody::Data(v) => Value::Binary(v.clone()),
+        };
+        crate::serde_value::from_value(value)
+    }
 }
 
 impl Message {
+    /// Create a message with an AMQP value body serialized from the given value.
+    #[cfg(feature = "serde")]
+    pub fn serialize_value<T: serde::Serialize + ?Sized>(value: &T) -> Result<Message> {
+        Ok(Message::amqp_value(crate::serde_value::to_value(value)?))
+    }
+
+    /// Create a message with an AMQP sequence body serialized from the given value, which
+    /// must serialize into a list (a sequence, tuple or tuple struct).
+    #[cfg(feature = "serde")]
+    pub fn serialize_sequence<T: serde::Serialize + ?Sized>(value: &T) -> Result<Message> {
+        match crate::serde_value::to_value(value)? {
+            Value::List(values) => Ok(Message::with_body(MessageBody::AmqpSequence(values))),
+            _ => Err(AmqpError::generic(
+                "AMQP sequence body must be serialized from a sequence",
+            )),
+        }
+    }
+
     pub fn amqp_value(value: Value) -> Message {
+        Message::with_body(MessageBody::AmqpValue(value))
+    }
+
+    fn with_body(body: MessageBody) -> Message {
         Message {
             header: Some(MessageHeader {
                 durable: Some(false),
@@ -85,15 +172,114 @@ impl Message {
             message_annotations: None,
             properties: None,
             application_properties: None,
-            body: MessageBody::AmqpValue(value),
+            body,
             footer: None,
         }
     }
 
+    /// Render the message as a JSON object keyed by section name, using the mapping of
+    /// [`crate::json`] for section contents. The header and properties are objects keyed
+    /// by field name.
+    #[cfg(feature = "json")]
+    pub fn to_json(&self) -> Result<serde_json::Value> {
+        use crate::json::{binary_to_json, map_to_json, to_json};
+        let mut sections = serde_json::Map::new();
+        if let Some(ref header) = self.header {
+            sections.insert("header".to_string(), header.to_json());
+        }
+        if let Some(ref delivery_annotations) = self.delivery_annotations {
+            sections.insert(
+                "delivery_annotations".to_string(),
+                map_to_json(delivery_annotations),
+            );
+        }
+        if let Some(ref message_annotations) = self.message_annotations {
+            sections.insert(
+                "message_annotations".to_string(),
+                map_to_json(message_annotations),
+            );
+        }
+        if let Some(ref properties) = self.properties {
+            sections.insert("properties".to_string(), properties.to_json());
+        }
+        if let Some(ref application_properties) = self.application_properties {
+            sections.insert(
+                "application_properties".to_string(),
+                map_to_json(application_properties),
+            );
+        }
+        let (name, body) = match &self.body {
+            MessageBody::Data(data) => ("data", binary_to_json(data)),
+            MessageBody::AmqpSequence(values) => (
+                "amqp_sequence",
+                serde_json::Value::Array(values.iter().map(to_json).collect()),
+            ),
+            MessageBody::AmqpValue(value) => ("amqp_value", to_json(value)),
+        };
+        sections.insert(name.to_string(), body);
+        if let Some(ref footer) = self.footer {
+            sections.insert("footer".to_string(), map_to_json(footer));
+        }
+        Ok(serde_json::Value::Object(sections))
+    }
+
+    /// Create a message from JSON produced by [`Message::to_json`].
+    #[cfg(feature = "json")]
+    pub fn from_json(json: &serde_json::Value) -> Result<Message> {
+        let sections = json
+            .as_object()
+            .ok_or_else(|| AmqpError::decode_error(Some("Expected message object")))?;
+        let mut message = Message::empty();
+        for (name, value) in sections.iter() {
+            match name.as_str() {
+                "header" => {
+                    message.header = Some(MessageHeader::from_json(value)?);
+                    continue;
+                }
+                "properties" => {
+                    message.properties = Some(MessageProperties::from_json(value)?);
+                    continue;
+                }
+                _ => {}
+            }
+            let descriptor = JSON_SECTIONS
+                .iter()
+                .find(|(n, _)| n == name)
+                .map(|(_, d)| d.clone())
+                .ok_or_else(|| {
+                    AmqpError::decode_error(Some(format!("Unknown section {}", name).as_str()))
+                })?;
+            message.decode_section(
+                Box::new(descriptor),
+                Box::new(crate::json::from_json(value)?),
+            )?;
+        }
+        Ok(message)
+    }
+
     pub fn decode(reader: &mut Vec<u8>) -> Result<Message> {
+        Message::decode_with_limits(reader, &DecodeLimits::default())
+    }
+
+    /// Decode a message, enforcing the given limits on each section.
+    pub fn decode_with_limits(reader: &mut Vec<u8>, limits: &DecodeLimits) -> Result<Message> {
         let len = reader.len() as u64;
         let mut cursor = Cursor::new(reader);
-        let mut message = Message {
+        let mut message = Message::empty();
+        while cursor.position() < len {
+            if let Value::Described(descriptor, value) =
+                decode_value_with_limits(&mut cursor, limits)?
+            {
+                message.decode_section(descriptor, value)?;
+            } else {
+                break;
+            }
+        }
+        Ok(message)
+    }
+
+    fn empty() -> Message {
+        Message {
             header: None,
             delivery_annotations: None,
             message_annotations: None,
@@ -101,61 +287,82 @@ impl Message {
             application_properties: None,
             body: MessageBody::AmqpValue(Value::Null),
             footer: None,
-        };
-        while cursor.position() < len {
-            if let Value::Described(descriptor, mut value) = decode_value(&mut cursor)? {
-                match *descriptor {
-                    DESC_MESSAGE_HEADER => {
-                        let decoder = FrameDecoder::new(&descriptor, &mut value)?;
-                        message.header = Some(MessageHeader::decode(decoder)?);
-                    }
-                    DESC_MESSAGE_DELIVERY_ANNOTATIONS => {
-                        if let Value::Map(m) = *value {
-                            message.delivery_annotations = Some(m);
-                        }
-                    }
+        }
+    }
 
-                    DESC_MESSAGE_ANNOTATIONS => {
-                        if let Value::Map(m) = *value {
-                            message.message_annotations = Some(m);
-                        }
-                    }
-                    DESC_MESSAGE_PROPERTIES => {
-                        let decoder = FrameDecoder::new(&descriptor, &mut value)?;
-                        message.properties = Some(MessageProperties::decode(decoder)?);
-                    }
-                    DESC_MESSAGE_APPLICATION_PROPERTIES => {
-                        if let Value::Map(m) = *value {
-                            message.application_properties = Some(m);
-                        }
-                    }
-                    DESC_MESSAGE_AMQP_DATA => {
-                        if let Value::Binary(d) = *value {
-                            message.body = MessageBody::Data(d);
-                        }
-                    }
-                    DESC_MESSAGE_AMQP_SEQUENCE => {
-                        if let Value::List(l) = *value {
-                            message.body = MessageBody::AmqpSequence(l);
-                        }
-                    }
-                    DESC_MESSAGE_AMQP_VALUE => {
-                        message.body = MessageBody::AmqpValue(*value);
-                    }
-                    DESC_MESSAGE_FOOTER => {
-                        if let Value::Map(m) = *value {
-                            message.footer = Some(m);
-                        }
-                    }
-                    _ => {
-                        return Err(AmqpError::framing_error(None));
+    /// Decode a single message section into this message. Sections with unknown descriptors
+    /// are returned as described values.
+    fn decode_section(
+        &mut self,
+        descriptor: Box<Value>,
+        mut value: Box<Value>,
+    ) -> Result<Option<Value>> {
+        match *descriptor {
+            DESC_MESSAGE_HEADER => {
+                let decoder = FrameDecoder::new(&descriptor, &mut value)?;
+                self.header = Some(MessageHeader::decode(decoder)?);
+            }
+            DESC_MESSAGE_DELIVERY_ANNOTATIONS => {
+                if let Value::Map(m) = *value {
+                    self.delivery_annotations = Some(m);
+                }
+            }
+
+            DESC_MESSAGE_ANNOTATIONS => {
+                if let Value::Map(m) = *value {
+                    self.message_annotations = Some(m);
+                }
+            }
+            DESC_MESSAGE_PROPERTIES => {
+                let decoder = FrameDecoder::new(&descriptor, &mut value)?;
+                self.properties = Some(MessageProperties::decode(decoder)?);
+            }
+            DESC_MESSAGE_APPLICATION_PROPERTIES => {
+                if let Value::Map(m) = *value {
+                    self.application_properties = Some(m);
+                }
+            }
+            DESC_MESSAGE_AMQP_DATA => {
+                if let Value::Binary(d) = *value {
+                    // The data of consecutive data sections forms the body
+                    match &mut self.body {
+                        MessageBody::Data(data) => data.extend_from_slice(&d[..]),
+                        body => *body = MessageBody::Data(d),
                     }
                 }
-            } else {
-                break;
             }
+            DESC_MESSAGE_AMQP_SEQUENCE => {
+                if let Value::List(l) = *value {
+                    self.body = MessageBody::AmqpSequence(l);
+                }
+            }
+            DESC_MESSAGE_AMQP_VALUE => {
+                self.body = MessageBody::AmqpValue(*value);
+            }
+            DESC_MESSAGE_FOOTER => {
+                if let Value::Map(m) = *value {
+                    self.footer = Some(m);
+                }
+            }
+            _ => return Ok(Some(Value::Described(descriptor, value))),
         }
-        Ok(message)
+        Ok(None)
+    }
+
+    /// Encode the message, encoding each section using the given options. The canonical mode
+    /// allows sections to be signed or hashed.
+    pub fn encode_with_options(
+        &self,
+        writer: &mut dyn Write,
+        options: &EncodeOptions,
+    ) -> Result<()> {
+        let mut buf = Vec::new();
+        self.encode(&mut buf)?;
+        let mut input = &buf[..];
+        while !input.is_empty() {
+            decode_value(&mut input)?.encode_with_options(writer, options)?;
+        }
+        Ok(())
     }
 
     pub fn encode(&self, writer: &mut dyn Write) -> Result<()> {
@@ -190,6 +397,368 @@ impl Message {
     }
 }
 
+impl ExtendedMessage {
+    pub fn decode(reader: &mut Vec<u8>) -> Result<ExtendedMessage> {
+        ExtendedMessage::decode_with_limits(reader, &DecodeLimits::default())
+    }
+
+    /// Decode a message, enforcing the given limits on each section.
+    pub fn decode_with_limits(
+        reader: &mut Vec<u8>,
+        limits: &DecodeLimits,
+    ) -> Result<ExtendedMessage> {
+        let len = reader.len() as u64;
+        let mut cursor = Cursor::new(reader);
+        let mut message = Message::empty();
+        let mut unknown_sections = Vec::new();
+        let mut position = 0;
+        while cursor.position() < len {
+            if let Value::Described(descriptor, value) =
+                decode_value_with_limits(&mut cursor, limits)?
+            {
+                if let Some(value) = message.decode_section(descriptor, value)? {
+                    unknown_sections.push(UnknownSection { position, value });
+                }
+                position += 1;
+            } else {
+                break;
+            }
+        }
+        Ok(ExtendedMessage {
+            message,
+            unknown_sections,
+        })
+    }
+
+    /// Encode the message, placing each unknown section at its recorded position among
+    /// the sections of the message.
+    pub fn encode(&self, writer: &mut dyn Write) -> Result<()> {
+        let mut buf = Vec::new();
+        self.message.encode(&mut buf)?;
+        let mut input = &buf[..];
+        let mut known = Vec::new();
+        while !input.is_empty() {
+            known.push(split_value(&mut input)?);
+        }
+
+        let mut unknown: Vec<&UnknownSection> = self.unknown_sections.iter().collect();
+        unknown.sort_by_key(|section| section.position);
+        let mut unknown = unknown.into_iter().peekable();
+        let mut position = 0;
+        for section in known.into_iter() {
+            while let Some(u) = unknown.next_if(|u| u.position <= position) {
+                u.value.encode(writer)?;
+                position += 1;
+            }
+            writer.write_all(section)?;
+            position += 1;
+        }
+        for u in unknown {
+            u.value.encode(writer)?;
+        }
+        Ok(())
+    }
+}
+
+impl LazyMessage {
+    /// Index the sections of an encoded message without decoding them.
+    pub fn new(payload: Vec<u8>) -> Result<LazyMessage> {
+        LazyMessage::with_limits(payload, DecodeLimits::default())
+    }
+
+    /// Index the sections of an encoded message, enforcing the given limits when indexing
+    /// and when decoding sections later on.
+    pub fn with_limits(payload: Vec<u8>, limits: DecodeLimits) -> Result<LazyMessage> {
+        let mut sections = Vec::new();
+        let mut input = &payload[..];
+        while !input.is_empty() {
+            let start = payload.len() - input.len();
+            // Decode only the descriptor and skip over the section value
+            if input.read_u8()? != TypeCode::Described as u8 {
+                return Err(AmqpError::framing_error(None));
+            }
+            let descriptor = decode_value_with_limits(&mut input, &limits)?;
+            let value = payload.len() - input.len();
+            split_value_with_limits(&mut input, &limits)?;
+            let end = payload.len() - input.len();
+            sections.push(SectionIndex {
+                descriptor,
+                section: start..end,
+                value: value..end,
+            });
+        }
+        Ok(LazyMessage {
+            payload,
+            sections,
+            limits,
+        })
+    }
+
+    /// The encoded message.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.payload[..]
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.payload
+    }
+
+    /// Write the message exactly as it was received.
+    pub fn encode(&self, writer: &mut dyn Write) -> Result<()> {
+        writer.write_all(&self.payload[..])?;
+        Ok(())
+    }
+
+    /// Iterate over the descriptors and encoded bytes of all sections in the message.
+    pub fn sections(&self) -> impl Iterator<Item = (&Value, &[u8])> {
+        self.sections
+            .iter()
+            .map(move |s| (&s.descriptor, &self.payload[s.section.clone()]))
+    }
+
+    /// Decode the full message.
+    pub fn decode(&self) -> Result<Message> {
+        let mut message = Message::empty();
+        for index in self.sections.iter() {
+            self.decode_into(&mut message, index)?;
+        }
+        Ok(message)
+    }
+
+    pub fn header(&self) -> Result<Option<MessageHeader>> {
+        Ok(self.decode_matching(&[DESC_MESSAGE_HEADER])?.header)
+    }
+
+    pub fn properties(&self) -> Result<Option<MessageProperties>> {
+        Ok(self.decode_matching(&[DESC_MESSAGE_PROPERTIES])?.properties)
+    }
+
+    pub fn body(&self) -> Result<MessageBody> {
+        Ok(self
+            .decode_matching(&[
+                DESC_MESSAGE_AMQP_DATA,
+                DESC_MESSAGE_AMQP_SEQUENCE,
+                DESC_MESSAGE_AMQP_VALUE,
+            ])?
+            .body)
+    }
+
+    /// The delivery annotations map, borrowed from the payload.
+    pub fn delivery_annotations(&self) -> Result<Option<BorrowedValue<'_>>> {
+        self.borrowed_value(&DESC_MESSAGE_DELIVERY_ANNOTATIONS)
+    }
+
+    /// The message annotations map, borrowed from the payload.
+    pub fn message_annotations(&self) -> Result<Option<BorrowedValue<'_>>> {
+        self.borrowed_value(&DESC_MESSAGE_ANNOTATIONS)
+    }
+
+    /// The application properties map, borrowed from the payload.
+    pub fn application_properties(&self) -> Result<Option<BorrowedValue<'_>>> {
+        self.borrowed_value(&DESC_MESSAGE_APPLICATION_PROPERTIES)
+    }
+
+    /// The footer map, borrowed from the payload.
+    pub fn footer(&self) -> Result<Option<BorrowedValue<'_>>> {
+        self.borrowed_value(&DESC_MESSAGE_FOOTER)
+    }
+
+    /// Look up a single message annotation by its symbol key.
+    pub fn message_annotation(&self, key: &str) -> Result<Option<BorrowedValue<'_>>> {
+        if let Some(BorrowedValue::Map(entries)) = self.message_annotations()? {
+            for (k, v) in entries.into_iter() {
+                match k {
+                    BorrowedValue::Symbol(k) if k == key.as_bytes() => return Ok(Some(v)),
+                    _ => {}
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    fn borrowed_value(&self, descriptor: &Value) -> Result<Option<BorrowedValue<'_>>> {
+        match self.sections.iter().find(|s| s.descriptor == *descriptor) {
+            Some(index) => {
+                let mut input = &self.payload[index.value.clone()];
+                Ok(Some(decode_borrowed_value_with_limits(
+                    &mut input,
+                    &self.limits,
+                )?))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Decode the sections matching any of the descriptors, in order, into an otherwise empty
+    /// message, so that the result is the same as for [`Message::decode`].
+    fn decode_matching(&self, descriptors: &[Value]) -> Result<Message> {
+        let mut message = Message::empty();
+        for index in self
+            .sections
+            .iter()
+            .filter(|s| descriptors.contains(&s.descriptor))
+        {
+            self.decode_into(&mut message, index)?;
+        }
+        Ok(message)
+    }
+
+    fn decode_into(&self, message: &mut Message, index: &SectionIndex) -> Result<()> {
+        let value =
+            decode_value_with_limits(&mut &self.payload[index.value.clone()], &self.limits)?;
+        message.decode_section(Box::new(index.descriptor.clone()), Box::new(value))?;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "json")]
+impl MessageHeader {
+    fn to_json(&self) -> serde_json::Value {
+        json_fields(vec![
+            ("durable", self.durable.map(Value::Bool)),
+            ("priority", self.priority.map(Value::Ubyte)),
+            ("ttl", self.ttl.map(Value::Uint)),
+            ("first_acquirer", self.first_acquirer.map(Value::Bool)),
+            ("delivery_count", self.delivery_count.map(Value::Uint)),
+        ])
+    }
+
+    fn from_json(json: &serde_json::Value) -> Result<MessageHeader> {
+        let fields = json_object(
+            json,
+            &[
+                "durable",
+                "priority",
+                "ttl",
+                "first_acquirer",
+                "delivery_count",
+            ],
+        )?;
+        Ok(MessageHeader {
+            durable: json_field(fields, "durable")?,
+            priority: json_field(fields, "priority")?,
+            ttl: json_field(fields, "ttl")?,
+            first_acquirer: json_field(fields, "first_acquirer")?,
+            delivery_count: json_field(fields, "delivery_count")?,
+        })
+    }
+}
+
+#[cfg(feature = "json")]
+impl MessageProperties {
+    fn to_json(&self) -> serde_json::Value {
+        let symbol = |s: &Symbol| Value::Symbol(s.as_slice().to_vec());
+        json_fields(vec![
+            ("message_id", self.message_id.clone()),
+            ("user_id", self.user_id.clone().map(Value::Binary)),
+            ("to", self.to.clone().map(Value::String)),
+            ("subject", self.subject.clone().map(Value::String)),
+            ("reply_to", self.reply_to.clone().map(Value::String)),
+            ("correlation_id", self.correlation_id.clone()),
+            ("content_type", self.content_type.as_ref().map(symbol)),
+            (
+                "content_encoding",
+                self.content_encoding.as_ref().map(symbol),
+            ),
+            (
+                "absolute_expiry_time",
+                self.absolute_expiry_time
+                    .as_ref()
+                    .map(|t| Value::Timestamp(t.0)),
+            ),
+            (
+                "creation_time",
+                self.creation_time.as_ref().map(|t| Value::Timestamp(t.0)),
+            ),
+            ("group_id", self.group_id.clone().map(Value::String)),
+            ("group_sequence", self.group_sequence.map(Value::Uint)),
+            (
+                "reply_to_group_id",
+                self.reply_to_group_id.clone().map(Value::String),
+            ),
+        ])
+    }
+
+    fn from_json(json: &serde_json::Value) -> Result<MessageProperties> {
+        let fields = json_object(
+            json,
+            &[
+                "message_id",
+                "user_id",
+                "to",
+                "subject",
+                "reply_to",
+                "correlation_id",
+                "content_type",
+                "content_encoding",
+                "absolute_expiry_time",
+                "creation_time",
+                "group_id",
+                "group_sequence",
+                "reply_to_group_id",
+            ],
+        )?;
+        Ok(MessageProperties {
+            message_id: json_field(fields, "message_id")?,
+            user_id: json_field(fields, "user_id")?,
+            to: json_field(fields, "to")?,
+            subject: json_field(fields, "subject")?,
+            reply_to: json_field(fields, "reply_to")?,
+            correlation_id: json_field(fields, "correlation_id")?,
+            content_type: json_field(fields, "content_type")?,
+            content_encoding: json_field(fields, "content_encoding")?,
+            absolute_expiry_time: json_field(fields, "absolute_expiry_time")?,
+            creation_time: json_field(fields, "creation_time")?,
+            group_id: json_field(fields, "group_id")?,
+            group_sequence: json_field(fields, "group_sequence")?,
+            reply_to_group_id: json_field(fields, "reply_to_group_id")?,
+        })
+    }
+}
+
+/// Render the fields that are set as a JSON object keyed by field name.
+#[cfg(feature = "json")]
+fn json_fields(fields: Vec<(&str, Option<Value>)>) -> serde_json::Value {
+    serde_json::Value::Object(
+        fields
+            .into_iter()
+            .filter_map(|(name, value)| {
+                value.map(|value| (name.to_string(), crate::json::to_json(&value)))
+            })
+            .collect(),
+    )
+}
+
+/// The JSON object of a section, rejecting fields that are not part of the section.
+#[cfg(feature = "json")]
+fn json_object<'a>(
+    json: &'a serde_json::Value,
+    names: &[&str],
+) -> Result<&'a serde_json::Map<String, serde_json::Value>> {
+    let fields = json
+        .as_object()
+        .ok_or_else(|| AmqpError::decode_error(Some("Expected section object")))?;
+    match fields.keys().find(|name| !names.contains(&name.as_str())) {
+        Some(name) => Err(AmqpError::decode_error(Some(
+            format!("Unknown field {}", name).as_str(),
+        ))),
+        None => Ok(fields),
+    }
+}
+
+#[cfg(feature = "json")]
+fn json_field<T: crate::convert::TryFromValue>(
+    fields: &serde_json::Map<String, serde_json::Value>,
+    name: &str,
+) -> Result<Option<T>> {
+    match fields.get(name) {
+        Some(json) => {
+            <Option<T> as crate::convert::TryFromValue>::try_from(crate::json::from_json(json)?)
+        }
+        None => Ok(None),
+    }
+}
+
 impl MessageHeader {
     pub fn encode(&self, writer: &mut dyn Write) -> Result<()> {
         let mut encoder = FrameEncoder::new(DESC_MESSAGE_HEADER);
@@ -283,9 +852,7 @@ impl MessageBody {
             MessageBody::AmqpSequence(values) => {
                 writer.write_u8(0)?;
                 DESC_MESSAGE_AMQP_SEQUENCE.encode(writer)?;
-                for value in values.iter() {
-                    value.encode(writer)?;
-                }
+                ValueRef::List(values).encode(writer)?;
             }
             MessageBody::Data(data) => {
                 writer.write_u8(0)?;
@@ -296,3 +863,246 @@ impl MessageBody {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use uuid::Uuid;
+
+    #[test]
+    fn check_uuid_properties() {
+        let id = Uuid::from_u128(0x936da01f_9abd_4d9d_80c7_02af85c822a8);
+        let mut message = Message::amqp_value(Value::String(String::from("Hello, world")));
+        message.properties = Some(MessageProperties {
+            message_id: Some(Value::Uuid(id)),
+            user_id: None,
+            to: None,
+            subject: None,
+            reply_to: None,
+            correlation_id: Some(Value::Uuid(id)),
+            content_type: None,
+            content_encoding: None,
+            absolute_expiry_time: None,
+            creation_time: None,
+            group_id: None,
+            group_sequence: None,
+            reply_to_group_id: None,
+        });
+
+        let mut output: Vec<u8> = Vec::new();
+        message.encode(&mut output).unwrap();
+        let decoded = Message::decode(&mut output).unwrap();
+
+        let properties = decoded.properties.unwrap();
+        assert_eq!(Some(Value::Uuid(id)), properties.message_id);
+        assert_eq!(Some(Value::Uuid(id)), properties.correlation_id);
+    }
+
+    #[test]
+    fn check_data_sections() {
+        let mut output: Vec<u8> = Vec::new();
+        MessageBody::Data(vec![1, 2]).encode(&mut output).unwrap();
+        MessageBody::Data(vec![3]).encode(&mut output).unwrap();
+        let decoded = Message::decode(&mut output.clone()).unwrap();
+        assert_eq!(Some(&[1, 2, 3][..]), decoded.body.binary_payload());
+
+        let lazy = LazyMessage::new(output).unwrap();
+        assert_eq!(2, lazy.sections().count());
+        assert_eq!(Some(&[1, 2, 3][..]), lazy.body().unwrap().binary_payload());
+    }
+
+    #[test]
+    fn check_unknown_sections() {
+        let section = Value::Described(
+            Box::new(Value::Symbol(b"com.example:trace".to_vec())),
+            Box::new(Value::String(String::from("abc"))),
+        );
+        let message = ExtendedMessage {
+            message: Message::amqp_value(Value::Int(1)),
+            unknown_sections: vec![UnknownSection {
+                position: 1,
+                value: section,
+            }],
+        };
+
+        let mut output: Vec<u8> = Vec::new();
+        message.encode(&mut output).unwrap();
+        let lazy = LazyMessage::new(output.clone()).unwrap();
+        let descriptors: Vec<&Value> = lazy.sections().map(|(d, _)| d).collect();
+        assert_eq!(
+            vec![
+                &DESC_MESSAGE_HEADER,
+                &Value::Symbol(b"com.example:trace".to_vec()),
+                &DESC_MESSAGE_AMQP_VALUE
+            ],
+            descriptors
+        );
+
+        let decoded = ExtendedMessage::decode(&mut output.clone()).unwrap();
+        assert_eq!(message.unknown_sections, decoded.unknown_sections);
+        assert!(matches!(
+            decoded.message.body,
+            MessageBody::AmqpValue(Value::Int(1))
+        ));
+
+        let mut reencoded: Vec<u8> = Vec::new();
+        decoded.encode(&mut reencoded).unwrap();
+        assert_eq!(output, reencoded);
+
+        let decoded = Message::decode(&mut output).unwrap();
+        assert!(matches!(
+            decoded.body,
+            MessageBody::AmqpValue(Value::Int(1))
+        ));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn check_serde_body() {
+        #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+        struct Order {
+            id: u32,
+            items: Vec<String>,
+        }
+
+        let order = Order {
+            id: 7,
+            items: vec!["apple".to_string(), "pear".to_string()],
+        };
+
+        let message = Message::serialize_value(&order).unwrap();
+        let mut output: Vec<u8> = Vec::new();
+        message.encode(&mut output).unwrap();
+        let decoded = Message::decode(&mut output).unwrap();
+        assert_eq!(order, decoded.body.deserialize::<Order>().unwrap());
+
+        let message = Message::serialize_sequence(&(1u32, "two")).unwrap();
+        let mut output: Vec<u8> = Vec::new();
+        message.encode(&mut output).unwrap();
+        let decoded = Message::decode(&mut output).unwrap();
+        let (one, two): (u32, String) = decoded.body.deserialize().unwrap();
+        assert_eq!((1, "two".to_string()), (one, two));
+        assert!(Message::serialize_sequence(&order).is_err());
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn check_json_message() {
+        let mut message = Message::amqp_value(Value::Binary(vec![1, 2, 3]));
+        message.application_properties = Some(vec![(
+            Value::String(String::from("count")),
+            Value::Ulong(3),
+        )]);
+
+        message.properties = Some(MessageProperties {
+            message_id: Some(Value::Ulong(7)),
+            user_id: None,
+            to: Some(String::from("queue1")),
+            subject: None,
+            reply_to: None,
+            correlation_id: None,
+            content_type: Some(Symbol::from(vec![0x61, 0xff])),
+            content_encoding: None,
+            absolute_expiry_time: None,
+            creation_time: Some(Timestamp(1000)),
+            group_id: None,
+            group_sequence: None,
+            reply_to_group_id: None,
+        });
+
+        let json = message.to_json().unwrap();
+        assert_eq!(serde_json::json!({"binary": "AQID"}), json["amqp_value"]);
+        assert_eq!(serde_json::json!({"ubyte": 4}), json["header"]["priority"]);
+        assert_eq!(
+            serde_json::json!({
+                "message_id": {"ulong": 7},
+                "to": "queue1",
+                "content_type": {"symbol": {"binary": "Yf8="}},
+                "creation_time": {"timestamp": 1000},
+            }),
+            json["properties"]
+        );
+        let decoded = Message::from_json(&json).unwrap();
+        assert_eq!(json, decoded.to_json().unwrap());
+        assert_eq!(
+            Some(Symbol::from(vec![0x61, 0xff])),
+            decoded.properties.unwrap().content_type
+        );
+        assert!(Message::from_json(&serde_json::json!({"bogus": null})).is_err());
+        assert!(Message::from_json(&serde_json::json!({"header": {"bogus": true}})).is_err());
+    }
+
+    #[test]
+    fn check_lazy_message() {
+        let mut message = Message::amqp_value(Value::String(String::from("Hello, world")));
+        message.properties = Some(MessageProperties {
+            message_id: None,
+            user_id: None,
+            to: Some(String::from("queue1")),
+            subject: None,
+            reply_to: None,
+            correlation_id: None,
+            content_type: None,
+            content_encoding: None,
+            absolute_expiry_time: None,
+            creation_time: None,
+            group_id: None,
+            group_sequence: None,
+            reply_to_group_id: None,
+        });
+
+        let mut output: Vec<u8> = Vec::new();
+        message
+            .header
+            .as_ref()
+            .unwrap()
+            .encode(&mut output)
+            .unwrap();
+        Value::Described(
+            Box::new(DESC_MESSAGE_ANNOTATIONS),
+            Box::new(Value::Map(vec![(
+                Value::Symbol(b"x-opt-route".to_vec()),
+                Value::String(String::from("east")),
+            )])),
+        )
+        .encode(&mut output)
+        .unwrap();
+        message
+            .properties
+            .as_ref()
+            .unwrap()
+            .encode(&mut output)
+            .unwrap();
+        message.body.encode(&mut output).unwrap();
+
+        let lazy = LazyMessage::new(output.clone()).unwrap();
+        assert_eq!(4, lazy.sections().count());
+        assert_eq!(
+            Some(String::from("queue1")),
+            lazy.properties().unwrap().unwrap().to
+        );
+        assert_eq!(
+            Some(BorrowedValue::String("east")),
+            lazy.message_annotation("x-opt-route").unwrap()
+        );
+        assert_eq!(None, lazy.message_annotation("x-opt-other").unwrap());
+        assert!(lazy.application_properties().unwrap().is_none());
+        match lazy.body().unwrap() {
+            MessageBody::AmqpValue(Value::String(s)) => assert_eq!("Hello, world", s),
+            body => panic!("unexpected body {:?}", body),
+        }
+
+        let decoded = lazy.decode().unwrap();
+        assert_eq!(Some(4), decoded.header.unwrap().priority);
+        assert_eq!(1, decoded.message_annotations.unwrap().len());
+
+        let mut forwarded: Vec<u8> = Vec::new();
+        lazy.encode(&mut forwarded).unwrap();
+        assert_eq!(output, forwarded);
+
+        let limits = DecodeLimits::default().max_size(4);
+        let lazy = LazyMessage::with_limits(output, limits).unwrap();
+        assert!(lazy.body().is_err());
+    }
+}
@@ -1,5 +1,5 @@
 use crate::container::Value;
-use crate::framing::{Attach, LinkRole};
+use crate::framing::{Attach, Coordinator, LinkRole, ReceiverSettleMode, SenderSettleMode};
 use crate::symbol::Symbol;
 use std::collections::BTreeMap;
 
@@ -10,6 +10,8 @@ pub trait ApplyOptionsTo<T> {
 pub enum LinkOptions {
     Sender(Option<SenderOptions>),
     Receiver(Option<ReceiverOptions>),
+    /// A controller link, sending to the transaction coordinator of the remote peer
+    Coordinator,
 }
 
 impl From<SenderOptions> for LinkOptions {
@@ -36,7 +38,7 @@ impl From<LinkRole> for LinkOptions {
 impl LinkOptions {
     pub fn role(&self) -> LinkRole {
         match self {
-            LinkOptions::Sender(_) => LinkRole::Sender,
+            LinkOptions::Sender(_) | LinkOptions::Coordinator => LinkRole::Sender,
             LinkOptions::Receiver(_) => LinkRole::Receiver,
         }
     }
@@ -45,6 +47,7 @@ impl LinkOptions {
         match self {
             LinkOptions::Sender(s) => s.as_ref().and_then(|s| s.dynamic.as_ref()),
             LinkOptions::Receiver(r) => r.as_ref().and_then(|r| r.dynamic.as_ref()),
+            LinkOptions::Coordinator => None,
         }
         .map(|d| DynamicFlag::NotDynamic != *d)
     }
@@ -67,6 +70,12 @@ impl ApplyOptionsTo<Attach> for LinkOptions {
         match self {
             LinkOptions::Sender(sender) => sender.apply_options_to(target),
             LinkOptions::Receiver(receiver) => receiver.apply_options_to(target),
+            LinkOptions::Coordinator => {
+                target.target = None;
+                target.coordinator = Some(Coordinator {
+                    capabilities: Some(vec![Symbol::from_static_str("amqp:local-transactions")]),
+                });
+            }
         }
     }
 }
@@ -138,6 +147,10 @@ impl ApplyOptionsTo<(&mut Option<bool>, &mut Option<BTreeMap<Symbol, Value>>)> f
 pub struct SenderOptions {
     /// Whether to create the exchange point dynamically, if it does not yet exist
     pub dynamic: Option<DynamicFlag>,
+    /// Whether messages are sent settled, unsettled or according to the send mode (default)
+    pub snd_settle_mode: Option<SenderSettleMode>,
+    /// When the receiver settles messages, requested by the sender
+    pub rcv_settle_mode: Option<ReceiverSettleMode>,
 }
 
 impl SenderOptions {
@@ -145,6 +158,16 @@ impl SenderOptions {
         self.dynamic = Some(dynamic.into());
         self
     }
+
+    pub fn with_sender_settle_mode(mut self, mode: SenderSettleMode) -> Self {
+        self.snd_settle_mode = Some(mode);
+        self
+    }
+
+    pub fn with_receiver_settle_mode(mut self, mode: ReceiverSettleMode) -> Self {
+        self.rcv_settle_mode = Some(mode);
+        self
+    }
 }
 
 impl ApplyOptionsTo<Attach> for SenderOptions {
@@ -153,6 +176,9 @@ impl ApplyOptionsTo<Attach> for SenderOptions {
             dynamic_flag
                 .apply_options_to(&mut (&mut target.dynamic, &mut target.dynamic_node_properties))
         }
+
+        attach.snd_settle_mode = self.snd_settle_mode.or(attach.snd_settle_mode);
+        attach.rcv_settle_mode = self.rcv_settle_mode.or(attach.rcv_settle_mode);
     }
 }
 
@@ -161,6 +187,14 @@ pub struct ReceiverOptions {
     pub filter: Option<ReceiverFilter>,
     /// Whether to create the exchange point dynamically, if it does not yet exist
     pub dynamic: Option<DynamicFlag>,
+    /// The largest message accepted on the link. Larger deliveries detach the link with a
+    /// `amqp:link:message-size-exceeded` error.
+    pub max_message_size: Option<u64>,
+    /// How the sender is requested to settle messages
+    pub snd_settle_mode: Option<SenderSettleMode>,
+    /// Whether messages are settled immediately (default) or only after the sender settled
+    /// them
+    pub rcv_settle_mode: Option<ReceiverSettleMode>,
 }
 
 impl ReceiverOptions {
@@ -173,6 +207,21 @@ impl ReceiverOptions {
         self.dynamic = Some(dynamic.into());
         self
     }
+
+    pub fn with_max_message_size(mut self, max_message_size: u64) -> Self {
+        self.max_message_size = Some(max_message_size);
+        self
+    }
+
+    pub fn with_sender_settle_mode(mut self, mode: SenderSettleMode) -> Self {
+        self.snd_settle_mode = Some(mode);
+        self
+    }
+
+    pub fn with_receiver_settle_mode(mut self, mode: ReceiverSettleMode) -> Self {
+        self.rcv_settle_mode = Some(mode);
+        self
+    }
 }
 
 #[allow(clippy::needless_update)]
@@ -190,6 +239,13 @@ impl ApplyOptionsTo<Attach> for ReceiverOptions {
             dynamic_flag
                 .apply_options_to(&mut (&mut source.dynamic, &mut source.dynamic_node_properties));
         }
+
+        if let Some(max_message_size) = self.max_message_size {
+            target.max_message_size = Some(max_message_size);
+        }
+
+        target.snd_settle_mode = self.snd_settle_mode.or(target.snd_settle_mode);
+        target.rcv_settle_mode = self.rcv_settle_mode.or(target.rcv_settle_mode);
     }
 }
 
@@ -0,0 +1,282 @@
+/*
+ * Copyright 2020, Ulf Lilleengen
+ * License: Apache License 2.0 (see the file LICENSE or http://apache.org/licenses/LICENSE-2.0.html).
+ */
+
+//! The registry module maps numeric or symbolic descriptors to typed decoders, so that
+//! application and broker specific described types can be decoded into Rust types.
+//!
+//! Types are registered either on a [`DescriptorRegistry`] instance, or on the process wide
+//! registry using [`register`]. The process wide registry is used when converting values
+//! into a [`Described`] value.
+
+use std::any::Any;
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::io::{Read, Write};
+use std::sync::{Arc, OnceLock, RwLock};
+
+use crate::convert::*;
+use crate::decoding::*;
+use crate::error::*;
+use crate::types::*;
+
+/// A descriptor identifying a described type, either by numeric code or symbolic name.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Descriptor {
+    Code(u64),
+    Name(String),
+}
+
+impl Descriptor {
+    /// Extract the descriptor from a descriptor value.
+    pub fn from_value(value: &Value) -> Option<Descriptor> {
+        match value {
+            Value::Ulong(code) => Some(Descriptor::Code(*code)),
+            Value::Symbol(name) => Some(Descriptor::Name(
+                String::from_utf8_lossy(&name[..]).into_owned(),
+            )),
+            Value::SymbolSlice(name) => {
+                Some(Descriptor::Name(String::from_utf8_lossy(name).into_owned()))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl From<u64> for Descriptor {
+    fn from(code: u64) -> Self {
+        Descriptor::Code(code)
+    }
+}
+
+impl From<&str> for Descriptor {
+    fn from(name: &str) -> Self {
+        Descriptor::Name(name.to_string())
+    }
+}
+
+/// A typed value decoded by the registry. Implemented for every type that can be encoded.
+pub trait DescribedValue: Encoder + Any + Debug + Send + Sync {
+    fn as_any(&self) -> &dyn Any;
+
+    fn into_any(self: Box<Self>) -> Box<dyn Any>;
+}
+
+impl<T: Encoder + Debug + Send + Sync + 'static> DescribedValue for T {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn into_any(self: Box<Self>) -> Box<dyn Any> {
+        self
+    }
+}
+
+type DecodeFn = dyn Fn(Value) -> Result<Box<dyn DescribedValue>> + Send + Sync;
+
+/// A registry of decoders for described types.
+#[derive(Clone, Default)]
+pub struct DescriptorRegistry {
+    decoders: HashMap<Descriptor, Arc<DecodeFn>>,
+}
+
+impl Debug for DescriptorRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("DescriptorRegistry")
+            .field("descriptors", &self.decoders.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl DescriptorRegistry {
+    pub fn new() -> DescriptorRegistry {
+        DescriptorRegistry {
+            decoders: HashMap::new(),
+        }
+    }
+
+    /// Register a type for the given descriptors. The type is decoded using its [`TryFromValue`]
+    /// implementation, which receives the full described value.
+    pub fn register<T>(&mut self, descriptors: &[Descriptor])
+    where
+        T: TryFromValue + Encoder + Send + Sync + 'static,
+    {
+        let decoder: Arc<DecodeFn> =
+            Arc::new(|value| Ok(Box::new(<T as TryFromValue>::try_from(value)?)));
+        for descriptor in descriptors.iter() {
+            self.decoders.insert(descriptor.clone(), decoder.clone());
+        }
+    }
+
+    pub fn contains(&self, descriptor: &Descriptor) -> bool {
+        self.decoders.contains_key(descriptor)
+    }
+
+    /// Convert a value using the registered decoder for its descriptor. Values that are
+    /// not described, or whose descriptor is not registered, are returned unchanged.
+    pub fn convert(&self, value: Value) -> Result<Described> {
+        let decoder = match &value {
+            Value::Described(descriptor, _) => Descriptor::from_value(descriptor)
+                .and_then(|descriptor| self.decoders.get(&descriptor)),
+            _ => None,
+        };
+        match decoder {
+            Some(decoder) => Ok(Described::Registered(decoder(value)?)),
+            None => Ok(Described::Unknown(value)),
+        }
+    }
+
+    /// Decode a value from a byte reader and convert it using the registered decoders.
+    pub fn decode(&self, reader: &mut dyn Read) -> Result<Described> {
+        self.decode_with_limits(reader, &DecodeLimits::default())
+    }
+
+    /// Decode a value from a byte reader, enforcing the given limits, and convert it using
+    /// the registered decoders.
+    pub fn decode_with_limits(
+        &self,
+        reader: &mut dyn Read,
+        limits: &DecodeLimits,
+    ) -> Result<Described> {
+        decode_described_with_limits(reader, self, limits)
+    }
+}
+
+fn global() -> &'static RwLock<DescriptorRegistry> {
+    static REGISTRY: OnceLock<RwLock<DescriptorRegistry>> = OnceLock::new();
+    REGISTRY.get_or_init(|| RwLock::new(DescriptorRegistry::new()))
+}
+
+/// Register a type for the given descriptors in the process wide registry.
+pub fn register<T>(descriptors: &[Descriptor])
+where
+    T: TryFromValue + Encoder + Send + Sync + 'static,
+{
+    global().write().unwrap().register::<T>(descriptors);
+}
+
+/// Convert a value using the process wide registry.
+pub fn convert(value: Value) -> Result<Described> {
+    global().read().unwrap().convert(value)
+}
+
+/// A value that has been converted using a registry. Values without a registered decoder
+/// are preserved as is.
+#[derive(Debug)]
+pub enum Described {
+    Registered(Box<dyn DescribedValue>),
+    Unknown(Value),
+}
+
+impl Described {
+    /// Retrieve a reference to the typed value, if it was decoded as the given type.
+    pub fn downcast_ref<T: 'static>(&self) -> Option<&T> {
+        match self {
+            Described::Registered(value) => value.as_any().downcast_ref::<T>(),
+            Described::Unknown(_) => None,
+        }
+    }
+
+    /// Take the typed value, if it was decoded as the given type. Otherwise the described
+    /// value is returned unchanged.
+    pub fn downcast<T: 'static>(self) -> std::result::Result<T, Described> {
+        match self {
+            Described::Registered(value) => {
+                if value.as_any().is::<T>() {
+                    Ok(*value.into_any().downcast::<T>().unwrap())
+                } else {
+                    Err(Described::Registered(value))
+                }
+            }
+            Described::Unknown(value) => Err(Described::Unknown(value)),
+        }
+    }
+}
+
+impl Encoder for Described {
+    fn encode(&self, writer: &mut dyn Write) -> Result<TypeCode> {
+        match self {
+            Described::Registered(value) => value.encode(writer),
+            Described::Unknown(value) => value.encode(writer),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct Selector(String);
+
+    impl Encoder for Selector {
+        fn encode(&self, writer: &mut dyn Write) -> Result<TypeCode> {
+            Value::Described(
+                Box::new(Value::Ulong(0x0000_468C_0000_0004)),
+                Box::new(Value::String(self.0.clone())),
+            )
+            .encode(writer)
+        }
+    }
+
+    impl TryFromValue for Selector {
+        fn try_from(value: Value) -> Result<Self> {
+            match value {
+                Value::Described(_, value) => match *value {
+                    Value::String(s) => Ok(Selector(s)),
+                    _ => Err(AmqpError::decode_error(Some("Expected selector string"))),
+                },
+                _ => Err(AmqpError::decode_error(Some("Expected described selector"))),
+            }
+        }
+    }
+
+    #[test]
+    fn check_registry() {
+        let mut registry = DescriptorRegistry::new();
+        registry.register::<Selector>(&[
+            Descriptor::from(0x0000_468C_0000_0004),
+            Descriptor::from("apache.org:selector-filter:string"),
+        ]);
+
+        let selector = Selector(String::from("color = 'red'"));
+        let mut output = Vec::new();
+        selector.encode(&mut output).unwrap();
+        let decoded = registry.decode(&mut &output[..]).unwrap();
+        assert_eq!(Some(&selector), decoded.downcast_ref::<Selector>());
+        let decoded = decode_described(&mut &output[..], &registry).unwrap();
+        assert_eq!(Some(&selector), decoded.downcast_ref::<Selector>());
+
+        let mut reencoded = Vec::new();
+        decoded.encode(&mut reencoded).unwrap();
+        assert_eq!(output, reencoded);
+
+        let symbolic = Value::Described(
+            Box::new(Value::Symbol(b"apache.org:selector-filter:string".to_vec())),
+            Box::new(Value::String(String::from("a > 1"))),
+        );
+        let decoded = registry.convert(symbolic).unwrap();
+        assert_eq!(
+            Some(&Selector(String::from("a > 1"))),
+            decoded.downcast_ref::<Selector>()
+        );
+
+        let unknown = Value::Described(Box::new(Value::Ulong(0x99)), Box::new(Value::Null));
+        match registry.convert(unknown.clone()).unwrap() {
+            Described::Unknown(value) => assert_eq!(unknown, value),
+            value => panic!("unexpected value {:?}", value),
+        }
+        assert!(try_from_registered::<Selector>(unknown, &registry).is_err());
+
+        let symbolic = Value::Described(
+            Box::new(Value::Symbol(b"apache.org:selector-filter:string".to_vec())),
+            Box::new(Value::String(String::from("b < 2"))),
+        );
+        assert_eq!(
+            Selector(String::from("b < 2")),
+            try_from_registered::<Selector>(symbolic, &registry).unwrap()
+        );
+    }
+}
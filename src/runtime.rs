@@ -0,0 +1,535 @@
+/*
+ * Copyright 2020, Ulf Lilleengen
+ * License: Apache License 2.0 (see the file LICENSE or http://apache.org/licenses/LICENSE-2.0.html).
+ */
+
+//! The runtime module drives connections as tasks on an async runtime, reading and writing an
+//! `AsyncRead` + `AsyncWrite` stream instead of using the container poll thread. Runtimes are
+//! provided for tokio and async-io behind the `tokio` and `async-io` features.
+
+use futures_lite::future;
+use futures_lite::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use std::collections::VecDeque;
+use std::future::Future;
+use std::io::{ErrorKind, Read, Write};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+
+use crate::conn::{self, ConnectionOptions};
+use crate::connection::ConnectionWaker;
+use crate::container::{process_connection, Connection};
+use crate::driver::ConnectionDriver;
+use crate::error::*;
+use crate::transport::{Network, PeerAddress, Transport};
+
+/// A connection task, driving the I/O of a single connection until it is closed.
+pub type ConnectionTask = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+// How often keepalive is checked for idle connections
+const TICK: Duration = Duration::from_secs(1);
+
+// Size of the stream read buffer, and the most data waiting to be written to the stream
+const STREAM_BUFFER_SIZE: usize = 64 * 1024;
+
+/// An async runtime that connection tasks are spawned on.
+pub trait Runtime: Send + Sync + 'static {
+    /// Spawn a task running in the background.
+    fn spawn(&self, task: ConnectionTask);
+
+    /// Wait for the given duration.
+    fn sleep(&self, duration: Duration) -> Pin<Box<dyn Future<Output = ()> + Send>>;
+}
+
+/// Spawns connection tasks on the current tokio runtime.
+#[cfg(feature = "tokio")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TokioRuntime;
+
+#[cfg(feature = "tokio")]
+impl Runtime for TokioRuntime {
+    fn spawn(&self, task: ConnectionTask) {
+        tokio::spawn(task);
+    }
+
+    fn sleep(&self, duration: Duration) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+        Box::pin(tokio::time::sleep(duration))
+    }
+}
+
+/// Spawns connection tasks with the given function, e.g. on a smol executor, using async-io
+/// for timers.
+#[cfg(feature = "async-io")]
+pub struct AsyncIoRuntime {
+    spawn: Box<dyn Fn(ConnectionTask) + Send + Sync>,
+}
+
+#[cfg(feature = "async-io")]
+impl AsyncIoRuntime {
+    pub fn new<F: Fn(ConnectionTask) + Send + Sync + 'static>(spawn: F) -> AsyncIoRuntime {
+        AsyncIoRuntime {
+            spawn: Box::new(spawn),
+        }
+    }
+}
+
+#[cfg(feature = "async-io")]
+impl Runtime for AsyncIoRuntime {
+    fn spawn(&self, task: ConnectionTask) {
+        (self.spawn)(task)
+    }
+
+    fn sleep(&self, duration: Duration) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+        let timer = async_io::Timer::after(duration);
+        Box::pin(async move {
+            timer.await;
+        })
+    }
+}
+
+/// Represents an AMQP 1.0 container running its connections as tasks on an async runtime.
+/// Connections, sessions and links are the same as for the [`crate::container::Container`].
+pub struct AsyncContainer<R: Runtime> {
+    container_id: String,
+    runtime: Arc<R>,
+}
+
+impl<R: Runtime> AsyncContainer<R> {
+    pub fn new(runtime: R) -> AsyncContainer<R> {
+        AsyncContainer::with_id(&Uuid::new_v4().to_string(), runtime)
+    }
+
+    pub fn with_id(container_id: &str, runtime: R) -> AsyncContainer<R> {
+        AsyncContainer {
+            container_id: container_id.to_string(),
+            runtime: Arc::new(runtime),
+        }
+    }
+
+    /// Connect to an AMQP endpoint over a connected stream and send the initial open
+    /// performative. The connection is driven by a task spawned on the runtime. TLS and
+    /// WebSocket options are not supported and fail to connect, but the stream itself may
+    /// be a TLS stream.
+    pub async fn connect_with_stream<S>(
+        &self,
+        stream: S,
+        host: PeerAddress,
+        opts: ConnectionOptions,
+    ) -> Result<Connection>
+    where
+        S: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+    {
+        #[cfg(feature = "tls")]
+        if opts.tls.is_some() {
+            return Err(AmqpError::generic(
+                "TLS options are not supported by the async container",
+            ));
+        }
+        #[cfg(feature = "websocket")]
+        if opts.websocket.is_some() {
+            return Err(AmqpError::generic(
+                "WebSocket options are not supported by the async container",
+            ));
+        }
+        let options = opts.clone();
+        let transport = Transport::new(
+            StreamBuffers::default(),
+            opts.buffer_size.unwrap_or(1024 * 1024),
+        );
+        let connection = conn::connect(transport, opts)?;
+
+        let (tx, rx) = async_channel::bounded(1);
+        let waker = Arc::new(TaskWaker(tx));
+        let driver = Arc::new(ConnectionDriver::new(
+            connection.handle(waker.clone()),
+            options.idle_timeout.unwrap_or_default(),
+        ));
+        debug!("{}: created connection to {}", self.container_id, host);
+
+        let task = run(
+            self.container_id.clone(),
+            driver.clone(),
+            connection,
+            stream,
+            rx,
+            self.runtime.clone(),
+        );
+        self.runtime.spawn(Box::pin(task));
+
+        Connection::open(&self.container_id, host, &options, driver, waker).await
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl AsyncContainer<TokioRuntime> {
+    /// Connect to an AMQP endpoint using a tokio TCP stream and send the initial open
+    /// performative. See [`AsyncContainer::connect_with_stream`] for the supported options.
+    pub async fn connect<A: tokio::net::ToSocketAddrs>(
+        &self,
+        host: A,
+        opts: ConnectionOptions,
+    ) -> Result<Connection> {
+        use tokio_util::compat::TokioAsyncReadCompatExt;
+
+        let stream = tokio::net::TcpStream::connect(host).await?;
+        if let Some(nodelay) = opts.tcp_nodelay {
+            stream.set_nodelay(nodelay)?;
+        }
+        let peer = stream.peer_addr()?;
+        self.connect_with_stream(stream.compat(), peer.into(), opts)
+            .await
+    }
+}
+
+#[cfg(feature = "async-io")]
+impl AsyncContainer<AsyncIoRuntime> {
+    /// Connect to an AMQP endpoint using an async-io TCP stream and send the initial open
+    /// performative. See [`AsyncContainer::connect_with_stream`] for the supported options.
+    pub async fn connect<A: std::net::ToSocketAddrs>(
+        &self,
+        host: A,
+        opts: ConnectionOptions,
+    ) -> Result<Connection> {
+        let address = host
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| std::io::Error::from(ErrorKind::AddrNotAvailable))?;
+        let stream = async_io::Async::<std::net::TcpStream>::connect(address).await?;
+        if let Some(nodelay) = opts.tcp_nodelay {
+            stream.get_ref().set_nodelay(nodelay)?;
+        }
+        self.connect_with_stream(stream, address.into(), opts).await
+    }
+}
+
+/// Wakes the connection task when frames are queued.
+#[derive(Debug)]
+struct TaskWaker(async_channel::Sender<()>);
+
+impl ConnectionWaker for TaskWaker {
+    fn wake(&self) -> Result<()> {
+        // A full channel means a wakeup is already pending
+        match self.0.try_send(()) {
+            Err(e @ async_channel::TrySendError::Closed(_)) => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// The network of a connection driven by a task. Data is exchanged with the stream by the
+/// task, so reads would block until it has received more data, and writes would block until
+/// it has written the pending data.
+#[derive(Debug, Default)]
+struct StreamBuffers {
+    incoming: VecDeque<u8>,
+    outgoing: VecDeque<u8>,
+    eof: bool,
+}
+
+impl Network for StreamBuffers {
+    fn set_nodelay(&self, _: bool) -> Result<()> {
+        // Set on the stream when connecting
+        Ok(())
+    }
+
+    fn close(&mut self) -> Result<()> {
+        self.eof = true;
+        Ok(())
+    }
+}
+
+impl Read for StreamBuffers {
+    fn read(&mut self, b: &mut [u8]) -> std::io::Result<usize> {
+        if self.incoming.is_empty() {
+            return if self.eof {
+                Ok(0)
+            } else {
+                Err(ErrorKind::WouldBlock.into())
+            };
+        }
+        self.incoming.read(b)
+    }
+}
+
+impl Write for StreamBuffers {
+    fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+        let len = data
+            .len()
+            .min(STREAM_BUFFER_SIZE.saturating_sub(self.outgoing.len()));
+        if len == 0 {
+            return Err(ErrorKind::WouldBlock.into());
+        }
+        self.outgoing.extend(&data[..len]);
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+enum Event {
+    Read(usize),
+    Written(usize),
+    Flushed,
+    Wake,
+}
+
+async fn run<R: Runtime, S: AsyncRead + AsyncWrite + Send + Unpin>(
+    container_id: String,
+    driver: Arc<ConnectionDriver>,
+    connection: conn::Connection<StreamBuffers>,
+    stream: S,
+    wakeups: async_channel::Receiver<()>,
+    runtime: Arc<R>,
+) {
+    debug!("{}: starting connection task", container_id);
+    if let Err(e) = drive(&driver, connection, stream, &wakeups, &*runtime).await {
+        error!("{}: connection task failed: {:?}", container_id, e);
+    }
+    wakeups.close();
+    debug!("{}: stopping connection task", container_id);
+}
+
+async fn drive<R: Runtime, S: AsyncRead + AsyncWrite + Send + Unpin>(
+    driver: &ConnectionDriver,
+    mut connection: conn::Connection<StreamBuffers>,
+    stream: S,
+    wakeups: &async_channel::Receiver<()>,
+    runtime: &R,
+) -> Result<()> {
+    let (mut reader, mut writer) = futures_lite::io::split(stream);
+    let mut buffer = vec![0; STREAM_BUFFER_SIZE];
+    let mut unflushed = false;
+
+    loop {
+        let result: Result<()> = (|| {
+            process_connection(driver, &mut connection)?;
+            driver.keepalive()?;
+            driver.flowcontrol()?;
+            connection.flush()
+        })();
+
+        if let Err(e) = result {
+            let condition = match e {
+                AmqpError::Amqp(ref condition) => Some(condition.clone()),
+                AmqpError::AmqpConnectionFramingError(ref description) => {
+                    Some(ErrorCondition::framing_error(description.clone()))
+                }
+                _ => None,
+            };
+            let _ = driver.close(condition);
+            let _ = connection.flush();
+            let _ = write_outgoing(&mut connection, &mut writer).await;
+            let _ = writer.close().await;
+            return Err(e);
+        }
+
+        if driver.closed() {
+            write_outgoing(&mut connection, &mut writer).await?;
+            writer.close().await?;
+            return Ok(());
+        }
+
+        // Keep reading while pending data is written, so that neither end is stuck writing
+        // when both are sending
+        let outgoing = &connection.transport().network().outgoing;
+        let write = async {
+            if !outgoing.is_empty() {
+                Ok(Event::Written(writer.write(outgoing.as_slices().0).await?))
+            } else if unflushed {
+                writer.flush().await?;
+                Ok(Event::Flushed)
+            } else {
+                future::pending().await
+            }
+        };
+        let read = async { Ok(Event::Read(reader.read(&mut buffer).await?)) };
+        let wake = async {
+            let _ = wakeups.recv().await;
+            Ok(Event::Wake)
+        };
+        let tick = async {
+            runtime.sleep(TICK).await;
+            Ok(Event::Wake)
+        };
+        let event: std::io::Result<Event> =
+            future::or(write, future::or(read, future::or(wake, tick))).await;
+
+        let network = connection.transport_mut().network_mut();
+        match event? {
+            Event::Read(0) => network.eof = true,
+            Event::Read(len) => network.incoming.extend(&buffer[..len]),
+            Event::Written(0) => return Err(std::io::Error::from(ErrorKind::WriteZero).into()),
+            Event::Written(len) => {
+                network.outgoing.drain(..len);
+                unflushed = true;
+            }
+            Event::Flushed => unflushed = false,
+            Event::Wake => {}
+        }
+    }
+}
+
+/// Write all pending data to the stream.
+async fn write_outgoing<W: AsyncWrite + Unpin>(
+    connection: &mut conn::Connection<StreamBuffers>,
+    writer: &mut W,
+) -> Result<()> {
+    loop {
+        let _ = connection.flush();
+        let outgoing = &mut connection.transport_mut().network_mut().outgoing;
+        if outgoing.is_empty() {
+            break;
+        }
+        writer.write_all(outgoing.make_contiguous()).await?;
+        outgoing.clear();
+    }
+    writer.flush().await?;
+    Ok(())
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+
+    use super::*;
+    use crate::container::{Message, SenderSettleMode, Value};
+    use crate::framing::{Begin, Flow, Open, Performative};
+    use crate::testing::{accept_connection, read_performative, write_performative};
+    use tokio_util::compat::TokioAsyncReadCompatExt;
+
+    #[tokio::test]
+    async fn check_tokio_runtime() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let peer = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut transport = accept_connection(stream, Open::new("peer"));
+
+            // The close performative is sent when the connection is dropped
+            assert!(matches!(
+                read_performative(&mut transport),
+                Performative::Close(_)
+            ));
+        });
+
+        let container = AsyncContainer::new(TokioRuntime);
+        let stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let connection = container
+            .connect_with_stream(stream.compat(), addr.into(), ConnectionOptions::new())
+            .await
+            .unwrap();
+        assert_eq!("peer", connection.remote_container_id);
+
+        drop(connection);
+        tokio::task::spawn_blocking(move || peer.join().unwrap())
+            .await
+            .unwrap();
+    }
+
+    #[test]
+    fn stream_buffers() {
+        let mut network = StreamBuffers::default();
+        network.incoming.extend(&[1, 2, 3, 4, 5]);
+        let mut data = [0; 3];
+        assert_eq!(3, network.read(&mut data).unwrap());
+        assert_eq!([1, 2, 3], data);
+        assert_eq!(2, network.read(&mut data).unwrap());
+        assert_eq!([4, 5], data[..2]);
+        assert_eq!(
+            ErrorKind::WouldBlock,
+            network.read(&mut data).unwrap_err().kind()
+        );
+
+        // Writes block once the data waiting for the stream reaches the limit
+        let data = vec![1; STREAM_BUFFER_SIZE - 10];
+        assert_eq!(data.len(), network.write(&data).unwrap());
+        assert_eq!(10, network.write(&data).unwrap());
+        assert_eq!(
+            ErrorKind::WouldBlock,
+            network.write(&data).unwrap_err().kind()
+        );
+        network.outgoing.drain(..10);
+        assert_eq!(10, network.write(&data).unwrap());
+    }
+
+    fn flow(incoming_window: u32) -> Performative {
+        Performative::Flow(Flow {
+            next_incoming_id: Some(0),
+            incoming_window,
+            next_outgoing_id: 0,
+            outgoing_window: 10,
+            handle: Some(0),
+            delivery_count: Some(0),
+            link_credit: Some(1),
+            available: None,
+            drain: None,
+            echo: None,
+            properties: None,
+        })
+    }
+
+    // Sending waits for the session window without blocking the connection task, which
+    // runs on the same thread
+    #[tokio::test(flavor = "current_thread")]
+    async fn check_session_window_current_thread() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let peer = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut transport = accept_connection(stream, Open::new("peer"));
+            assert!(matches!(
+                read_performative(&mut transport),
+                Performative::Begin(_)
+            ));
+            let mut begin = Begin::new(0, 0, 10);
+            begin.remote_channel = Some(0);
+            write_performative(&mut transport, Performative::Begin(begin));
+            let mut attach = match read_performative(&mut transport) {
+                Performative::Attach(attach) => attach,
+                performative => panic!("unexpected performative {:?}", performative),
+            };
+            attach.snd_settle_mode = Some(SenderSettleMode::Settled);
+            write_performative(&mut transport, Performative::Attach(attach));
+            write_performative(&mut transport, flow(0));
+
+            std::thread::sleep(Duration::from_millis(100));
+            write_performative(&mut transport, flow(1));
+            match read_performative(&mut transport) {
+                Performative::Transfer(transfer) => assert_eq!(Some(0), transfer.delivery_id),
+                performative => panic!("unexpected performative {:?}", performative),
+            }
+        });
+
+        let container = AsyncContainer::new(TokioRuntime);
+        let connection = container
+            .connect(addr, ConnectionOptions::new())
+            .await
+            .unwrap();
+        let session = connection.new_session(None).await.unwrap();
+        let sender = session.new_sender("queue1").await.unwrap();
+        while sender.credits() == 0 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        sender
+            .send(Message::amqp_value(Value::Int(1)))
+            .await
+            .unwrap();
+        tokio::task::spawn_blocking(move || peer.join().unwrap())
+            .await
+            .unwrap();
+    }
+
+    #[cfg(feature = "websocket")]
+    #[tokio::test]
+    async fn reject_websocket_options() {
+        let container = AsyncContainer::new(TokioRuntime);
+        let (stream, _) = tokio::io::duplex(1024);
+        let opts = ConnectionOptions::new().websocket("ws://localhost/");
+        let result = container
+            .connect_with_stream(stream.compat(), PeerAddress::Other("duplex".into()), opts)
+            .await;
+        assert!(matches!(result, Err(AmqpError::Generic(_))));
+    }
+}
@@ -88,9 +88,9 @@ impl AsRef<str> for SaslMechanism {
     }
 }
 
-impl ToString for SaslMechanism {
-    fn to_string(&self) -> String {
-        self.as_ref().to_string()
+impl std::fmt::Display for SaslMechanism {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_ref())
     }
 }
 
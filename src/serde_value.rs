@@ -0,0 +1,505 @@
+/*
+ * Copyright 2020, Ulf Lilleengen
+ * License: Apache License 2.0 (see the file LICENSE or http://apache.org/licenses/LICENSE-2.0.html).
+ */
+
+//! The serde_value module contains a serde [`serde::Serializer`] and [`serde::Deserializer`] for
+//! the AMQP [`Value`] type. Structs and maps are mapped to AMQP maps with string keys, sequences
+//! and tuples to AMQP lists, and enum variants to their name (unit variants) or a single entry map
+//! keyed by their name. Described values are deserialized as their inner value.
+
+use serde::de::{self, DeserializeOwned, IntoDeserializer, Visitor};
+use serde::ser::{self, Serialize};
+use std::fmt::Display;
+use std::vec::Vec;
+
+use crate::error::*;
+use crate::types::*;
+
+/// Serialize any serializable type into an AMQP value.
+pub fn to_value<T: Serialize + ?Sized>(value: &T) -> Result<Value> {
+    value.serialize(Serializer)
+}
+
+/// Deserialize any deserializable type from an AMQP value.
+pub fn from_value<T: DeserializeOwned>(value: Value) -> Result<T> {
+    T::deserialize(Deserializer(value))
+}
+
+impl ser::Error for AmqpError {
+    fn custom<T: Display>(msg: T) -> Self {
+        AmqpError::Generic(msg.to_string())
+    }
+}
+
+impl de::Error for AmqpError {
+    fn custom<T: Display>(msg: T) -> Self {
+        AmqpError::decode_error(Some(msg.to_string().as_str()))
+    }
+}
+
+/// A serializer that produces an owned [`Value`].
+pub struct Serializer;
+
+impl ser::Serializer for Serializer {
+    type Ok = Value;
+    type Error = AmqpError;
+
+    type SerializeSeq = SerializeList;
+    type SerializeTuple = SerializeList;
+    type SerializeTupleStruct = SerializeList;
+    type SerializeTupleVariant = SerializeVariant<SerializeList>;
+    type SerializeMap = SerializeMap;
+    type SerializeStruct = SerializeMap;
+    type SerializeStructVariant = SerializeVariant<SerializeMap>;
+
+    fn serialize_bool(self, v: bool) -> Result<Value> {
+        Ok(Value::Bool(v))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Value> {
+        Ok(Value::Byte(v))
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Value> {
+        Ok(Value::Short(v))
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Value> {
+        Ok(Value::Int(v))
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Value> {
+        Ok(Value::Long(v))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Value> {
+        Ok(Value::Ubyte(v))
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Value> {
+        Ok(Value::Ushort(v))
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Value> {
+        Ok(Value::Uint(v))
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Value> {
+        Ok(Value::Ulong(v))
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Value> {
+        Ok(Value::from(v))
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Value> {
+        Ok(Value::from(v))
+    }
+
+    fn serialize_char(self, v: char) -> Result<Value> {
+        Ok(Value::Char(v))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Value> {
+        Ok(Value::String(v.to_string()))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Value> {
+        Ok(Value::Binary(v.to_vec()))
+    }
+
+    fn serialize_none(self) -> Result<Value> {
+        Ok(Value::Null)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Value> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Value> {
+        Ok(Value::Null)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Value> {
+        Ok(Value::Null)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<Value> {
+        Ok(Value::String(variant.to_string()))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Value> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Value> {
+        Ok(Value::Map(vec![(
+            Value::String(variant.to_string()),
+            to_value(value)?,
+        )]))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<SerializeList> {
+        Ok(SerializeList(Vec::with_capacity(len.unwrap_or(0))))
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<SerializeList> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, len: usize) -> Result<SerializeList> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<SerializeVariant<SerializeList>> {
+        Ok(SerializeVariant {
+            variant,
+            inner: SerializeList(Vec::with_capacity(len)),
+        })
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<SerializeMap> {
+        Ok(SerializeMap {
+            entries: Vec::with_capacity(len.unwrap_or(0)),
+            key: None,
+        })
+    }
+
+    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<SerializeMap> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<SerializeVariant<SerializeMap>> {
+        Ok(SerializeVariant {
+            variant,
+            inner: self.serialize_map(Some(len))?,
+        })
+    }
+}
+
+pub struct SerializeList(Vec<Value>);
+
+impl ser::SerializeSeq for SerializeList {
+    type Ok = Value;
+    type Error = AmqpError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        self.0.push(to_value(value)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Value> {
+        Ok(Value::List(self.0))
+    }
+}
+
+impl ser::SerializeTuple for SerializeList {
+    type Ok = Value;
+    type Error = AmqpError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<Value> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+impl ser::SerializeTupleStruct for SerializeList {
+    type Ok = Value;
+    type Error = AmqpError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<Value> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+pub struct SerializeMap {
+    entries: Vec<(Value, Value)>,
+    key: Option<Value>,
+}
+
+impl ser::SerializeMap for SerializeMap {
+    type Ok = Value;
+    type Error = AmqpError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<()> {
+        self.key = Some(to_value(key)?);
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        let key = self
+            .key
+            .take()
+            .ok_or_else(|| AmqpError::generic("Map value serialized before its key"))?;
+        self.entries.push((key, to_value(value)?));
+        Ok(())
+    }
+
+    fn end(self) -> Result<Value> {
+        Ok(Value::Map(self.entries))
+    }
+}
+
+impl ser::SerializeStruct for SerializeMap {
+    type Ok = Value;
+    type Error = AmqpError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<()> {
+        self.entries
+            .push((Value::String(key.to_string()), to_value(value)?));
+        Ok(())
+    }
+
+    fn end(self) -> Result<Value> {
+        ser::SerializeMap::end(self)
+    }
+}
+
+/// Wraps the serialized content of a tuple or struct variant into a single entry map
+/// keyed by the variant name.
+pub struct SerializeVariant<S> {
+    variant: &'static str,
+    inner: S,
+}
+
+impl<S> SerializeVariant<S> {
+    fn wrap(variant: &'static str, value: Value) -> Value {
+        Value::Map(vec![(Value::String(variant.to_string()), value)])
+    }
+}
+
+impl ser::SerializeTupleVariant for SerializeVariant<SerializeList> {
+    type Ok = Value;
+    type Error = AmqpError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        ser::SerializeSeq::serialize_element(&mut self.inner, value)
+    }
+
+    fn end(self) -> Result<Value> {
+        let value = ser::SerializeSeq::end(self.inner)?;
+        Ok(Self::wrap(self.variant, value))
+    }
+}
+
+impl ser::SerializeStructVariant for SerializeVariant<SerializeMap> {
+    type Ok = Value;
+    type Error = AmqpError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<()> {
+        ser::SerializeStruct::serialize_field(&mut self.inner, key, value)
+    }
+
+    fn end(self) -> Result<Value> {
+        let value = ser::SerializeMap::end(self.inner)?;
+        Ok(Self::wrap(self.variant, value))
+    }
+}
+
+/// A deserializer that consumes an owned [`Value`].
+pub struct Deserializer(pub Value);
+
+impl<'de> IntoDeserializer<'de, AmqpError> for Value {
+    type Deserializer = Deserializer;
+
+    fn into_deserializer(self) -> Deserializer {
+        Deserializer(self)
+    }
+}
+
+fn symbol_to_string(symbol: &[u8]) -> Result<String> {
+    Ok(std::str::from_utf8(symbol)?.to_string())
+}
+
+impl<'de> de::Deserializer<'de> for Deserializer {
+    type Error = AmqpError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.0 {
+            Value::Described(_, value) => Deserializer(*value).deserialize_any(visitor),
+            Value::Null => visitor.visit_unit(),
+            Value::Bool(v) => visitor.visit_bool(v),
+            Value::Ubyte(v) => visitor.visit_u8(v),
+            Value::Ushort(v) => visitor.visit_u16(v),
+            Value::Uint(v) => visitor.visit_u32(v),
+            Value::Ulong(v) => visitor.visit_u64(v),
+            Value::Byte(v) => visitor.visit_i8(v),
+            Value::Short(v) => visitor.visit_i16(v),
+            Value::Int(v) => visitor.visit_i32(v),
+            Value::Long(v) => visitor.visit_i64(v),
+            Value::Float(v) => visitor.visit_f32(v.0),
+            Value::Double(v) => visitor.visit_f64(v.0),
+            Value::Char(v) => visitor.visit_char(v),
+            Value::Timestamp(v) => visitor.visit_u64(v),
+            Value::Uuid(v) => visitor.visit_string(v.to_string()),
+            Value::Binary(v) => visitor.visit_byte_buf(v),
+            Value::Str(v) => visitor.visit_str(v),
+            Value::String(v) => visitor.visit_string(v),
+            Value::Symbol(v) => visitor.visit_string(symbol_to_string(&v)?),
+            Value::SymbolSlice(v) => visitor.visit_string(symbol_to_string(v)?),
+            Value::List(v) | Value::Array(v) => {
+                visitor.visit_seq(de::value::SeqDeserializer::new(v.into_iter()))
+            }
+            Value::Map(v) => visitor.visit_map(de::value::MapDeserializer::new(v.into_iter())),
+            v @ (Value::Decimal32(_) | Value::Decimal64(_) | Value::Decimal128(_)) => {
+                Err(AmqpError::decode_error(Some(
+                    format!("Unable to deserialize decimal value {:?}", v).as_str(),
+                )))
+            }
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.0 {
+            Value::Null => visitor.visit_none(),
+            value => visitor.visit_some(Deserializer(value)),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value> {
+        match self.0 {
+            Value::Described(_, value) => {
+                Deserializer(*value).deserialize_enum(name, variants, visitor)
+            }
+            Value::Map(mut entries) if entries.len() == 1 => {
+                let (variant, value) = entries.remove(0);
+                visitor.visit_enum(de::value::MapAccessDeserializer::new(
+                    de::value::MapDeserializer::new(std::iter::once((variant, value))),
+                ))
+            }
+            Value::String(v) => visitor.visit_enum(v.into_deserializer()),
+            Value::Str(v) => visitor.visit_enum(v.into_deserializer()),
+            Value::Symbol(v) => visitor.visit_enum(symbol_to_string(&v)?.into_deserializer()),
+            Value::SymbolSlice(v) => visitor.visit_enum(symbol_to_string(v)?.into_deserializer()),
+            value => Err(AmqpError::decode_error(Some(
+                format!("Unable to deserialize enum from {:?}", value).as_str(),
+            ))),
+        }
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple tuple_struct map struct
+        identifier ignored_any
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    enum Unit {
+        Celsius,
+        Kelvin,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    enum Location {
+        Room(String),
+        Coordinates { lat: f64, lon: f64 },
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Reading {
+        sensor: String,
+        value: f64,
+        unit: Unit,
+        location: Option<Location>,
+        tags: Vec<String>,
+    }
+
+    #[test]
+    fn check_roundtrip() {
+        let reading = Reading {
+            sensor: "t1".to_string(),
+            value: 21.5,
+            unit: Unit::Celsius,
+            location: Some(Location::Coordinates {
+                lat: 59.9,
+                lon: 10.7,
+            }),
+            tags: vec!["indoor".to_string()],
+        };
+
+        let value = to_value(&reading).unwrap();
+        if let Value::Map(ref entries) = value {
+            assert_eq!(
+                (
+                    Value::String("unit".to_string()),
+                    Value::String("Celsius".to_string())
+                ),
+                entries[2]
+            );
+        } else {
+            panic!("expected map, got {:?}", value);
+        }
+
+        let decoded: Reading = from_value(value).unwrap();
+        assert_eq!(reading, decoded);
+
+        let room: Location =
+            from_value(to_value(&Location::Room("a".to_string())).unwrap()).unwrap();
+        assert_eq!(Location::Room("a".to_string()), room);
+        let unit: Unit = from_value(Value::Symbol(b"Kelvin".to_vec())).unwrap();
+        assert_eq!(Unit::Kelvin, unit);
+    }
+}
@@ -0,0 +1,451 @@
+/*
+ * Copyright 2020, Ulf Lilleengen
+ * License: Apache License 2.0 (see the file LICENSE or http://apache.org/licenses/LICENSE-2.0.html).
+ */
+
+//! The streaming module implements incremental decoding of frames and values. The decoders
+//! do not perform any I/O: byte chunks are fed as they arrive from any source, and frames or
+//! values are returned once they are complete. Partial input is kept until more bytes arrive.
+//!
+//! The payload of AMQP frames can be consumed in chunks using [`FrameStreamDecoder::next_event`],
+//! which avoids buffering a frame in full.
+
+use std::io::Cursor;
+
+use crate::decoding::*;
+use crate::error::*;
+use crate::framing::*;
+use crate::transport::ProtocolHeader;
+use crate::types::*;
+
+/// The largest frame accepted by a [`FrameStreamDecoder`] unless configured otherwise.
+pub const DEFAULT_MAX_FRAME_SIZE: usize = 1024 * 1024;
+
+/// The smallest maximum frame size a peer may announce, as required by AMQP.
+pub const MIN_MAX_FRAME_SIZE: usize = 512;
+
+/// Bytes fed to a decoder that have not been consumed yet.
+#[derive(Debug, Default)]
+struct StreamBuffer {
+    buffer: Vec<u8>,
+    position: usize,
+}
+
+impl StreamBuffer {
+    fn feed(&mut self, data: &[u8]) {
+        // Compact once the consumed prefix dominates the buffer.
+        if self.position > 0 && self.position >= self.buffer.len() / 2 {
+            self.buffer.drain(..self.position);
+            self.position = 0;
+        }
+        self.buffer.extend_from_slice(data);
+    }
+
+    fn peek(&self) -> &[u8] {
+        &self.buffer[self.position..]
+    }
+
+    fn consume(&mut self, nbytes: usize) {
+        self.position += nbytes;
+        if self.position == self.buffer.len() {
+            self.buffer.clear();
+            self.position = 0;
+        }
+    }
+}
+
+/// An incremental decoder of AMQP values.
+#[derive(Debug, Default)]
+pub struct ValueStreamDecoder {
+    input: StreamBuffer,
+    limits: DecodeLimits,
+}
+
+impl ValueStreamDecoder {
+    pub fn new() -> ValueStreamDecoder {
+        ValueStreamDecoder::with_limits(DecodeLimits::default())
+    }
+
+    pub fn with_limits(limits: DecodeLimits) -> ValueStreamDecoder {
+        ValueStreamDecoder {
+            input: StreamBuffer::default(),
+            limits,
+        }
+    }
+
+    /// Add bytes to the decoder.
+    pub fn feed(&mut self, data: &[u8]) {
+        self.input.feed(data);
+    }
+
+    /// The number of bytes fed but not yet decoded.
+    pub fn buffered(&self) -> usize {
+        self.input.peek().len()
+    }
+
+    /// Decode the next value, or return `None` if more bytes are needed.
+    pub fn next_value(&mut self) -> Result<Option<Value>> {
+        let input = self.input.peek();
+        match value_size(input, &self.limits)? {
+            Some(size) => {
+                let value = decode_value_with_limits(&mut &input[..size], &self.limits)?;
+                self.input.consume(size);
+                Ok(Some(value))
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+/// An event produced by [`FrameStreamDecoder::next_event`].
+#[derive(Debug)]
+pub enum FrameEvent {
+    /// The channel and performative of an AMQP frame. The `payload_size` bytes of payload
+    /// that follow are produced as [`FrameEvent::Payload`] events.
+    Amqp {
+        channel: u16,
+        performative: Option<Box<Performative>>,
+        payload_size: usize,
+    },
+    /// A chunk of the payload of the current AMQP frame.
+    Payload(Vec<u8>),
+    /// A complete SASL frame.
+    Sasl(SaslFrame),
+}
+
+/// An incremental decoder of frames and protocol headers.
+#[derive(Debug)]
+pub struct FrameStreamDecoder {
+    input: StreamBuffer,
+    limits: DecodeLimits,
+    max_frame_size: usize,
+    // Payload bytes remaining of the current AMQP frame
+    payload_remaining: usize,
+    // Frame being assembled by next_frame
+    pending: Option<AmqpFrame>,
+}
+
+impl Default for FrameStreamDecoder {
+    fn default() -> Self {
+        FrameStreamDecoder {
+            input: StreamBuffer::default(),
+            limits: DecodeLimits::default(),
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+            payload_remaining: 0,
+            pending: None,
+        }
+    }
+}
+
+impl FrameStreamDecoder {
+    pub fn new() -> FrameStreamDecoder {
+        FrameStreamDecoder::default()
+    }
+
+    /// Set the limits applied when decoding performatives.
+    pub fn set_limits(&mut self, limits: DecodeLimits) {
+        self.limits = limits;
+    }
+
+    /// Reject frames larger than the given size with a framing error. Defaults to
+    /// [`DEFAULT_MAX_FRAME_SIZE`].
+    pub fn set_max_frame_size(&mut self, max_frame_size: usize) {
+        self.max_frame_size = max_frame_size;
+    }
+
+    /// Add bytes to the decoder.
+    pub fn feed(&mut self, data: &[u8]) {
+        self.input.feed(data);
+    }
+
+    /// The number of bytes fed but not yet decoded.
+    pub fn buffered(&self) -> usize {
+        self.input.peek().len()
+    }
+
+    /// Decode a protocol header, or return `None` if more bytes are needed. Must only be called
+    /// between frames.
+    pub fn next_protocol_header(&mut self) -> Result<Option<ProtocolHeader>> {
+        let mut input = self.input.peek();
+        if input.len() < 8 {
+            return Ok(None);
+        }
+        let header = ProtocolHeader::decode(&mut input)?;
+        self.input.consume(8);
+        Ok(Some(header))
+    }
+
+    /// Decode the next event, or return `None` if more bytes are needed.
+    pub fn next_event(&mut self) -> Result<Option<FrameEvent>> {
+        if self.payload_remaining > 0 {
+            let input = self.input.peek();
+            if input.is_empty() {
+                return Ok(None);
+            }
+            let len = input.len().min(self.payload_remaining);
+            let chunk = input[..len].to_vec();
+            self.input.consume(len);
+            self.payload_remaining -= len;
+            return Ok(Some(FrameEvent::Payload(chunk)));
+        }
+
+        let input = self.input.peek();
+        if input.len() < FRAME_HEADER_SIZE {
+            return Ok(None);
+        }
+        let header = FrameHeader::decode(&mut &input[..FRAME_HEADER_SIZE])?;
+        let frame_size = header.size as usize;
+        let body_offset = header.doff as usize * 4;
+        if header.doff < 2 || frame_size < body_offset {
+            return Err(AmqpError::framing_error(Some(
+                format!(
+                    "Invalid frame size {} or data offset {}",
+                    frame_size, header.doff
+                )
+                .as_str(),
+            )));
+        }
+        // Checked before SASL frames are buffered, as they are only decoded once complete.
+        if frame_size > self.max_frame_size {
+            return Err(AmqpError::framing_error(Some(
+                format!(
+                    "Frame size {} exceeds maximum frame size {}",
+                    frame_size, self.max_frame_size
+                )
+                .as_str(),
+            )));
+        }
+
+        if header.frame_type != 0 {
+            if input.len() < frame_size {
+                return Ok(None);
+            }
+            let mut frame = &input[FRAME_HEADER_SIZE..frame_size];
+            let frame =
+                Frame::decode_with_limits(header, &mut Cursor::new(&mut frame), &self.limits)?;
+            self.input.consume(frame_size);
+            return match frame {
+                Frame::SASL(frame) => Ok(Some(FrameEvent::Sasl(frame))),
+                Frame::AMQP(_) => Err(AmqpError::framing_error(None)),
+            };
+        }
+
+        // Wait for the extended header and the complete performative, the payload is streamed.
+        if input.len() < body_offset {
+            return Ok(None);
+        }
+        let body = &input[body_offset..input.len().min(frame_size)];
+        let performative_size = if frame_size > body_offset {
+            match value_size(body, &self.limits)? {
+                Some(size) => size,
+                None if body.len() < frame_size - body_offset => return Ok(None),
+                None => {
+                    return Err(AmqpError::framing_error(Some(
+                        "Performative exceeds frame size",
+                    )))
+                }
+            }
+        } else {
+            0
+        };
+        let performative = if performative_size > 0 {
+            Performative::decode_with_limits(&mut &body[..performative_size], &self.limits)?
+        } else {
+            None
+        };
+
+        let payload_size = frame_size - body_offset - performative_size;
+        self.input.consume(body_offset + performative_size);
+        self.payload_remaining = payload_size;
+        Ok(Some(FrameEvent::Amqp {
+            channel: header.ext,
+            performative: performative.map(Box::new),
+            payload_size,
+        }))
+    }
+
+    /// Decode the next complete frame, or return `None` if more bytes are needed. Payload
+    /// chunks are collected until the frame is complete. Must not be interleaved with
+    /// [`FrameStreamDecoder::next_event`] within a frame.
+    pub fn next_frame(&mut self) -> Result<Option<Frame>> {
+        loop {
+            if let Some(frame) = &self.pending {
+                if self.payload_remaining == 0 {
+                    debug_assert!(frame.payload.is_some());
+                    return Ok(self.pending.take().map(Frame::AMQP));
+                }
+            }
+            match self.next_event()? {
+                None => return Ok(None),
+                Some(FrameEvent::Sasl(frame)) => return Ok(Some(Frame::SASL(frame))),
+                Some(FrameEvent::Amqp {
+                    channel,
+                    performative,
+                    ..
+                }) => {
+                    // The payload grows as chunks arrive rather than trusting the frame header
+                    self.pending = Some(AmqpFrame {
+                        channel,
+                        performative: performative.map(|p| *p),
+                        payload: Some(Vec::new()),
+                    });
+                }
+                Some(FrameEvent::Payload(chunk)) => {
+                    if let Some(AmqpFrame {
+                        payload: Some(payload),
+                        ..
+                    }) = &mut self.pending
+                    {
+                        payload.extend_from_slice(&chunk[..]);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn check_value_stream() {
+        let mut output = Vec::new();
+        Value::String(String::from("Hello, world"))
+            .encode(&mut output)
+            .unwrap();
+        Value::List(vec![Value::Int(1), Value::Bool(true)])
+            .encode(&mut output)
+            .unwrap();
+
+        let mut decoder = ValueStreamDecoder::new();
+        let mut values = Vec::new();
+        for chunk in output.chunks(3) {
+            decoder.feed(chunk);
+            while let Some(value) = decoder.next_value().unwrap() {
+                values.push(value);
+            }
+        }
+        assert_eq!(
+            vec![
+                Value::String(String::from("Hello, world")),
+                Value::List(vec![Value::Int(1), Value::Bool(true)])
+            ],
+            values
+        );
+        assert_eq!(0, decoder.buffered());
+    }
+
+    #[test]
+    fn check_frame_stream() {
+        let mut output = Vec::new();
+        ProtocolHeader::AMQP(crate::transport::Version(1, 0, 0))
+            .encode(&mut output)
+            .unwrap();
+        Frame::AMQP(AmqpFrame {
+            channel: 3,
+            performative: Some(Performative::Transfer(Transfer::new(1))),
+            payload: Some(vec![7; 100]),
+        })
+        .encode(&mut output)
+        .unwrap();
+        Frame::AMQP(AmqpFrame {
+            channel: 0,
+            performative: None,
+            payload: None,
+        })
+        .encode(&mut output)
+        .unwrap();
+
+        let mut decoder = FrameStreamDecoder::new();
+        let mut header = None;
+        let mut frames = Vec::new();
+        for chunk in output.chunks(5) {
+            decoder.feed(chunk);
+            if header.is_none() {
+                header = decoder.next_protocol_header().unwrap();
+            }
+            if header.is_some() {
+                while let Some(frame) = decoder.next_frame().unwrap() {
+                    frames.push(frame);
+                }
+            }
+        }
+        assert_eq!(
+            Some(ProtocolHeader::AMQP(crate::transport::Version(1, 0, 0))),
+            header
+        );
+        assert_eq!(2, frames.len());
+        match &frames[0] {
+            Frame::AMQP(AmqpFrame {
+                channel: 3,
+                performative: Some(Performative::Transfer(transfer)),
+                payload: Some(payload),
+            }) => {
+                assert_eq!(1, transfer.handle);
+                assert_eq!(vec![7; 100], *payload);
+            }
+            frame => panic!("unexpected frame {:?}", frame),
+        }
+
+        // Payload is produced in chunks as it arrives
+        let frame = &output[8..output.len() - 8];
+        let mut decoder = FrameStreamDecoder::new();
+        decoder.set_max_frame_size(frame.len());
+        decoder.feed(&frame[..frame.len() - 50]);
+        assert!(matches!(
+            decoder.next_event().unwrap(),
+            Some(FrameEvent::Amqp {
+                channel: 3,
+                payload_size: 100,
+                ..
+            })
+        ));
+        assert!(
+            matches!(decoder.next_event().unwrap(), Some(FrameEvent::Payload(p)) if p.len() == 50)
+        );
+        assert!(decoder.next_event().unwrap().is_none());
+        decoder.feed(&frame[frame.len() - 50..]);
+        assert!(
+            matches!(decoder.next_event().unwrap(), Some(FrameEvent::Payload(p)) if p.len() == 50)
+        );
+
+        decoder.set_max_frame_size(frame.len() - 1);
+        decoder.feed(frame);
+        assert!(decoder.next_event().is_err());
+    }
+
+    #[test]
+    fn check_frame_size_limit() {
+        // An oversized SASL frame is rejected from its header, before it is buffered
+        let mut decoder = FrameStreamDecoder::new();
+        let size = (DEFAULT_MAX_FRAME_SIZE + 1) as u32;
+        let mut header = Vec::new();
+        header.extend_from_slice(&size.to_be_bytes());
+        header.extend_from_slice(&[2, 1, 0, 0]);
+        decoder.feed(&header);
+        assert!(decoder.next_frame().is_err());
+
+        // The payload buffer is not sized from the frame header
+        let mut frame = Vec::new();
+        Frame::AMQP(AmqpFrame {
+            channel: 0,
+            performative: Some(Performative::Transfer(Transfer::new(1))),
+            payload: Some(vec![7; 10]),
+        })
+        .encode(&mut frame)
+        .unwrap();
+        frame[..4].copy_from_slice(&(DEFAULT_MAX_FRAME_SIZE as u32).to_be_bytes());
+        let mut decoder = FrameStreamDecoder::new();
+        decoder.feed(&frame);
+        assert!(decoder.next_frame().unwrap().is_none());
+        match &decoder.pending {
+            Some(AmqpFrame {
+                payload: Some(payload),
+                ..
+            }) => assert!(payload.capacity() < 1024),
+            frame => panic!("unexpected frame {:?}", frame),
+        }
+    }
+}
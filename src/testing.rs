@@ -0,0 +1,106 @@
+/*
+ * Copyright 2020, Ulf Lilleengen
+ * License: Apache License 2.0 (see the file LICENSE or http://apache.org/licenses/LICENSE-2.0.html).
+ */
+
+//! Helpers shared by the tests of several modules.
+
+use crate::error::*;
+use crate::framing::{AmqpFrame, Frame, Open, Performative};
+use crate::transport::{Network, ProtocolHeader, Transport, Version};
+use std::fmt::Debug;
+use std::io::{ErrorKind, Read, Write};
+use std::thread;
+use std::time::Duration;
+
+/// Read up to `len` bytes from a non-blocking reader, waiting while it would block. Fewer
+/// bytes are returned if the stream ends before.
+pub fn read_nonblocking<R: Read>(reader: &mut R, len: usize) -> Vec<u8> {
+    let mut data = Vec::new();
+    while data.len() < len {
+        let mut buf = vec![0; len - data.len()];
+        match reader.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => data.extend_from_slice(&buf[..n]),
+            Err(e) if e.kind() == ErrorKind::WouldBlock => thread::sleep(Duration::from_millis(10)),
+            Err(e) => panic!("Read failed: {:?}", e),
+        }
+    }
+    data
+}
+
+/// The blocking end of a connection, acting as the remote peer.
+#[derive(Debug)]
+pub struct Peer<S>(pub S);
+
+impl<S: Read + Write + Debug> Network for Peer<S> {
+    fn set_nodelay(&self, _: bool) -> Result<()> {
+        Ok(())
+    }
+
+    fn close(&mut self) -> Result<()> {
+        // The stream is closed when the peer is dropped
+        Ok(())
+    }
+}
+
+impl<S: Read> Read for Peer<S> {
+    fn read(&mut self, b: &mut [u8]) -> std::io::Result<usize> {
+        self.0.read(b)
+    }
+}
+
+impl<S: Write> Write for Peer<S> {
+    fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+        self.0.write(data)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.0.flush()
+    }
+}
+
+/// Accept a connection as the remote peer: exchange the protocol headers and answer the
+/// open performative of the client with the given one.
+pub fn accept_connection<S: Read + Write + Debug>(stream: S, open: Open) -> Transport<Peer<S>> {
+    let mut transport = Transport::new(Peer(stream), 1024);
+    while transport.read_protocol_header().unwrap().is_none() {}
+    transport
+        .write_protocol_header(&ProtocolHeader::AMQP(Version(1, 0, 0)))
+        .unwrap();
+    assert!(matches!(
+        read_performative(&mut transport),
+        Performative::Open(_)
+    ));
+    transport
+        .write_frame(&Frame::AMQP(AmqpFrame {
+            channel: 0,
+            performative: Some(Performative::Open(open)),
+            payload: None,
+        }))
+        .unwrap();
+    transport
+}
+
+/// Write a frame with the given performative on channel 0.
+#[cfg(feature = "tokio")]
+pub fn write_performative<N: Network>(transport: &mut Transport<N>, performative: Performative) {
+    transport
+        .write_frame(&Frame::AMQP(AmqpFrame {
+            channel: 0,
+            performative: Some(performative),
+            payload: None,
+        }))
+        .unwrap();
+}
+
+/// Read the performative of the next frame.
+pub fn read_performative<N: Network>(transport: &mut Transport<N>) -> Performative {
+    match transport.read_frame().unwrap() {
+        Frame::AMQP(AmqpFrame {
+            performative: Some(performative),
+            ..
+        }) => performative,
+        frame => panic!("unexpected frame {:?}", frame),
+    }
+}
@@ -0,0 +1,330 @@
+/*
+ * Copyright 2020, Ulf Lilleengen
+ * License: Apache License 2.0 (see the file LICENSE or http://apache.org/licenses/LICENSE-2.0.html).
+ */
+
+//! The tls module implements a TLS network for `amqps://` connections using rustls.
+
+use mio::event::Source;
+use mio::{Interest, Registry, Token};
+use rustls::{Certificate, ClientConfig, ClientConnection, PrivateKey, RootCertStore, ServerName};
+use std::fmt::Debug;
+use std::io::{ErrorKind, Read, Write};
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::sync::Arc;
+
+use crate::error::*;
+use crate::transport::mio::MioNetwork;
+use crate::transport::Network;
+
+/// Configuration of a TLS connection. The server certificate is verified against the
+/// configured root certificates, or the Mozilla root certificates if none are configured.
+/// The default configuration has no server name, which is then taken from the URL when
+/// connecting with [`Container::connect_url`](crate::container::Container::connect_url).
+#[derive(Clone, Default)]
+pub struct TlsConfig {
+    server_name: Option<String>,
+    root_certificates: Vec<Certificate>,
+    client_certificate: Option<(Vec<Certificate>, PrivateKey)>,
+}
+
+impl TlsConfig {
+    /// Create a configuration for connecting to the given server. The name is sent for SNI
+    /// and must match the server certificate.
+    pub fn new(server_name: impl Into<String>) -> TlsConfig {
+        TlsConfig {
+            server_name: Some(server_name.into()),
+            root_certificates: Vec::new(),
+            client_certificate: None,
+        }
+    }
+
+    /// Trust the PEM encoded root certificates, e.g. of a private CA.
+    pub fn root_certificates_pem(mut self, pem: &[u8]) -> Result<TlsConfig> {
+        let certificates = read_certificates(pem)?;
+        self.root_certificates.extend(certificates);
+        Ok(self)
+    }
+
+    /// Authenticate with the PEM encoded client certificate chain and private key for
+    /// mutual TLS.
+    pub fn client_certificate_pem(mut self, certificates: &[u8], key: &[u8]) -> Result<TlsConfig> {
+        let certificates = read_certificates(certificates)?;
+        let key = read_private_key(key)?;
+        self.client_certificate = Some((certificates, key));
+        Ok(self)
+    }
+
+    pub fn server_name(&self) -> Option<&str> {
+        self.server_name.as_deref()
+    }
+
+    /// Use the given server name unless one is configured.
+    pub(crate) fn default_server_name(mut self, server_name: &str) -> TlsConfig {
+        self.server_name
+            .get_or_insert_with(|| server_name.to_string());
+        self
+    }
+
+    fn client_config(&self) -> Result<ClientConfig> {
+        let mut roots = RootCertStore::empty();
+        if self.root_certificates.is_empty() {
+            roots.add_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.iter().map(|anchor| {
+                rustls::OwnedTrustAnchor::from_subject_spki_name_constraints(
+                    anchor.subject,
+                    anchor.spki,
+                    anchor.name_constraints,
+                )
+            }));
+        }
+        for certificate in &self.root_certificates {
+            roots.add(certificate)?;
+        }
+
+        let builder = ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(roots);
+        Ok(match &self.client_certificate {
+            Some((certificates, key)) => {
+                builder.with_client_auth_cert(certificates.clone(), key.clone())?
+            }
+            None => builder.with_no_client_auth(),
+        })
+    }
+}
+
+impl Debug for TlsConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("TlsConfig")
+            .field("server_name", &self.server_name)
+            .field("root_certificates", &self.root_certificates.len())
+            .field("client_certificate", &self.client_certificate.is_some())
+            .finish()
+    }
+}
+
+fn read_certificates(pem: &[u8]) -> Result<Vec<Certificate>> {
+    let mut reader = pem;
+    let certificates = rustls_pemfile::certs(&mut reader)?;
+    if certificates.is_empty() {
+        return Err(AmqpError::generic("No certificate found in PEM input"));
+    }
+    Ok(certificates.into_iter().map(Certificate).collect())
+}
+
+fn read_private_key(pem: &[u8]) -> Result<PrivateKey> {
+    let mut reader = pem;
+    while let Some(item) = rustls_pemfile::read_one(&mut reader)? {
+        match item {
+            rustls_pemfile::Item::PKCS8Key(key)
+            | rustls_pemfile::Item::RSAKey(key)
+            | rustls_pemfile::Item::ECKey(key) => return Ok(PrivateKey(key)),
+            _ => {}
+        }
+    }
+    Err(AmqpError::generic("No private key found in PEM input"))
+}
+
+/// A TLS connection on top of a [`MioNetwork`]. The handshake is performed as data is read
+/// and written.
+#[derive(Debug)]
+pub struct TlsNetwork {
+    network: MioNetwork,
+    tls: ClientConnection,
+}
+
+impl TlsNetwork {
+    pub fn connect<S: ToSocketAddrs>(host: &S, config: &TlsConfig) -> Result<TlsNetwork> {
+        TlsNetwork::new(MioNetwork::connect(host)?, config)
+    }
+
+    /// Start a TLS connection on a connected network.
+    pub fn new(network: MioNetwork, config: &TlsConfig) -> Result<TlsNetwork> {
+        let name = config
+            .server_name()
+            .ok_or_else(|| AmqpError::generic("No server name configured for TLS"))?;
+        let server_name = ServerName::try_from(name)
+            .map_err(|_| AmqpError::Generic(format!("Invalid server name {}", name)))?;
+        // Plaintext is buffered up to the default limit of rustls, also before the handshake
+        // completes, and writes fail with WouldBlock beyond it
+        let tls = ClientConnection::new(Arc::new(config.client_config()?), server_name)?;
+        Ok(TlsNetwork { network, tls })
+    }
+
+    pub fn peer_addr(&self) -> SocketAddr {
+        self.network.peer_addr()
+    }
+
+    /// Write pending TLS records, failing with WouldBlock if the network does not accept
+    /// all of them. The remaining records are written on the next read, write or flush.
+    fn write_tls(&mut self) -> std::io::Result<()> {
+        while self.tls.wants_write() {
+            self.tls.write_tls(&mut self.network)?;
+        }
+        Ok(())
+    }
+
+    /// Write pending TLS records like [`TlsNetwork::write_tls`], ignoring WouldBlock.
+    fn try_write_tls(&mut self) -> std::io::Result<()> {
+        match self.write_tls() {
+            Err(e) if e.kind() == ErrorKind::WouldBlock => Ok(()),
+            result => result,
+        }
+    }
+}
+
+impl Network for TlsNetwork {
+    fn set_nodelay(&self, nodelay: bool) -> Result<()> {
+        self.network.set_nodelay(nodelay)
+    }
+
+    fn close(&mut self) -> Result<()> {
+        self.tls.send_close_notify();
+        let _ = self.write_tls();
+        self.network.close()
+    }
+}
+
+impl Read for TlsNetwork {
+    fn read(&mut self, b: &mut [u8]) -> std::io::Result<usize> {
+        loop {
+            self.try_write_tls()?;
+            match self.tls.reader().read(b) {
+                Ok(n) => return Ok(n),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+                Err(e) => return Err(e),
+            }
+
+            // No plaintext available, read more records
+            if self.tls.read_tls(&mut self.network)? == 0 {
+                return Ok(0);
+            }
+            self.tls
+                .process_new_packets()
+                .map_err(|e| std::io::Error::new(ErrorKind::InvalidData, e))?;
+        }
+    }
+}
+
+impl Write for TlsNetwork {
+    fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+        // Make room for the data by writing pending records first
+        let written = self.write_tls();
+        let n = self.tls.writer().write(data)?;
+        if n == 0 && !data.is_empty() {
+            return Err(written
+                .err()
+                .unwrap_or_else(|| ErrorKind::WouldBlock.into()));
+        }
+        self.try_write_tls()?;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.write_tls()
+    }
+}
+
+impl Source for TlsNetwork {
+    fn register(
+        &mut self,
+        registry: &Registry,
+        token: Token,
+        interests: Interest,
+    ) -> std::io::Result<()> {
+        Source::register(&mut self.network, registry, token, interests)
+    }
+
+    fn reregister(
+        &mut self,
+        registry: &Registry,
+        token: Token,
+        interests: Interest,
+    ) -> std::io::Result<()> {
+        Source::reregister(&mut self.network, registry, token, interests)
+    }
+
+    fn deregister(&mut self, registry: &Registry) -> std::io::Result<()> {
+        Source::deregister(&mut self.network, registry)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::testing::read_nonblocking;
+    use rcgen::{BasicConstraints, CertificateParams, IsCa};
+    use rustls::{ServerConfig, ServerConnection, StreamOwned};
+    use std::net::TcpListener;
+    use std::thread;
+
+    #[test]
+    fn check_tls_network() {
+        let mut ca_params = CertificateParams::new(Vec::new());
+        ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        let ca = rcgen::Certificate::from_params(ca_params).unwrap();
+        let server =
+            rcgen::Certificate::from_params(CertificateParams::new(vec!["localhost".to_string()]))
+                .unwrap();
+        let server_certificate = Certificate(server.serialize_der_with_signer(&ca).unwrap());
+        let server_key = PrivateKey(server.serialize_private_key_der());
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let echo = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let config = ServerConfig::builder()
+                .with_safe_defaults()
+                .with_no_client_auth()
+                .with_single_cert(vec![server_certificate], server_key)
+                .unwrap();
+            let tls = ServerConnection::new(Arc::new(config)).unwrap();
+            let mut stream = StreamOwned::new(tls, stream);
+            let mut data = [0; 5];
+            stream.read_exact(&mut data).unwrap();
+            stream.write_all(&data).unwrap();
+            stream.flush().unwrap();
+        });
+
+        let config = TlsConfig::new("localhost")
+            .root_certificates_pem(ca.serialize_pem().unwrap().as_bytes())
+            .unwrap();
+        let mut network = TlsNetwork::connect(&addr, &config).unwrap();
+        network.write_all(b"hello").unwrap();
+
+        let data = read_nonblocking(&mut network, 5);
+        echo.join().unwrap();
+        assert_eq!(b"hello", &data[..]);
+    }
+
+    #[test]
+    fn check_tls_write_would_block() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        // A peer that never reads, so that the network stops accepting data
+        let peer = thread::spawn(move || listener.accept().unwrap());
+
+        let mut network = TlsNetwork::connect(&addr, &TlsConfig::new("localhost")).unwrap();
+        let _peer = peer.join().unwrap();
+        let data = vec![1; 16 * 1024];
+        let mut written = 0;
+        let result = loop {
+            match network.write(&data) {
+                Ok(n) => written += n,
+                Err(e) => break e,
+            }
+            assert!(written < 64 * 1024 * 1024, "writes are not bounded");
+        };
+        assert_eq!(ErrorKind::WouldBlock, result.kind());
+    }
+
+    #[test]
+    fn check_server_name() {
+        assert_eq!(None, TlsConfig::default().server_name());
+        let config = TlsConfig::default().default_server_name("localhost");
+        assert_eq!(Some("localhost"), config.server_name());
+        let config = TlsConfig::new("broker").default_server_name("localhost");
+        assert_eq!(Some("broker"), config.server_name());
+    }
+}
@@ -5,15 +5,18 @@
 
 //! The transport module contains the network connectivity transport for the upper layers. It is implemented using mio.
 
-use std::fmt::Debug;
-use std::io::Cursor;
+use std::fmt::{Debug, Display};
 use std::io::Read;
 use std::io::Write;
+use std::net::SocketAddr;
+use std::path::PathBuf;
 
 use std::time::Instant;
 
+use crate::decoding::DecodeLimits;
 use crate::error::*;
 use crate::framing::*;
+use crate::streaming::FrameStreamDecoder;
 use std::sync::{Arc, Mutex};
 
 #[derive(Debug, PartialEq, Eq)]
@@ -89,20 +92,12 @@ impl Buffer {
         }
     }
 
-    pub fn is_full(&self) -> bool {
-        self.position == self.capacity()
-    }
-
-    fn capacity(&self) -> usize {
-        self.buffer.capacity()
-    }
-
     fn peek(&mut self) -> &[u8] {
         &self.buffer[0..self.position]
     }
 
     fn fill(&mut self, reader: &mut dyn Read) -> Result<&[u8]> {
-        let capacity = self.buffer.capacity();
+        let capacity = self.capacity();
         if self.position < capacity {
             let len = reader.read(&mut self.buffer[self.position..capacity])?;
 
@@ -125,16 +120,24 @@ impl Buffer {
         // println!("(Consume) Position is now {}", self.position);
     }
 
-    fn written(&mut self) -> &[u8] {
-        &self.buffer[0..self.position]
+    fn capacity(&self) -> usize {
+        self.buffer.len()
+    }
+
+    fn len(&self) -> usize {
+        self.position
     }
 
-    fn clear(&mut self) {
-        self.position = 0;
+    fn available(&self) -> usize {
+        self.buffer.len() - self.position
+    }
+
+    fn grow(&mut self, additional: usize) {
+        self.buffer.resize(self.buffer.len() + additional, 0);
     }
 
     fn write_buf(&mut self, data: &[u8]) -> std::io::Result<usize> {
-        if data.len() > self.buffer.capacity().saturating_sub(self.position) {
+        if data.len() > self.available() {
             return Err(std::io::Error::new(
                 std::io::ErrorKind::InvalidInput,
                 "written data is bigger than output buffer",
@@ -165,9 +168,46 @@ pub trait Network: Read + Write + Debug {
     fn close(&mut self) -> Result<()>;
 }
 
+impl<N: Network + ?Sized> Network for Box<N> {
+    fn set_nodelay(&self, nodelay: bool) -> Result<()> {
+        (**self).set_nodelay(nodelay)
+    }
+
+    fn close(&mut self) -> Result<()> {
+        (**self).close()
+    }
+}
+
+/// The address of the remote peer of a network.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PeerAddress {
+    Tcp(SocketAddr),
+    Unix(PathBuf),
+    /// A peer reached through a custom network, described by name.
+    Other(String),
+}
+
+impl Display for PeerAddress {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            PeerAddress::Tcp(address) => write!(f, "{}", address),
+            PeerAddress::Unix(path) => write!(f, "{}", path.display()),
+            PeerAddress::Other(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+impl From<SocketAddr> for PeerAddress {
+    fn from(address: SocketAddr) -> Self {
+        PeerAddress::Tcp(address)
+    }
+}
+
 pub struct TransportInfo {
     last_sent: Mutex<Instant>,
     last_received: Mutex<Instant>,
+    decode_limits: Mutex<DecodeLimits>,
+    remote_max_frame_size: Mutex<usize>,
 }
 
 impl TransportInfo {
@@ -186,6 +226,16 @@ impl TransportInfo {
     pub fn last_received(&self) -> Instant {
         *self.last_received.lock().unwrap()
     }
+
+    /// The limits applied when decoding received frames and messages.
+    pub fn decode_limits(&self) -> DecodeLimits {
+        *self.decode_limits.lock().unwrap()
+    }
+
+    /// The largest frame accepted by the remote peer.
+    pub fn remote_max_frame_size(&self) -> usize {
+        *self.remote_max_frame_size.lock().unwrap()
+    }
 }
 
 impl Debug for TransportInfo {
@@ -193,6 +243,8 @@ impl Debug for TransportInfo {
         f.debug_struct("TransportInfo")
             .field("last_sent", &self.last_sent())
             .field("last_received", &self.last_received())
+            .field("decode_limits", &self.decode_limits())
+            .field("remote_max_frame_size", &self.remote_max_frame_size())
             .finish_non_exhaustive()
     }
 }
@@ -203,6 +255,8 @@ impl Default for TransportInfo {
         TransportInfo {
             last_sent: Mutex::new(now),
             last_received: Mutex::new(now),
+            decode_limits: Mutex::new(DecodeLimits::default()),
+            remote_max_frame_size: Mutex::new(u32::MAX as usize),
         }
     }
 }
@@ -211,18 +265,24 @@ impl Default for TransportInfo {
 pub struct Transport<N: Network> {
     network: N,
     incoming: Buffer,
+    decoder: FrameStreamDecoder,
     outgoing: Buffer,
-    _max_frame_size: usize,
+    write_limit: usize,
     info: Arc<TransportInfo>,
 }
 
 impl<N: Network> Transport<N> {
-    pub fn new(network: N, max_frame_size: usize) -> Transport<N> {
+    /// Create a transport with read and write buffers of the given size. The buffer size does
+    /// not limit the size of frames, which are read and written in multiple steps if needed.
+    /// Data the network does not accept is buffered up to four times the buffer size, after
+    /// which writes fail with [`std::io::ErrorKind::WouldBlock`] until the data is flushed.
+    pub fn new(network: N, buffer_size: usize) -> Transport<N> {
         Transport {
             network,
-            incoming: Buffer::new(max_frame_size),
-            outgoing: Buffer::new(max_frame_size),
-            _max_frame_size: max_frame_size,
+            incoming: Buffer::new(buffer_size),
+            decoder: FrameStreamDecoder::new(),
+            outgoing: Buffer::new(buffer_size),
+            write_limit: 4 * buffer_size,
             info: Arc::new(TransportInfo::default()),
         }
     }
@@ -231,6 +291,23 @@ impl<N: Network> Transport<N> {
         &self.info
     }
 
+    /// Set the limits applied when decoding received frames and messages.
+    pub fn set_decode_limits(&mut self, limits: DecodeLimits) {
+        *self.info.decode_limits.lock().unwrap() = limits;
+        self.decoder.set_limits(limits);
+    }
+
+    /// Set the largest frame accepted from the remote peer. Larger frames fail with a
+    /// framing error.
+    pub fn set_max_frame_size(&mut self, max_frame_size: usize) {
+        self.decoder.set_max_frame_size(max_frame_size);
+    }
+
+    /// Set the largest frame accepted by the remote peer, as announced in its open performative.
+    pub fn set_remote_max_frame_size(&mut self, max_frame_size: usize) {
+        *self.info.remote_max_frame_size.lock().unwrap() = max_frame_size;
+    }
+
     pub fn network(&self) -> &N {
         &self.network
     }
@@ -244,77 +321,118 @@ impl<N: Network> Transport<N> {
         Ok(())
     }
 
+    /// Pass bytes read from the network on to the decoder.
+    fn feed_decoder(&mut self) {
+        let data = self.incoming.peek();
+        let len = data.len();
+        if len > 0 {
+            self.decoder.feed(data);
+            self.incoming.consume(len);
+        }
+    }
+
     pub fn read_protocol_header(&mut self) -> Result<Option<ProtocolHeader>> {
-        let mut buf = self.incoming.peek();
-        if buf.len() >= 8 {
-            let header = ProtocolHeader::decode(&mut buf)?;
-            self.incoming.consume(8);
-            Ok(Some(header))
-        } else {
-            self.incoming.fill(&mut self.network)?;
-            Ok(None)
+        self.feed_decoder();
+        match self.decoder.next_protocol_header()? {
+            Some(header) => Ok(Some(header)),
+            None => {
+                self.incoming.fill(&mut self.network)?;
+                Ok(None)
+            }
         }
     }
 
     pub fn write_protocol_header(&mut self, header: &ProtocolHeader) -> Result<()> {
-        header.encode(&mut self.outgoing)?;
+        let mut data = Vec::new();
+        header.encode(&mut data)?;
+        self.write(&data[..])?;
         Ok(())
     }
 
+    /// Read the next frame. Frames are decoded incrementally as bytes arrive, so they are not
+    /// limited by the size of the read buffer.
     pub fn read_frame(&mut self) -> Result<Frame> {
         loop {
-            let mut buf = self.incoming.peek();
-            trace!("Filled {} bytes", buf.len());
-            if buf.len() >= 8 {
-                let header = FrameHeader::decode(&mut buf)?;
-                let frame_size = header.size as usize;
-
-                trace!(
-                    "Found enough bytes for header {:?}. Buffer is {} bytes!",
-                    header,
-                    buf.len()
-                );
-
-                if buf.len() >= frame_size - 8 {
-                    let mut cursor = Cursor::new(&mut buf);
-                    let frame = Frame::decode(header, &mut cursor)?;
-                    self.incoming.consume(frame_size);
-                    self.info.update_last_received();
-                    debug!("RX {:?}", frame);
-                    return Ok(frame);
-                } else if self.incoming.capacity() < frame_size || self.incoming.is_full() {
-                    return Err(AmqpError::ReceiveBufferHasInsufficientCapacity {
-                        frame_size,
-                        buffer_capacity: self.incoming.capacity(),
-                    });
-                } else {
-                    self.incoming.fill(&mut self.network)?;
-                }
-            } else {
-                self.incoming.fill(&mut self.network)?;
+            self.feed_decoder();
+            if let Some(frame) = self.decoder.next_frame()? {
+                self.info.update_last_received();
+                debug!("RX {:?}", frame);
+                return Ok(frame);
             }
+            let data = self.incoming.fill(&mut self.network)?;
+            trace!("Filled {} bytes", data.len());
         }
     }
 
     pub fn write_frame(&mut self, frame: &Frame) -> Result<usize> {
-        let sz = frame.encode(&mut self.outgoing)?;
+        let mut data = Vec::new();
+        let sz = frame.encode(&mut data)?;
+        let max_frame_size = self.info.remote_max_frame_size();
+        if sz > max_frame_size {
+            return Err(AmqpError::framing_error(Some(
+                format!(
+                    "Frame size {} exceeds remote maximum frame size {}",
+                    sz, max_frame_size
+                )
+                .as_str(),
+            )));
+        }
+        self.write(&data[..])?;
         self.info.update_last_sent();
-        self.flush()?;
         Ok(sz)
     }
 
+    /// Whether the write buffer accepts more data without exceeding the write limit.
+    pub fn is_writable(&self) -> bool {
+        self.outgoing.len() < self.write_limit
+    }
+
+    /// Write data to the network through the write buffer. Data the network does not accept
+    /// without blocking stays buffered until the next flush. Once the buffered data reaches
+    /// the write limit, the write fails with [`std::io::ErrorKind::WouldBlock`] without
+    /// writing any of the data.
     pub fn write(&mut self, data: &[u8]) -> Result<usize> {
-        self.outgoing.write_all(data)?;
+        if data.len() > self.outgoing.available() || !self.is_writable() {
+            self.flush()?;
+        }
+        if !self.is_writable() {
+            return Err(AmqpError::IoError(std::io::Error::from(
+                std::io::ErrorKind::WouldBlock,
+            )));
+        }
+        if data.len() > self.outgoing.available() {
+            self.outgoing.grow(data.len() - self.outgoing.available());
+        }
+        self.outgoing.write_buf(data)?;
         self.flush()?;
         Ok(data.len())
     }
 
+    /// Write buffered data to the network, returning the number of bytes written. Stops
+    /// when the network would block, keeping the unflushed data for the next flush.
     pub fn flush(&mut self) -> Result<usize> {
-        let data = self.outgoing.written();
-        let len = data.len();
-        self.network.write_all(data)?;
-        self.outgoing.clear();
-        Ok(len)
+        let mut written = 0;
+        while !self.outgoing.peek().is_empty() {
+            match self.network.write(self.outgoing.peek()) {
+                Ok(0) => {
+                    return Err(AmqpError::IoError(std::io::Error::from(
+                        std::io::ErrorKind::WriteZero,
+                    )))
+                }
+                Ok(len) => {
+                    self.outgoing.consume(len);
+                    written += len;
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => return Ok(written),
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        // Networks like TLS may buffer data themselves
+        match self.network.flush() {
+            Err(e) if e.kind() != std::io::ErrorKind::WouldBlock => Err(e.into()),
+            _ => Ok(written),
+        }
     }
 }
 
@@ -329,6 +447,13 @@ pub mod mio {
     use std::io::Write;
     use std::net::ToSocketAddrs;
     use std::net::{Shutdown, SocketAddr};
+    #[cfg(unix)]
+    use std::path::{Path, PathBuf};
+
+    /// A [`Network`] that can be registered with a mio [`Poll`].
+    pub trait MioSource: Network + Source + Send {}
+
+    impl<N: Network + Source + Send> MioSource for N {}
 
     #[derive(Debug)]
     pub struct MioNetwork {
@@ -418,12 +543,295 @@ pub mod mio {
             self.stream.deregister(registry)
         }
     }
+
+    /// A network connected to a Unix domain socket, e.g. of a local broker sidecar.
+    #[cfg(unix)]
+    #[derive(Debug)]
+    pub struct UnixNetwork {
+        stream: mio::net::UnixStream,
+        path: PathBuf,
+    }
+
+    #[cfg(unix)]
+    impl UnixNetwork {
+        pub fn connect<P: AsRef<Path>>(path: P) -> Result<UnixNetwork> {
+            Ok(UnixNetwork {
+                stream: mio::net::UnixStream::connect(path.as_ref())?,
+                path: path.as_ref().to_path_buf(),
+            })
+        }
+
+        pub fn path(&self) -> &Path {
+            &self.path
+        }
+    }
+
+    #[cfg(unix)]
+    impl Network for UnixNetwork {
+        fn set_nodelay(&self, _nodelay: bool) -> Result<()> {
+            // Unix domain sockets do not delay small writes
+            Ok(())
+        }
+
+        fn close(&mut self) -> Result<()> {
+            self.stream.shutdown(Shutdown::Both)?;
+            Ok(())
+        }
+    }
+
+    #[cfg(unix)]
+    impl Write for UnixNetwork {
+        fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+            self.stream.write(data)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            self.stream.flush()
+        }
+    }
+
+    #[cfg(unix)]
+    impl Read for UnixNetwork {
+        fn read(&mut self, b: &mut [u8]) -> std::io::Result<usize> {
+            self.stream.read(b)
+        }
+    }
+
+    #[cfg(unix)]
+    impl Source for UnixNetwork {
+        fn register(
+            &mut self,
+            registry: &Registry,
+            token: Token,
+            interests: Interest,
+        ) -> std::io::Result<()> {
+            self.stream.register(registry, token, interests)
+        }
+
+        fn reregister(
+            &mut self,
+            registry: &Registry,
+            token: Token,
+            interests: Interest,
+        ) -> std::io::Result<()> {
+            self.stream.reregister(registry, token, interests)
+        }
+
+        fn deregister(&mut self, registry: &Registry) -> std::io::Result<()> {
+            self.stream.deregister(registry)
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
 
-    use super::Buffer;
+    use super::*;
+    #[cfg(unix)]
+    use crate::testing::read_nonblocking;
+
+    #[derive(Debug)]
+    struct TestNetwork(std::io::Cursor<Vec<u8>>, Vec<u8>);
+
+    impl Read for TestNetwork {
+        fn read(&mut self, b: &mut [u8]) -> std::io::Result<usize> {
+            self.0.read(b)
+        }
+    }
+
+    impl Write for TestNetwork {
+        fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+            self.1.extend_from_slice(data);
+            Ok(data.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Network for TestNetwork {
+        fn set_nodelay(&self, _: bool) -> Result<()> {
+            Ok(())
+        }
+
+        fn close(&mut self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn read_frame_larger_than_buffer() {
+        let mut input = Vec::new();
+        ProtocolHeader::AMQP(Version(1, 0, 0))
+            .encode(&mut input)
+            .unwrap();
+        Frame::AMQP(AmqpFrame {
+            channel: 0,
+            performative: Some(Performative::Transfer(Transfer::new(1))),
+            payload: Some(vec![1; 64]),
+        })
+        .encode(&mut input)
+        .unwrap();
+
+        let mut transport =
+            Transport::new(TestNetwork(std::io::Cursor::new(input), Vec::new()), 16);
+        let header = loop {
+            if let Some(header) = transport.read_protocol_header().unwrap() {
+                break header;
+            }
+        };
+        assert_eq!(ProtocolHeader::AMQP(Version(1, 0, 0)), header);
+        match transport.read_frame().unwrap() {
+            Frame::AMQP(AmqpFrame {
+                payload: Some(payload),
+                ..
+            }) => assert_eq!(vec![1; 64], payload),
+            frame => panic!("unexpected frame {:?}", frame),
+        }
+    }
+
+    #[test]
+    fn write_frame_larger_than_buffer() {
+        let frame = Frame::AMQP(AmqpFrame {
+            channel: 0,
+            performative: Some(Performative::Transfer(Transfer::new(1))),
+            payload: Some(vec![1; 64]),
+        });
+        let mut expected = Vec::new();
+        frame.encode(&mut expected).unwrap();
+
+        let mut transport = Transport::new(
+            TestNetwork(std::io::Cursor::new(Vec::new()), Vec::new()),
+            16,
+        );
+        assert_eq!(expected.len(), transport.write_frame(&frame).unwrap());
+        assert_eq!(expected, transport.network().1);
+
+        transport.set_remote_max_frame_size(expected.len() - 1);
+        assert!(matches!(
+            transport.write_frame(&frame),
+            Err(AmqpError::AmqpConnectionFramingError(_))
+        ));
+        assert_eq!(expected.len(), transport.network().1.len());
+    }
+
+    /// A network accepting a limited number of bytes before it would block.
+    #[derive(Debug)]
+    struct BlockingNetwork {
+        written: Vec<u8>,
+        limit: usize,
+    }
+
+    impl Read for BlockingNetwork {
+        fn read(&mut self, _: &mut [u8]) -> std::io::Result<usize> {
+            Err(std::io::ErrorKind::WouldBlock.into())
+        }
+    }
+
+    impl Write for BlockingNetwork {
+        fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+            let len = data.len().min(self.limit - self.written.len());
+            if len == 0 {
+                return Err(std::io::ErrorKind::WouldBlock.into());
+            }
+            self.written.extend_from_slice(&data[..len]);
+            Ok(len)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Network for BlockingNetwork {
+        fn set_nodelay(&self, _: bool) -> Result<()> {
+            Ok(())
+        }
+
+        fn close(&mut self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn write_frame_would_block() {
+        let frame = Frame::AMQP(AmqpFrame {
+            channel: 0,
+            performative: Some(Performative::Transfer(Transfer::new(1))),
+            payload: Some(vec![1; 64]),
+        });
+        let mut expected = Vec::new();
+        frame.encode(&mut expected).unwrap();
+        frame.encode(&mut expected).unwrap();
+
+        let network = BlockingNetwork {
+            written: Vec::new(),
+            limit: 10,
+        };
+        let mut transport = Transport::new(network, 32);
+        transport.write_frame(&frame).unwrap();
+        transport.write_frame(&frame).unwrap();
+        assert_eq!(expected[..10], transport.network().written[..]);
+
+        assert_eq!(0, transport.flush().unwrap());
+        transport.network_mut().limit = expected.len();
+        assert_eq!(expected.len() - 10, transport.flush().unwrap());
+        assert_eq!(expected, transport.network().written);
+    }
+
+    #[test]
+    fn write_buffer_bounded() {
+        let frame = Frame::AMQP(AmqpFrame {
+            channel: 0,
+            performative: Some(Performative::Transfer(Transfer::new(1))),
+            payload: Some(vec![1; 64]),
+        });
+        let mut encoded = Vec::new();
+        let frame_size = frame.encode(&mut encoded).unwrap();
+
+        let network = BlockingNetwork {
+            written: Vec::new(),
+            limit: 0,
+        };
+        let mut transport = Transport::new(network, 64);
+        let mut frames = 0;
+        for _ in 0..100 {
+            match transport.write_frame(&frame) {
+                Ok(_) => frames += 1,
+                Err(AmqpError::IoError(e)) if e.kind() == std::io::ErrorKind::WouldBlock => break,
+                Err(e) => panic!("unexpected error {:?}", e),
+            }
+        }
+        assert!(!transport.is_writable());
+        assert_eq!(frames * frame_size, transport.outgoing.len());
+        assert!(transport.outgoing.capacity() < 4 * 64 + frame_size);
+
+        // Flushing makes room for more frames
+        transport.network_mut().limit = frames * frame_size;
+        assert_eq!(frames * frame_size, transport.flush().unwrap());
+        assert!(transport.is_writable());
+        transport.write_frame(&frame).unwrap();
+        assert_eq!(frame_size, transport.outgoing.len());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn unix_network() {
+        let path = std::env::temp_dir().join(format!("dove-{}.sock", uuid::Uuid::new_v4()));
+        let listener = std::os::unix::net::UnixListener::bind(&path).unwrap();
+
+        let mut network = mio::UnixNetwork::connect(&path).unwrap();
+        let (mut peer, _) = listener.accept().unwrap();
+        network.write_all(b"AMQP").unwrap();
+        let mut data = [0; 4];
+        peer.read_exact(&mut data).unwrap();
+        assert_eq!(b"AMQP", &data);
+
+        peer.write_all(b"PQMA").unwrap();
+        assert_eq!(b"PQMA", &read_nonblocking(&mut network, 4)[..]);
+        std::fs::remove_file(&path).unwrap();
+    }
 
     #[test]
     fn readbuffer() {
@@ -6,9 +6,13 @@
 //! The types module contains the AMQP 1.0 types system encoders and decoders. By using these types you can enforce a certain encoding for your data.
 
 use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
 use std::io::Write;
 use std::vec::Vec;
+use uuid::Uuid;
 
+use crate::decimal::*;
 use crate::error::*;
 use crate::symbol::Symbol;
 
@@ -17,11 +21,72 @@ use crate::symbol::Symbol;
  */
 pub trait Encoder {
     fn encode(&self, writer: &mut dyn Write) -> Result<TypeCode>;
+
+    /// Encode using the given options. Types without a specialized implementation are
+    /// encoded with [`Encoder::encode`], and re-encoded if other than the default mode is
+    /// requested.
+    fn encode_with_options(
+        &self,
+        writer: &mut dyn Write,
+        options: &EncodeOptions,
+    ) -> Result<TypeCode> {
+        if options.mode == EncodeMode::Default {
+            return self.encode(writer);
+        }
+        let mut buf = Vec::new();
+        self.encode(&mut buf)?;
+        let mut input = &buf[..];
+        let mut code = TypeCode::Null;
+        while !input.is_empty() {
+            code =
+                crate::decoding::decode_value(&mut input)?.encode_with_options(writer, options)?;
+        }
+        Ok(code)
+    }
+}
+
+/// The encoding mode used by [`Encoder::encode_with_options`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum EncodeMode {
+    /// The encoding produced by [`Encoder::encode`].
+    #[default]
+    Default,
+    /// The smallest encoding, eliding trailing null fields of composite types.
+    Compact,
+    /// The compact encoding with map entries ordered by their encoded keys, so that equal
+    /// values always produce the same bytes. Used for signing, hashing and golden tests.
+    Canonical,
+}
+
+/// Options for [`Encoder::encode_with_options`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct EncodeOptions {
+    pub mode: EncodeMode,
+}
+
+impl EncodeOptions {
+    pub const fn new() -> EncodeOptions {
+        EncodeOptions {
+            mode: EncodeMode::Default,
+        }
+    }
+
+    pub const fn compact() -> EncodeOptions {
+        EncodeOptions {
+            mode: EncodeMode::Compact,
+        }
+    }
+
+    pub const fn canonical() -> EncodeOptions {
+        EncodeOptions {
+            mode: EncodeMode::Canonical,
+        }
+    }
 }
 
 // Various constants used in encoding and decoding.
 pub const U8_MAX: usize = u8::MAX as usize;
-pub const I8_MAX: usize = std::i8::MAX as usize;
+pub const I8_MAX: usize = i8::MAX as usize;
 pub const LIST8_MAX: usize = (u8::MAX as usize) - 1;
 pub const LIST32_MAX: usize = (u32::MAX as usize) - 4;
 
@@ -50,6 +115,12 @@ pub const DESC_DELIVERY_STATE_REJECTED: Value = Value::Ulong(0x25);
 pub const DESC_DELIVERY_STATE_RELEASED: Value = Value::Ulong(0x26);
 pub const DESC_DELIVERY_STATE_MODIFIED: Value = Value::Ulong(0x27);
 
+pub const DESC_COORDINATOR: Value = Value::Ulong(0x30);
+pub const DESC_DECLARE: Value = Value::Ulong(0x31);
+pub const DESC_DISCHARGE: Value = Value::Ulong(0x32);
+pub const DESC_DECLARED: Value = Value::Ulong(0x33);
+pub const DESC_TRANSACTIONAL_STATE: Value = Value::Ulong(0x34);
+
 pub const DESC_DETACH: Value = Value::Ulong(0x16);
 pub const DESC_END: Value = Value::Ulong(0x17);
 pub const DESC_CLOSE: Value = Value::Ulong(0x18);
@@ -70,6 +141,56 @@ pub const DESC_ERROR: Value = Value::Ulong(0x1D);
 #[derive(Copy, Clone, Debug, Ord, PartialOrd, Eq, PartialEq)]
 pub struct Timestamp(pub u64);
 
+/// An IEEE 754-2008 binary32 value. Equality and ordering follow [`f32::total_cmp`], which
+/// allows the value to be used as key in the *Maps of this crate.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Float(pub f32);
+
+/// An IEEE 754-2008 binary64 value. Equality and ordering follow [`f64::total_cmp`], which
+/// allows the value to be used as key in the *Maps of this crate.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Double(pub f64);
+
+impl PartialEq for Float {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Float {}
+
+impl PartialOrd for Float {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Float {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+impl PartialEq for Double {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Double {}
+
+impl PartialOrd for Double {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Double {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
 /// A reference to a type with a given value. This allows efficient zero copy of the provided values
 /// and should be used when possible (depends on lifetime constraints where its used).
 #[derive(Clone, PartialEq, Debug, PartialOrd, Ord, Eq, derive_more::From)]
@@ -94,15 +215,21 @@ pub enum ValueRef<'a> {
     Int(&'a i32),
     #[from]
     Long(&'a i64),
-    // Float(&'a f32),  does not impl Ord nor Eq, but is used as key in *Maps in this crate...
-    // Double(&'a f64), does not impl Ord nor Eq, but is used as key in *Maps in this crate...
-    // decimal32
-    // decimal64
-    // decimal128
+    #[from]
+    Float(&'a Float),
+    #[from]
+    Double(&'a Double),
+    #[from]
+    Decimal32(&'a Decimal32),
+    #[from]
+    Decimal64(&'a Decimal64),
+    #[from]
+    Decimal128(&'a Decimal128),
     #[from]
     Char(&'a char),
     Timestamp(&'a u64),
-    // uuid
+    #[from]
+    Uuid(&'a Uuid),
     #[from]
     Binary(&'a [u8]),
     #[from]
@@ -152,12 +279,91 @@ impl<'a> From<&'a Value> for ValueRef<'a> {
             Value::Short(ref value) => ValueRef::Short(value),
             Value::Int(ref value) => ValueRef::Int(value),
             Value::Long(ref value) => ValueRef::Long(value),
+            Value::Float(ref value) => ValueRef::Float(value),
+            Value::Double(ref value) => ValueRef::Double(value),
+            Value::Decimal32(ref value) => ValueRef::Decimal32(value),
+            Value::Decimal64(ref value) => ValueRef::Decimal64(value),
+            Value::Decimal128(ref value) => ValueRef::Decimal128(value),
             Value::Array(ref value) => ValueRef::Array(value),
             Value::List(ref value) => ValueRef::List(value),
             Value::Map(ref value) => ValueRef::Map(value),
             Value::Binary(ref value) => ValueRef::Binary(value),
             Value::Char(ref value) => ValueRef::Char(value),
             Value::Timestamp(ref value) => ValueRef::Timestamp(value),
+            Value::Uuid(ref value) => ValueRef::Uuid(value),
+        }
+    }
+}
+
+/// A value decoded without copying from a buffer, borrowing binary, string and symbol data
+/// from it. See [`crate::decoding::decode_borrowed_value`].
+#[derive(Clone, PartialEq, Debug, PartialOrd, Ord, Eq)]
+pub enum BorrowedValue<'a> {
+    Described(Box<BorrowedValue<'a>>, Box<BorrowedValue<'a>>),
+    Null,
+    Bool(bool),
+    Ubyte(u8),
+    Ushort(u16),
+    Uint(u32),
+    Ulong(u64),
+    Byte(i8),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Float(Float),
+    Double(Double),
+    Decimal32(Decimal32),
+    Decimal64(Decimal64),
+    Decimal128(Decimal128),
+    Char(char),
+    Timestamp(u64),
+    Uuid(Uuid),
+    Binary(&'a [u8]),
+    String(&'a str),
+    Symbol(&'a [u8]),
+    List(Vec<BorrowedValue<'a>>),
+    Map(Vec<(BorrowedValue<'a>, BorrowedValue<'a>)>),
+    Array(Vec<BorrowedValue<'a>>),
+}
+
+impl From<BorrowedValue<'_>> for Value {
+    fn from(value: BorrowedValue<'_>) -> Self {
+        match value {
+            BorrowedValue::Described(descriptor, value) => Value::Described(
+                Box::new(Value::from(*descriptor)),
+                Box::new(Value::from(*value)),
+            ),
+            BorrowedValue::Null => Value::Null,
+            BorrowedValue::Bool(value) => Value::Bool(value),
+            BorrowedValue::Ubyte(value) => Value::Ubyte(value),
+            BorrowedValue::Ushort(value) => Value::Ushort(value),
+            BorrowedValue::Uint(value) => Value::Uint(value),
+            BorrowedValue::Ulong(value) => Value::Ulong(value),
+            BorrowedValue::Byte(value) => Value::Byte(value),
+            BorrowedValue::Short(value) => Value::Short(value),
+            BorrowedValue::Int(value) => Value::Int(value),
+            BorrowedValue::Long(value) => Value::Long(value),
+            BorrowedValue::Float(value) => Value::Float(value),
+            BorrowedValue::Double(value) => Value::Double(value),
+            BorrowedValue::Decimal32(value) => Value::Decimal32(value),
+            BorrowedValue::Decimal64(value) => Value::Decimal64(value),
+            BorrowedValue::Decimal128(value) => Value::Decimal128(value),
+            BorrowedValue::Char(value) => Value::Char(value),
+            BorrowedValue::Timestamp(value) => Value::Timestamp(value),
+            BorrowedValue::Uuid(value) => Value::Uuid(value),
+            BorrowedValue::Binary(value) => Value::Binary(value.to_vec()),
+            BorrowedValue::String(value) => Value::String(value.to_string()),
+            BorrowedValue::Symbol(value) => Value::Symbol(value.to_vec()),
+            BorrowedValue::List(value) => Value::List(value.into_iter().map(Value::from).collect()),
+            BorrowedValue::Map(value) => Value::Map(
+                value
+                    .into_iter()
+                    .map(|(k, v)| (Value::from(k), Value::from(v)))
+                    .collect(),
+            ),
+            BorrowedValue::Array(value) => {
+                Value::Array(value.into_iter().map(Value::from).collect())
+            }
         }
     }
 }
@@ -186,15 +392,21 @@ pub enum Value {
     Int(i32),
     #[from]
     Long(i64),
-    // Float(f32),  does not impl Ord nor Eq, but is used as key in *Maps in this crate...
-    // Double(f64), does not impl Ord nor Eq, but is used as key in *Maps in this crate...
-    // decimal32
-    // decimal64
-    // decimal128
+    #[from]
+    Float(Float),
+    #[from]
+    Double(Double),
+    #[from]
+    Decimal32(Decimal32),
+    #[from]
+    Decimal64(Decimal64),
+    #[from]
+    Decimal128(Decimal128),
     #[from]
     Char(char),
     Timestamp(u64),
-    // uuid
+    #[from]
+    Uuid(Uuid),
     #[from]
     Binary(Vec<u8>),
     #[from]
@@ -214,10 +426,41 @@ impl Value {
     /**
      * Convert to a reference type. Not currently implemented for all types.
      */
-    pub fn value_ref(&self) -> ValueRef {
+    pub fn value_ref(&self) -> ValueRef<'_> {
         ValueRef::from(self)
     }
 
+    /// The name of the AMQP type of this value, as used in the specification.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Self::Described(_, _) => "described",
+            Self::Null => "null",
+            Self::Bool(_) => "boolean",
+            Self::Ubyte(_) => "ubyte",
+            Self::Ushort(_) => "ushort",
+            Self::Uint(_) => "uint",
+            Self::Ulong(_) => "ulong",
+            Self::Byte(_) => "byte",
+            Self::Short(_) => "short",
+            Self::Int(_) => "int",
+            Self::Long(_) => "long",
+            Self::Float(_) => "float",
+            Self::Double(_) => "double",
+            Self::Decimal32(_) => "decimal32",
+            Self::Decimal64(_) => "decimal64",
+            Self::Decimal128(_) => "decimal128",
+            Self::Char(_) => "char",
+            Self::Timestamp(_) => "timestamp",
+            Self::Uuid(_) => "uuid",
+            Self::Binary(_) => "binary",
+            Self::Str(_) | Self::String(_) => "string",
+            Self::Symbol(_) | Self::SymbolSlice(_) => "symbol",
+            Self::List(_) => "list",
+            Self::Map(_) => "map",
+            Self::Array(_) => "array",
+        }
+    }
+
     pub fn as_any_integer(&self) -> Option<i64> {
         match self {
             Self::Ubyte(v) => Some(*v as _),
@@ -248,12 +491,72 @@ impl From<Timestamp> for Value {
     }
 }
 
+impl From<f32> for Value {
+    fn from(f: f32) -> Self {
+        Self::Float(Float(f))
+    }
+}
+
+impl From<f64> for Value {
+    fn from(d: f64) -> Self {
+        Self::Double(Double(d))
+    }
+}
+
 impl<V: Into<Value>, const N: usize> From<[V; N]> for Value {
     fn from(array: [V; N]) -> Self {
         Self::Array(array.map(Into::into).to_vec())
     }
 }
 
+impl<V: Into<Value>> From<Option<V>> for Value {
+    fn from(value: Option<V>) -> Self {
+        value.map(Into::into).unwrap_or(Self::Null)
+    }
+}
+
+impl<K: Into<Value>, V: Into<Value>> From<BTreeMap<K, V>> for Value {
+    fn from(map: BTreeMap<K, V>) -> Self {
+        Self::Map(map.into_iter().map(|(k, v)| (k.into(), v.into())).collect())
+    }
+}
+
+impl<K: Into<Value>, V: Into<Value>> From<HashMap<K, V>> for Value {
+    fn from(map: HashMap<K, V>) -> Self {
+        Self::Map(map.into_iter().map(|(k, v)| (k.into(), v.into())).collect())
+    }
+}
+
+impl From<ErrorCondition> for Value {
+    fn from(error: ErrorCondition) -> Self {
+        Self::Described(
+            Box::new(DESC_ERROR),
+            Box::new(Self::List(vec![
+                Self::Symbol(error.condition.into_bytes()),
+                Self::String(error.description),
+            ])),
+        )
+    }
+}
+
+/// Tuples convert into lists.
+macro_rules! value_from_tuple {
+    ($($t:ident $v:ident),+) => {
+        impl<$($t: Into<Value>),+> From<($($t,)+)> for Value {
+            fn from(($($v,)+): ($($t,)+)) -> Self {
+                Self::List(vec![$($v.into()),+])
+            }
+        }
+    };
+}
+
+value_from_tuple!(A a);
+value_from_tuple!(A a, B b);
+value_from_tuple!(A a, B b, C c);
+value_from_tuple!(A a, B b, C c, D d);
+value_from_tuple!(A a, B b, C c, D d, E e);
+value_from_tuple!(A a, B b, C c, D d, E e, F f);
+
 /// All basic type codes in AMQP.
 /// http://docs.oasis-open.org/amqp/core/v1.0/csprd01/amqp-core-types-v1.0-csprd01.html#doc-idp280416
 #[repr(u8)]
@@ -278,16 +581,19 @@ pub enum TypeCode {
     Intsmall = 0x54,
     Long = 0x81,
     Longsmall = 0x55,
-    // /// IEEE 754-2008 binary32
-    // Float = 0x72,
-    // /// IEEE 754-2008 binary64
-    // Double = 0x82,
-    // decimal32
-    // decimal32
-    // decimal12
+    /// IEEE 754-2008 binary32
+    Float = 0x72,
+    /// IEEE 754-2008 binary64
+    Double = 0x82,
+    /// IEEE 754-2008 decimal32 using the Binary Integer Decimal encoding
+    Decimal32 = 0x74,
+    /// IEEE 754-2008 decimal64 using the Binary Integer Decimal encoding
+    Decimal64 = 0x84,
+    /// IEEE 754-2008 decimal128 using the Binary Integer Decimal encoding
+    Decimal128 = 0x94,
     Char = 0x73,
     Timestamp = 0x83,
-    // uuid
+    Uuid = 0x98,
     Bin8 = 0xA0,
     Bin32 = 0xB0,
     Str8 = 0xA1,
@@ -307,7 +613,8 @@ pub enum TypeCode {
 mod tests {
 
     use super::*;
-    use crate::decoding::decode_value;
+    use crate::decoding::{decode_borrowed_value, decode_value, split_value};
+    use crate::symbol::Symbol;
 
     fn assert_type(value: &Value, expected_len: usize, expected_type: TypeCode) {
         let mut output: Vec<u8> = Vec::new();
@@ -317,6 +624,206 @@ mod tests {
 
         let decoded = decode_value(&mut &output[..]).unwrap();
         assert_eq!(&decoded, value);
+
+        let mut input = &output[..];
+        let borrowed = decode_borrowed_value(&mut input).unwrap();
+        assert!(input.is_empty());
+        assert_eq!(&Value::from(borrowed), value);
+
+        let mut input = &output[..];
+        assert_eq!(output.len(), split_value(&mut input).unwrap().len());
+    }
+
+    #[test]
+    fn check_encode_options() {
+        let encode = |value: &Value, options: &EncodeOptions| {
+            let mut output = Vec::new();
+            value.encode_with_options(&mut output, options).unwrap();
+            output
+        };
+
+        // Small encodings are only used for values in range
+        assert_eq!(
+            vec![0x54, 0x80],
+            encode(&Value::Int(-128), &EncodeOptions::new())
+        );
+        assert_eq!(
+            vec![0x71, 0xFF, 0xFF, 0xFC, 0x18],
+            encode(&Value::Int(-1000), &EncodeOptions::new())
+        );
+
+        // Trailing null fields of composites are elided
+        let composite = Value::Described(
+            Box::new(Value::Ulong(0x70)),
+            Box::new(Value::List(vec![
+                Value::Bool(true),
+                Value::Null,
+                Value::Null,
+            ])),
+        );
+        assert_eq!(
+            vec![0x00, 0x53, 0x70, 0xC0, 0x04, 0x03, 0x41, 0x40, 0x40],
+            encode(&composite, &EncodeOptions::new())
+        );
+        assert_eq!(
+            vec![0x00, 0x53, 0x70, 0xC0, 0x02, 0x01, 0x41],
+            encode(&composite, &EncodeOptions::compact())
+        );
+        let empty = Value::Described(
+            Box::new(Value::Ulong(0x70)),
+            Box::new(Value::List(vec![Value::Null])),
+        );
+        assert_eq!(
+            vec![0x00, 0x53, 0x70, 0x45],
+            encode(&empty, &EncodeOptions::compact())
+        );
+
+        // Canonical maps are ordered by their encoded keys
+        let m1 = Value::Map(vec![
+            (Value::from("b"), Value::Int(2)),
+            (
+                Value::from("a"),
+                Value::Map(vec![
+                    (Value::Uint(2), Value::Null),
+                    (Value::Uint(1), Value::Null),
+                ]),
+            ),
+        ]);
+        let m2 = Value::Map(vec![
+            (
+                Value::from("a"),
+                Value::Map(vec![
+                    (Value::Uint(1), Value::Null),
+                    (Value::Uint(2), Value::Null),
+                ]),
+            ),
+            (Value::from("b"), Value::Int(2)),
+        ]);
+        assert_ne!(
+            encode(&m1, &EncodeOptions::new()),
+            encode(&m2, &EncodeOptions::new())
+        );
+        assert_eq!(
+            encode(&m1, &EncodeOptions::canonical()),
+            encode(&m2, &EncodeOptions::canonical())
+        );
+        assert_eq!(
+            encode(&m2, &EncodeOptions::new()),
+            encode(&m1, &EncodeOptions::canonical())
+        );
+
+        // Types without a specialized implementation are re-encoded
+        let mut encoder = crate::frame_codec::FrameEncoder::new(Value::Ulong(0x70));
+        encoder.encode_arg(&true).unwrap();
+        encoder.encode_arg(&None::<u32>).unwrap();
+        let mut output = Vec::new();
+        encoder
+            .encode_with_options(&mut output, &EncodeOptions::compact())
+            .unwrap();
+        assert_eq!(vec![0x00, 0x53, 0x70, 0xC0, 0x02, 0x01, 0x41], output);
+    }
+
+    #[test]
+    fn check_arrays() {
+        // Elements share one full width constructor regardless of their value
+        assert_type(
+            &Value::Array(vec![Value::Uint(0), Value::Uint(7), Value::Uint(300)]),
+            16,
+            TypeCode::Array8,
+        );
+        assert_type(
+            &Value::Array(vec![Value::Bool(true), Value::Bool(false)]),
+            6,
+            TypeCode::Array8,
+        );
+        assert_type(
+            &Value::Array(vec![
+                Value::Symbol(b"ANONYMOUS".to_vec()),
+                Value::Symbol(vec![b'a'; 300]),
+            ]),
+            327,
+            TypeCode::Array32,
+        );
+        assert_type(
+            &Value::Array(vec![
+                Value::Array(vec![Value::Long(1)]),
+                Value::Array(vec![]),
+            ]),
+            30,
+            TypeCode::Array8,
+        );
+        assert_type(
+            &Value::Array(vec![
+                Value::Described(Box::new(Value::Ulong(0x24)), Box::new(Value::List(vec![]))),
+                Value::Described(
+                    Box::new(Value::Ulong(0x24)),
+                    Box::new(Value::List(vec![Value::Bool(true)])),
+                ),
+            ]),
+            24,
+            TypeCode::Array8,
+        );
+
+        let mut output: Vec<u8> = Vec::new();
+        let capabilities = vec![
+            Symbol::from_string(String::from("a")),
+            Symbol::from_string(String::from("bc")),
+        ];
+        capabilities.encode(&mut output).unwrap();
+        assert_eq!(
+            vec![0xE0, 0x07, 0x02, 0xA3, 0x01, b'a', 0x02, b'b', b'c'],
+            output
+        );
+
+        let mixed = Value::Array(vec![Value::Uint(1), Value::String(String::from("a"))]);
+        assert!(mixed.encode(&mut Vec::new()).is_err());
+        let mixed = Value::Array(vec![
+            Value::Described(Box::new(Value::Ulong(1)), Box::new(Value::Null)),
+            Value::Described(Box::new(Value::Ulong(2)), Box::new(Value::Null)),
+        ]);
+        assert!(mixed.encode(&mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn check_borrowed_decoding() {
+        let value = Value::Described(
+            Box::new(Value::Ulong(0x77)),
+            Box::new(Value::List(vec![
+                Value::String(String::from("Hello, world")),
+                Value::Binary(vec![1, 2, 3]),
+                Value::Uint(42),
+            ])),
+        );
+        let mut output: Vec<u8> = Vec::new();
+        value.encode(&mut output).unwrap();
+        output.push(TypeCode::Null as u8);
+
+        let mut input = &output[..];
+        let decoded = decode_borrowed_value(&mut input).unwrap();
+        assert_eq!(&[TypeCode::Null as u8], input);
+        let range = output.as_ptr_range();
+        match decoded {
+            BorrowedValue::Described(descriptor, list) => {
+                assert_eq!(BorrowedValue::Ulong(0x77), *descriptor);
+                match *list {
+                    BorrowedValue::List(ref items) => match items[..] {
+                        [BorrowedValue::String(s), BorrowedValue::Binary(b), BorrowedValue::Uint(42)] =>
+                        {
+                            assert_eq!("Hello, world", s);
+                            assert!(range.contains(&s.as_ptr()));
+                            assert_eq!(&[1, 2, 3], b);
+                            assert!(range.contains(&b.as_ptr()));
+                        }
+                        _ => panic!("unexpected list {:?}", items),
+                    },
+                    _ => panic!("unexpected value {:?}", list),
+                }
+            }
+            _ => panic!("unexpected value {:?}", decoded),
+        }
+
+        let mut truncated = &output[..output.len() - 3];
+        assert!(decode_borrowed_value(&mut truncated).is_err());
     }
 
     #[test]
@@ -329,6 +836,29 @@ mod tests {
             TypeCode::Str8,
         );
         assert_type(&Value::String(String::from("aaaaaaaaaaaaaaaaaaaabbbbbbbbbbbbbbbbbbbbbbbbbbcccccccccccccccccccccccdddddddddddddddddddddddddeeeeeeeeeeeeeeeeeeeeeeeeeffffffffffffffffffffgggggggggggggggggggggggghhhhhhhhhhhhhhhhhhhhhhhiiiiiiiiiiiiiiiiiiiiiiiijjjjjjjjjjjjjjjjjjjkkkkkkkkkkkkkkkkkkkkkkllllllllllllllllllllmmmmmmmmmmmmmmmmmmmmnnnnnnnnnnnnnnnnnnnnooooooooooooooooooooppppppppppppppppppqqqqqqqqqqqqqqqq")), 370, TypeCode::Str32);
+        assert_type(&Value::Float(Float(1.5)), 5, TypeCode::Float);
+        assert_type(&Value::Double(Double(-273.15)), 9, TypeCode::Double);
+        assert_type(&Value::Double(Double(f64::NAN)), 9, TypeCode::Double);
+        assert_type(
+            &Value::Decimal32(Decimal32(0x3280_0001)),
+            5,
+            TypeCode::Decimal32,
+        );
+        assert_type(
+            &Value::Decimal64(Decimal64(0x31c0_0000_0000_0001)),
+            9,
+            TypeCode::Decimal64,
+        );
+        assert_type(
+            &Value::Decimal128(Decimal128(0x3040_0000_0000_0000_0000_0000_0000_0001)),
+            17,
+            TypeCode::Decimal128,
+        );
+        assert_type(
+            &Value::Uuid(Uuid::from_u128(0x936da01f_9abd_4d9d_80c7_02af85c822a8)),
+            17,
+            TypeCode::Uuid,
+        );
         assert_type(
             &Value::List(vec![
                 Value::Ulong(1),
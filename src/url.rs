@@ -15,20 +15,47 @@ pub struct Url<'a> {
     pub hostname: &'a str,
     pub port: u16,
     pub address: &'a str,
+    /// The path of the socket for `amqp+unix://` URLs
+    pub socket_path: Option<&'a str>,
 }
 
-#[derive(PartialEq, Eq, Debug)]
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
 pub enum UrlScheme {
     AMQP,
     AMQPS,
+    WS,
+    WSS,
+    UNIX,
 }
 
 impl UrlScheme {
+    /// The port used when the URL does not specify one. Unix domain sockets have no port.
+    pub fn default_port(&self) -> u16 {
+        match self {
+            UrlScheme::AMQP => 5672,
+            UrlScheme::AMQPS => 5671,
+            UrlScheme::WS => 80,
+            UrlScheme::WSS => 443,
+            UrlScheme::UNIX => 0,
+        }
+    }
+
+    /// Whether the connection uses TLS.
+    pub fn tls(&self) -> bool {
+        matches!(self, UrlScheme::AMQPS | UrlScheme::WSS)
+    }
+
     fn parse(input: &str) -> (&str, Result<UrlScheme>) {
         if let Some(s) = input.strip_prefix("amqp://") {
             (s, Ok(UrlScheme::AMQP))
         } else if let Some(s) = input.strip_prefix("amqps://") {
             (s, Ok(UrlScheme::AMQPS))
+        } else if let Some(s) = input.strip_prefix("ws://") {
+            (s, Ok(UrlScheme::WS))
+        } else if let Some(s) = input.strip_prefix("wss://") {
+            (s, Ok(UrlScheme::WSS))
+        } else if let Some(s) = input.strip_prefix("amqp+unix://") {
+            (s, Ok(UrlScheme::UNIX))
         } else {
             (input, Err(AmqpError::generic("unable to detect scheme")))
         }
@@ -36,10 +63,48 @@ impl UrlScheme {
 }
 
 impl Url<'_> {
-    pub fn parse(input: &str) -> Result<Url> {
+    /// The WebSocket endpoint for `ws://` and `wss://` URLs. The path of the URL is the
+    /// address, so the endpoint is the root resource of the host.
+    pub fn websocket_url(&self) -> Option<String> {
+        self.websocket_url_with_path("/")
+    }
+
+    /// The WebSocket endpoint for `ws://` and `wss://` URLs at the given path of the host,
+    /// e.g. `/$servicebus/websocket`.
+    pub fn websocket_url_with_path(&self, path: &str) -> Option<String> {
+        let scheme = match self.scheme {
+            UrlScheme::WS => "ws",
+            UrlScheme::WSS => "wss",
+            _ => return None,
+        };
+        let separator = if path.starts_with('/') { "" } else { "/" };
+        Some(format!(
+            "{}://{}:{}{}{}",
+            scheme, self.hostname, self.port, separator, path
+        ))
+    }
+
+    pub fn parse(input: &str) -> Result<Url<'_>> {
         let (input, scheme) = UrlScheme::parse(input);
         let scheme = scheme?;
 
+        if scheme == UrlScheme::UNIX {
+            if input.is_empty() {
+                return Err(AmqpError::generic(
+                    "amqp+unix:// URL is missing a socket path",
+                ));
+            }
+            return Ok(Url {
+                scheme,
+                username: None,
+                password: None,
+                hostname: "",
+                port: scheme.default_port(),
+                address: "",
+                socket_path: Some(input),
+            });
+        }
+
         let (input, username, password) = if let Some(creds_end) = input.find('@') {
             let s = &input[0..creds_end];
             if let Some(user_end) = s.find(':') {
@@ -62,9 +127,9 @@ impl Url<'_> {
             };
             (input, hostname, port)
         } else if let Some(pos) = input.find('/') {
-            (&input[pos + 1..], &input[0..pos], 5672_u16)
+            (&input[pos + 1..], &input[0..pos], scheme.default_port())
         } else {
-            ("", input, 5672_u16)
+            ("", input, scheme.default_port())
         };
 
         let address = if let Some(pos) = input.find('/') {
@@ -80,6 +145,7 @@ impl Url<'_> {
             hostname,
             port,
             address,
+            socket_path: None,
         })
     }
 }
@@ -118,6 +184,43 @@ mod tests {
         assert_eq!("myqueue", url.address);
     }
 
+    #[test]
+    fn test_amqps() {
+        let url = Url::parse(r"amqps://localhost/myqueue").expect("error parsing");
+        assert_eq!(UrlScheme::AMQPS, url.scheme);
+        assert_eq!(5671, url.port);
+    }
+
+    #[test]
+    fn test_websocket() {
+        let url = Url::parse(r"wss://localhost/myqueue").expect("error parsing");
+        assert_eq!(UrlScheme::WSS, url.scheme);
+        assert_eq!(443, url.port);
+        assert_eq!("myqueue", url.address);
+        assert_eq!(
+            Some("wss://localhost:443/".to_string()),
+            url.websocket_url()
+        );
+        assert_eq!(
+            Some("wss://localhost:443/$servicebus/websocket".to_string()),
+            url.websocket_url_with_path("/$servicebus/websocket")
+        );
+        assert_eq!(
+            Some("wss://localhost:443/amqp".to_string()),
+            url.websocket_url_with_path("amqp")
+        );
+        let url = Url::parse(r"amqps://localhost/myqueue").expect("error parsing");
+        assert_eq!(None, url.websocket_url_with_path("/amqp"));
+    }
+
+    #[test]
+    fn test_unix() {
+        let url = Url::parse(r"amqp+unix:///var/run/broker.sock").expect("error parsing");
+        assert_eq!(UrlScheme::UNIX, url.scheme);
+        assert_eq!(Some("/var/run/broker.sock"), url.socket_path);
+        assert!(Url::parse(r"amqp+unix://").is_err());
+    }
+
     #[test]
     fn test_nodest() {
         let url = Url::parse(r"amqp://localhost").expect("error parsing");
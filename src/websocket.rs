@@ -0,0 +1,271 @@
+/*
+ * Copyright 2020, Ulf Lilleengen
+ * License: Apache License 2.0 (see the file LICENSE or http://apache.org/licenses/LICENSE-2.0.html).
+ */
+
+//! The websocket module implements AMQP over WebSocket, using the `amqp` subprotocol and
+//! binary messages.
+
+use mio::event::Source;
+use mio::{Interest, Registry, Token};
+use std::io::{ErrorKind, Read, Write};
+use tungstenite::client::IntoClientRequest;
+use tungstenite::handshake::client::ClientHandshake;
+use tungstenite::handshake::{HandshakeError, MidHandshake};
+use tungstenite::http::HeaderValue;
+use tungstenite::{Message, WebSocket};
+
+use crate::error::*;
+use crate::transport::Network;
+
+#[derive(Debug)]
+enum State<N: Network> {
+    Handshake(MidHandshake<ClientHandshake<N>>),
+    Open(WebSocket<N>),
+    Failed,
+}
+
+impl<N: Network> State<N> {
+    fn from_handshake<R>(
+        result: std::result::Result<(WebSocket<N>, R), HandshakeError<ClientHandshake<N>>>,
+    ) -> Result<State<N>> {
+        match result {
+            Ok((socket, _)) => Ok(State::Open(socket)),
+            Err(HandshakeError::Interrupted(handshake)) => Ok(State::Handshake(handshake)),
+            Err(HandshakeError::Failure(e)) => Err(ws_error(e)),
+        }
+    }
+}
+
+/// A WebSocket connection on top of another network, e.g. a TLS network for `wss://`. The
+/// upgrade handshake is performed as data is read and written. Data written before the
+/// handshake completes is buffered.
+#[derive(Debug)]
+pub struct WsNetwork<N: Network> {
+    state: State<N>,
+    // Payload of received messages not read yet
+    incoming: Vec<u8>,
+    // Data written during the handshake
+    outgoing: Vec<u8>,
+}
+
+impl<N: Network> WsNetwork<N> {
+    /// Start the upgrade handshake for the given `ws://` or `wss://` URL on a connected network.
+    pub fn new(network: N, url: &str) -> Result<WsNetwork<N>> {
+        let mut request = url.into_client_request().map_err(ws_error)?;
+        request
+            .headers_mut()
+            .insert("Sec-WebSocket-Protocol", HeaderValue::from_static("amqp"));
+
+        Ok(WsNetwork {
+            state: State::from_handshake(tungstenite::client(request, network))?,
+            incoming: Vec::new(),
+            outgoing: Vec::new(),
+        })
+    }
+
+    fn network(&self) -> Option<&N> {
+        match &self.state {
+            State::Handshake(handshake) => Some(handshake.get_ref().get_ref()),
+            State::Open(socket) => Some(socket.get_ref()),
+            State::Failed => None,
+        }
+    }
+
+    fn network_mut(&mut self) -> Option<&mut N> {
+        match &mut self.state {
+            State::Handshake(handshake) => Some(handshake.get_mut().get_mut()),
+            State::Open(socket) => Some(socket.get_mut()),
+            State::Failed => None,
+        }
+    }
+
+    /// Continue the handshake, returning the socket once it is complete.
+    fn socket(&mut self) -> std::io::Result<&mut WebSocket<N>> {
+        if let State::Handshake(_) = self.state {
+            if let State::Handshake(handshake) = std::mem::replace(&mut self.state, State::Failed) {
+                self.state = State::from_handshake(handshake.handshake())
+                    .map_err(|e| std::io::Error::new(ErrorKind::ConnectionRefused, e))?;
+            }
+        }
+
+        match &mut self.state {
+            State::Open(socket) => {
+                if !self.outgoing.is_empty() {
+                    let data = std::mem::take(&mut self.outgoing);
+                    send(socket, data)?;
+                }
+                Ok(socket)
+            }
+            State::Handshake(_) => Err(ErrorKind::WouldBlock.into()),
+            State::Failed => Err(ErrorKind::NotConnected.into()),
+        }
+    }
+}
+
+/// Send a binary message. Messages that cannot be written without blocking are queued and
+/// written on the next read or write.
+fn send<N: Network>(socket: &mut WebSocket<N>, data: Vec<u8>) -> std::io::Result<()> {
+    match socket.send(Message::Binary(data)) {
+        Ok(()) => Ok(()),
+        Err(tungstenite::Error::Io(e)) if e.kind() == ErrorKind::WouldBlock => Ok(()),
+        Err(e) => Err(into_io_error(e)),
+    }
+}
+
+fn ws_error(error: tungstenite::Error) -> AmqpError {
+    AmqpError::Generic(error.to_string())
+}
+
+fn into_io_error(error: tungstenite::Error) -> std::io::Error {
+    match error {
+        tungstenite::Error::Io(e) => e,
+        e => std::io::Error::other(e),
+    }
+}
+
+impl<N: Network> Network for WsNetwork<N> {
+    fn set_nodelay(&self, nodelay: bool) -> Result<()> {
+        match self.network() {
+            Some(network) => network.set_nodelay(nodelay),
+            None => Ok(()),
+        }
+    }
+
+    fn close(&mut self) -> Result<()> {
+        if let State::Open(socket) = &mut self.state {
+            let _ = socket.close(None);
+            let _ = socket.flush();
+        }
+        match self.network_mut() {
+            Some(network) => network.close(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<N: Network> Read for WsNetwork<N> {
+    fn read(&mut self, b: &mut [u8]) -> std::io::Result<usize> {
+        while self.incoming.is_empty() {
+            let socket = self.socket()?;
+            match socket.read() {
+                Ok(Message::Binary(data)) => self.incoming = data,
+                Ok(Message::Close(_)) => return Ok(0),
+                Ok(Message::Text(_)) => {
+                    return Err(std::io::Error::new(
+                        ErrorKind::InvalidData,
+                        "Unexpected text message",
+                    ))
+                }
+                // Pings are answered by the socket
+                Ok(_) => {}
+                Err(tungstenite::Error::ConnectionClosed | tungstenite::Error::AlreadyClosed) => {
+                    return Ok(0)
+                }
+                Err(e) => return Err(into_io_error(e)),
+            }
+        }
+
+        let len = b.len().min(self.incoming.len());
+        b[..len].copy_from_slice(&self.incoming[..len]);
+        self.incoming.drain(..len);
+        Ok(len)
+    }
+}
+
+impl<N: Network> Write for WsNetwork<N> {
+    fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+        match self.socket() {
+            Ok(socket) => send(socket, data.to_vec())?,
+            Err(e) if e.kind() == ErrorKind::WouldBlock => self.outgoing.extend_from_slice(data),
+            Err(e) => return Err(e),
+        }
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self.socket() {
+            Ok(socket) => match socket.flush() {
+                Err(tungstenite::Error::Io(e)) if e.kind() == ErrorKind::WouldBlock => Ok(()),
+                result => result.map_err(into_io_error),
+            },
+            Err(e) if e.kind() == ErrorKind::WouldBlock => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+impl<N: Network + Source> Source for WsNetwork<N> {
+    fn register(
+        &mut self,
+        registry: &Registry,
+        token: Token,
+        interests: Interest,
+    ) -> std::io::Result<()> {
+        match self.network_mut() {
+            Some(network) => network.register(registry, token, interests),
+            None => Err(ErrorKind::NotConnected.into()),
+        }
+    }
+
+    fn reregister(
+        &mut self,
+        registry: &Registry,
+        token: Token,
+        interests: Interest,
+    ) -> std::io::Result<()> {
+        match self.network_mut() {
+            Some(network) => network.reregister(registry, token, interests),
+            None => Err(ErrorKind::NotConnected.into()),
+        }
+    }
+
+    fn deregister(&mut self, registry: &Registry) -> std::io::Result<()> {
+        match self.network_mut() {
+            Some(network) => network.deregister(registry),
+            None => Err(ErrorKind::NotConnected.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::testing::read_nonblocking;
+    use crate::transport::mio::MioNetwork;
+    use std::net::TcpListener;
+    use std::thread;
+    use tungstenite::handshake::server::{Request, Response};
+
+    #[test]
+    #[allow(clippy::result_large_err)] // the error type is given by the handshake callback
+    fn check_websocket_network() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let echo = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut socket =
+                tungstenite::accept_hdr(stream, |request: &Request, mut response: Response| {
+                    assert_eq!("/$servicebus/websocket", request.uri().path());
+                    response
+                        .headers_mut()
+                        .insert("Sec-WebSocket-Protocol", HeaderValue::from_static("amqp"));
+                    Ok(response)
+                })
+                .unwrap();
+            let message = socket.read().unwrap();
+            assert!(message.is_binary());
+            socket.send(message).unwrap();
+        });
+
+        let network = MioNetwork::connect(&addr).unwrap();
+        let url = format!("ws://{}/$servicebus/websocket", addr);
+        let mut network = WsNetwork::new(network, &url).unwrap();
+        network.write_all(b"hello").unwrap();
+
+        let data = read_nonblocking(&mut network, 5);
+        echo.join().unwrap();
+        assert_eq!(b"hello", &data[..]);
+    }
+}
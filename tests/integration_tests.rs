@@ -229,7 +229,7 @@ async fn single_client(port: u16, opts: ConnectionOptions) {
         if let MessageBody::AmqpValue(Value::String(ref s)) = delivery.message().body {
             assert!(s.starts_with("Hello, World"));
         } else {
-            assert!(false);
+            panic!("unexpected message body");
         }
         // Manual disposition. If not sent, disposition settled + Accepted will be sent on delivery teardown
         delivery
@@ -337,7 +337,7 @@ async fn multiple_clients(port: u16, opts: ConnectionOptions) {
             if let MessageBody::AmqpValue(Value::String(ref s)) = delivery.message().body {
                 assert!(s.starts_with("Hello, World"));
             } else {
-                assert!(false);
+                panic!("unexpected message body");
             }
             delivery
                 .disposition(true, DeliveryState::Accepted)